@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use cgmath::Vector3;
-use shaderunner::{game_program::GameProgram, renderer::Renderer, scene::{camera::Camera, light::{AmbientLight, DirectionalLight}, Transform}, Game, Mesh};
+use shaderunner::{game_program::GameProgram, renderer::Renderer, scene::{camera::Camera, light::{AmbientLight, DirectionalLight}, Transform}, ColorNormalVertex, Game, Mesh, RendererConfig};
 use winit::{event::WindowEvent, window::Window};
 
 
@@ -16,19 +16,21 @@ impl ExampleGame {
 
 impl Game for ExampleGame {
     async fn new(window: Window) -> ExampleGame {
-        let mut renderer = Renderer::new(window).await;
+        let mut renderer = Renderer::with_config(window, RendererConfig::new().with_vsync(true)).await;
     
+        let material = renderer.default_material();
+
         let cube_mesh = shaderunner::test_assets::cube_mesh();
         let cube_mesh = renderer.add_mesh(&cube_mesh);
-        let _instance0 = renderer.add_instance(cube_mesh, Transform::from_translation(Vector3::new(-0.5, -0.5, -0.5)));
+        let _instance0 = renderer.add_instance(cube_mesh, material, Transform::from_translation(Vector3::new(-0.5, -0.5, -0.5))).unwrap();
     
         let sphere_mesh = shaderunner::test_assets::simple_sphere_mesh(1.0, 16, Vector3::new(1.0, 1.0, 1.0));
         let sphere_mesh = renderer.add_mesh(&sphere_mesh);
-        let _instance1 = renderer.add_instance(sphere_mesh, Transform::from_translation(Vector3::new(0.5, 0.5, 0.5)));
+        let _instance1 = renderer.add_instance(sphere_mesh, material, Transform::from_translation(Vector3::new(0.5, 0.5, 0.5))).unwrap();
     
-        let empty_mesh = Mesh::new();
+        let empty_mesh: Mesh<ColorNormalVertex> = Mesh::new();
         let empty_mesh = renderer.add_mesh(&empty_mesh);
-        let _empty_instance = renderer.add_instance(empty_mesh, Transform::identity());
+        let _empty_instance = renderer.add_instance(empty_mesh, material, Transform::identity()).unwrap();
 
         // let quad_mesh = echoes_graphics::test_assets::gradient_quad_mesh();
         // let quad_mesh = renderer.add_mesh(&quad_mesh);
@@ -93,7 +95,9 @@ impl Game for ExampleGame {
         self.frames += 1;
 
         // render
-        self.renderer.render();
+        if let Err(error) = self.renderer.render() {
+            eprintln!("render error: {}", error);
+        }
     }
 }
 
@@ -6,7 +6,7 @@ use crate::mesh::Vertex;
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ColorNormalVertex {
     position: [f32; 3],
-    color: [f32; 3],
+    color: [f32; 4],
     normal: [f32; 3],
 }
 
@@ -15,7 +15,7 @@ impl ColorNormalVertex {
     pub fn new(position: Vector3<f32>, color: Vector3<f32>, normal: Vector3<f32>) -> ColorNormalVertex {
         ColorNormalVertex {
             position: position.into(),
-            color: color.into(),
+            color: color.extend(1.0).into(),
             normal: normal.into(),
         }
     }
@@ -23,6 +23,13 @@ impl ColorNormalVertex {
     pub fn new_white(position: Vector3<f32>, normal: Vector3<f32>) -> ColorNormalVertex {
         Self::new(position, Vector3::new(1.0, 1.0, 1.0), normal)
     }
+
+    /// Sets the alpha of the color, which is the vertex's opacity with `AlphaMode::Blend`
+    /// materials; vertices are opaque by default.
+    pub fn with_alpha(mut self, alpha: f32) -> ColorNormalVertex {
+        self.color[3] = alpha;
+        self
+    }
 }
 
 impl Vertex for ColorNormalVertex {
@@ -43,10 +50,10 @@ impl Vertex for ColorNormalVertex {
                 wgpu::VertexAttribute {
                     offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Float32x4,
                 },
                 wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    offset: std::mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 }
@@ -6,7 +6,7 @@ use crate::mesh::Vertex;
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ColorVertex {
     position: [f32; 3],
-    color: [f32; 3],
+    color: [f32; 4],
 }
 
 // todo: investigate performance implications of using into() everywhere
@@ -14,13 +14,20 @@ impl ColorVertex {
     pub fn new(position: Vector3<f32>, color: Vector3<f32>) -> ColorVertex {
         ColorVertex {
             position: position.into(),
-            color: color.into(),
+            color: color.extend(1.0).into(),
         }
     }
 
     pub fn new_white(position: Vector3<f32>) -> ColorVertex {
         Self::new(position, Vector3::new(1.0, 1.0, 1.0))
     }
+
+    /// Sets the alpha of the color, which is the vertex's opacity with `AlphaMode::Blend`
+    /// materials; vertices are opaque by default.
+    pub fn with_alpha(mut self, alpha: f32) -> ColorVertex {
+        self.color[3] = alpha;
+        self
+    }
 }
 
 impl Vertex for ColorVertex {
@@ -41,7 +48,7 @@ impl Vertex for ColorVertex {
                 wgpu::VertexAttribute {
                     offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Float32x4,
                 },
             ]
         }
@@ -0,0 +1,107 @@
+// culls instances against up to two frusta, appending the visible ones to their draw command's
+// range of the output buffer and counting them in the command's instance count
+
+struct Cull {
+    // six inward-facing planes per frustum
+    planes: array<vec4<f32>, 12>,
+    frustum_count: u32,
+    row_length: u32, // invocations per row of workgroups
+    instance_count: u32,
+    job_count: u32,
+}
+
+// a run of instances of one mesh and material in the source buffer
+struct Job {
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    source_first: u32,
+    count: u32,
+    output_first: u32,
+    command: u32,
+}
+
+struct DrawCommand {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// the floats in one `InstanceData`
+const INSTANCE_FLOATS: u32 = 25u;
+
+@group(0) @binding(0)
+var<uniform> cull: Cull;
+@group(0) @binding(1)
+var<storage, read> jobs: array<Job>;
+@group(0) @binding(2)
+var<storage, read> source: array<f32>;
+@group(0) @binding(3)
+var<storage, read_write> output: array<f32>;
+@group(0) @binding(4)
+var<storage, read_write> commands: array<DrawCommand>;
+
+// the bounding sphere test, then the box test, against one frustum's planes
+fn inside(first_plane: u32, center: vec3<f32>, radius: f32, extent: vec3<f32>) -> bool {
+    for (var i = 0u; i < 6u; i++) {
+        let plane = cull.planes[first_plane + i];
+        let distance = dot(plane.xyz, center) + plane.w;
+        if distance < -radius || distance < -dot(abs(plane.xyz), extent) {
+            return false;
+        }
+    }
+    return true;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let instance = id.y * cull.row_length + id.x;
+    if instance >= cull.instance_count {
+        return;
+    }
+
+    // the last job starting at or before the instance, which may not cover it
+    var low = 0u;
+    var high = cull.job_count;
+    while high - low > 1u {
+        let middle = (low + high) / 2u;
+        if jobs[middle].source_first <= instance {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    let job = jobs[low];
+    if instance < job.source_first || instance >= job.source_first + job.count {
+        return;
+    }
+
+    let base = instance * INSTANCE_FLOATS;
+    var model: mat4x4<f32>;
+    for (var column = 0u; column < 4u; column++) {
+        let i = base + column * 4u;
+        model[column] = vec4<f32>(source[i], source[i + 1u], source[i + 2u], source[i + 3u]);
+    }
+
+    // the world-space sphere and box around the mesh's bounds, as in `Frustum::intersects()`
+    let half = (job.bounds_max.xyz - job.bounds_min.xyz) / 2.0;
+    let center = (model * vec4<f32>((job.bounds_min.xyz + job.bounds_max.xyz) / 2.0, 1.0)).xyz;
+    let scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+    let radius = length(half) * scale;
+    let extent = abs(model[0].xyz) * half.x + abs(model[1].xyz) * half.y + abs(model[2].xyz) * half.z;
+
+    var visible = false;
+    for (var frustum = 0u; frustum < cull.frustum_count; frustum++) {
+        visible = visible || inside(frustum * 6u, center, radius, extent);
+    }
+    if !visible {
+        return;
+    }
+
+    let slot = atomicAdd(&commands[job.command].instance_count, 1u);
+    let destination = (job.output_first + slot) * INSTANCE_FLOATS;
+    for (var i = 0u; i < INSTANCE_FLOATS; i++) {
+        output[destination + i] = source[base + i];
+    }
+}
@@ -0,0 +1,17 @@
+// copies the first sample of the multisampled depth buffer into a single-sampled depth texture, so
+// post-processing can read depth with msaa enabled
+
+@group(0) @binding(0)
+var depth: texture_multisampled_2d<f32>;
+
+// a triangle covering the screen, from the vertex index alone
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @builtin(frag_depth) f32 {
+    return textureLoad(depth, vec2<i32>(position.xy), 0).r;
+}
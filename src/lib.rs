@@ -7,6 +7,7 @@ pub mod scene;
 
 pub mod color_normal_vertex;
 pub mod color_vertex;
+pub mod textured_normal_vertex;
 
 pub mod test_assets;
 
@@ -16,11 +17,15 @@ pub mod game_program;
 
 
 
-pub use renderer::{instances::InstanceHandle, gpu_resources::MeshHandle, Renderer};
+pub use renderer::{instances::InstanceHandle, line_renderer::LineSetHandle, local_lights::{PointLightHandle, SpotLightHandle, MAX_POINT_LIGHTS, MAX_SPOT_LIGHTS}, multisampling::UnsupportedSampleCount, indirect::{DrawMode, UnsupportedDrawMode}, lod::{LodError, LodMetric, LodSettings}, config::RendererConfig, error::{HandleError, HandleKind, MeshUpdateError, RenderError, TextureError}, handle_report::{DanglingHandle, HandleReport}, stats::RendererStats, gpu_resources::{MaterialHandle, MeshHandle, PipelineHandle, TextureHandle}, pipeline::{PipelineBuilder, ShaderError, ShaderReloadError}, post_process::{BloomSettings, ColorGradingSettings, PostProcessBuilder, PostProcessHandle, VignetteSettings}, tone_mapping::ToneMapping, Renderer};
 pub use mesh::{Mesh, Vertex};
 pub use scene::{Transform, camera::Camera};
-pub use scene::light::{AmbientLight, DirectionalLight};
+pub use scene::light::{AmbientLight, DirectionalLight, PointLight, ShadowSettings, SpotLight};
+pub use scene::line_set::LineSet;
+pub use scene::material::{AlphaMode, Material, ShadingModel};
+pub use color_vertex::ColorVertex;
 pub use color_normal_vertex::ColorNormalVertex;
+pub use textured_normal_vertex::TexturedNormalVertex;
 pub use game_program::{Game, GameProgram};
 pub use window::{game_loop::GameLoop, run_program};
 pub use ui_manager::UIManager;
\ No newline at end of file
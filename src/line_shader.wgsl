@@ -1,6 +1,5 @@
-// ================================
-// vertex shader
-// ================================
+// draws each line as a quad, widened across the line to its thickness on screen
+
 struct CameraUniform {
     position: vec4<f32>,
     view_proj: mat4x4<f32>,
@@ -8,46 +7,71 @@ struct CameraUniform {
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) color: vec3<f32>,
+struct Viewport {
+    size: vec2<f32>, // in pixels
+};
+@group(1) @binding(0)
+var<uniform> viewport: Viewport;
+
+struct LineInput {
+    @location(0) start: vec3<f32>,
+    @location(1) start_color: vec4<f32>,
+    @location(2) end: vec3<f32>,
+    @location(3) end_color: vec4<f32>,
+    @location(4) thickness: f32,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) color: vec3<f32>,
-    @location(2) world_position: vec3<f32>,
+    @location(0) color: vec4<f32>,
 }
 
 @vertex
 fn vs_main(
-    model: VertexInput,
+    @builtin(vertex_index) vertex_index: u32,
+    line: LineInput,
 ) -> VertexOutput {
+    // the quad's corners: x is 0 at the start and 1 at the end, and y is the side of the line
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+    );
+    let corner = corners[vertex_index];
+
+    var start = camera.view_proj * vec4<f32>(line.start, 1.0);
+    var end = camera.view_proj * vec4<f32>(line.end, 1.0);
+
+    // clip the line to the near plane, so an end behind the camera doesn't flip its direction
+    if start.z < 0.0 && end.z >= 0.0 {
+        start = mix(start, end, start.z / (start.z - end.z));
+    } else if end.z < 0.0 && start.z >= 0.0 {
+        end = mix(end, start, end.z / (end.z - start.z));
+    }
+
+    // the line's direction in pixels, and the offset to the quad's edge in normalized device
+    // coordinates, which span two units across the viewport
+    let direction = (end.xy / end.w - start.xy / start.w) * viewport.size;
+    var across = vec2<f32>(0.0, 1.0);
+    if length(direction) > 0.0 {
+        let along = normalize(direction);
+        across = vec2<f32>(-along.y, along.x);
+    }
+    let offset = across * corner.y * line.thickness / viewport.size;
+
+    let position = mix(start, end, corner.x);
 
     var out: VertexOutput;
-    out.color = model.color;
-
-    var world_position: vec4<f32> = vec4<f32>(model.position, 1.0);
-    out.world_position = world_position.xyz;
-
-    out.clip_position = camera.view_proj * world_position;
+    out.clip_position = position + vec4<f32>(offset * position.w, 0.0, 0.0);
+    out.color = mix(line.start_color, line.end_color, corner.x);
 
     return out;
 }
 
-// ================================
-// fragment shader
-// ================================
-
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+    return in.color;
 }
-
-
-
-
-
-
-
-
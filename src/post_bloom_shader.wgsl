@@ -0,0 +1,36 @@
+struct BloomSettings {
+    threshold: f32,
+    intensity: f32,
+    radius: f32,
+};
+@group(0) @binding(5)
+var<uniform> settings: BloomSettings;
+
+fn bright(uv: vec2<f32>) -> vec3<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, uv, 0.0).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    return color * max(brightness - settings.threshold, 0.0) / max(brightness, 0.0001);
+}
+
+// gathers the bright parts of the image from rings of taps around each pixel, weighted to fall
+// off with distance
+@fragment
+fn fs_main(in: PostProcessVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, in.uv, 0.0);
+
+    var glow = bright(in.uv);
+    var total_weight = 1.0;
+    for (var ring = 1; ring <= 4; ring++) {
+        let distance = f32(ring) / 4.0;
+        let weight = exp(-4.0 * distance * distance);
+        for (var tap = 0; tap < 8; tap++) {
+            // offset each ring by half a tap so the taps do not line up
+            let angle = (f32(tap) + 0.5 * f32(ring % 2)) * 0.785398;
+            let offset = vec2<f32>(cos(angle), sin(angle)) * distance * settings.radius * frame.texel_size;
+            glow += weight * bright(in.uv + offset);
+            total_weight += weight;
+        }
+    }
+
+    return vec4<f32>(color.rgb + settings.intensity * glow / total_weight, color.a);
+}
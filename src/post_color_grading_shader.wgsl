@@ -0,0 +1,23 @@
+struct ColorGradingSettings {
+    tint: vec3<f32>,
+    saturation: f32,
+    contrast: f32,
+    brightness: f32,
+};
+@group(0) @binding(5)
+var<uniform> settings: ColorGradingSettings;
+
+@fragment
+fn fs_main(in: PostProcessVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, in.uv, 0.0);
+
+    var graded = color.rgb * settings.tint * settings.brightness;
+
+    let luma = dot(graded, vec3<f32>(0.2126, 0.7152, 0.0722));
+    graded = mix(vec3<f32>(luma), graded, settings.saturation);
+
+    // contrast pivots around middle grey so it does not change overall brightness much
+    graded = max((graded - 0.18) * settings.contrast + 0.18, vec3<f32>(0.0));
+
+    return vec4<f32>(graded, color.a);
+}
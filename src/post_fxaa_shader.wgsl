@@ -0,0 +1,54 @@
+// a simplified fxaa: blends across edges found from the luma of the pixel's neighbours
+
+fn luma(color: vec3<f32>) -> f32 {
+    // compress hdr values so bright pixels do not dominate the edge search
+    let mapped = color / (1.0 + color);
+    return dot(mapped, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+fn sample_input(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(input_texture, input_sampler, uv, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(in: PostProcessVertexOutput) -> @location(0) vec4<f32> {
+    let texel = frame.texel_size;
+    let center = sample_input(in.uv);
+
+    let luma_center = luma(center);
+    let luma_north_west = luma(sample_input(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_north_east = luma(sample_input(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_south_west = luma(sample_input(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_south_east = luma(sample_input(in.uv + vec2<f32>(1.0, 1.0) * texel));
+
+    let luma_min = min(luma_center, min(min(luma_north_west, luma_north_east), min(luma_south_west, luma_south_east)));
+    let luma_max = max(luma_center, max(max(luma_north_west, luma_north_east), max(luma_south_west, luma_south_east)));
+    if luma_max - luma_min < max(0.0312, luma_max * 0.125) {
+        return vec4<f32>(center, 1.0);
+    }
+
+    // the edge runs perpendicular to the luma gradient
+    var direction = vec2<f32>(
+        -((luma_north_west + luma_north_east) - (luma_south_west + luma_south_east)),
+        (luma_north_west + luma_south_west) - (luma_north_east + luma_south_east),
+    );
+    let direction_reduce = max((luma_north_west + luma_north_east + luma_south_west + luma_south_east) * 0.25 * 0.125, 1.0 / 128.0);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(direction * scale, vec2<f32>(-8.0), vec2<f32>(8.0)) * texel;
+
+    let near = 0.5 * (
+        sample_input(in.uv + direction * (1.0 / 3.0 - 0.5)) +
+        sample_input(in.uv + direction * (2.0 / 3.0 - 0.5))
+    );
+    let far = near * 0.5 + 0.25 * (
+        sample_input(in.uv - direction * 0.5) +
+        sample_input(in.uv + direction * 0.5)
+    );
+
+    // the wider blend overshoots if it picks up colors from outside the local range
+    let luma_far = luma(far);
+    if luma_far < luma_min || luma_far > luma_max {
+        return vec4<f32>(near, 1.0);
+    }
+    return vec4<f32>(far, 1.0);
+}
@@ -0,0 +1,37 @@
+// prepended to every post-process shader
+
+struct PostProcessFrame {
+    resolution: vec2<f32>,
+    texel_size: vec2<f32>,
+};
+
+// output of the previous pass, or the scene for the first pass
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+// the hdr scene before any post-processing
+@group(0) @binding(1)
+var scene_texture: texture_2d<f32>;
+// the scene's depth in the red channel; not filterable, so read it with textureLoad
+@group(0) @binding(2)
+var depth_texture: texture_2d<f32>;
+// linear filtering, clamped to the edges
+@group(0) @binding(3)
+var input_sampler: sampler;
+@group(0) @binding(4)
+var<uniform> frame: PostProcessFrame;
+
+struct PostProcessVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// a triangle covering the screen, from the vertex index alone
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> PostProcessVertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: PostProcessVertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
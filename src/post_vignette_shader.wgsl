@@ -0,0 +1,19 @@
+struct VignetteSettings {
+    strength: f32,
+    radius: f32,
+    softness: f32,
+};
+@group(0) @binding(5)
+var<uniform> settings: VignetteSettings;
+
+@fragment
+fn fs_main(in: PostProcessVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, in.uv, 0.0);
+
+    // distance from the center, corrected for the aspect ratio so the vignette is round
+    let aspect = frame.resolution.x / frame.resolution.y;
+    let offset = (in.uv - 0.5) * vec2<f32>(aspect, 1.0);
+    let darkening = smoothstep(settings.radius, settings.radius + settings.softness, length(offset));
+
+    return vec4<f32>(color.rgb * (1.0 - settings.strength * darkening), color.a);
+}
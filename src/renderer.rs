@@ -1,11 +1,13 @@
+use std::{path::Path, sync::{atomic::{AtomicBool, Ordering}, Arc}};
+
 use cgmath::Vector3;
 use egui::Context;
-use line_renderer::LineRenderer;
+use line_renderer::{LineRenderer, LineSetHandle};
 use winit::window::Window;
 
-use crate::{color_normal_vertex::ColorNormalVertex, color_vertex::ColorVertex, mesh::{Mesh, Vertex}, scene::{camera::Camera, light::{AmbientLight, DirectionalLight}, Transform}, UIManager};
+use crate::{color_normal_vertex::ColorNormalVertex, color_vertex::ColorVertex, textured_normal_vertex::TexturedNormalVertex, mesh::{Mesh, Vertex}, scene::{camera::Camera, light::{AmbientLight, DirectionalLight, PointLight, SpotLight}, line_set::LineSet, material::Material, Transform}, UIManager};
 
-use self::{gpu_resources::{Resources, MeshHandle}, instances::{InstanceListResource, InstanceHandle, InstanceData}};
+use self::{config::RendererConfig, error::{HandleError, MeshUpdateError, RenderError, TextureError}, handle_report::HandleReport, stats::RendererStats, gpu_resources::{Resources, MaterialHandle, MeshHandle, PipelineHandle, TextureHandle}, indirect::{DrawMode, IndirectRenderer, IndirectSupport, UnsupportedDrawMode}, instances::{InstanceListResource, InstanceHandle}, lod::{LodError, LodSettings}, local_lights::{PointLightHandle, SpotLightHandle}, multisampling::{MultisampleSupport, MultisampleTargets, UnsupportedSampleCount}, offscreen::OffscreenTarget, post_process::{PostProcessBuilder, PostProcessChain, PostProcessHandle}, tone_mapping::{ToneMapper, ToneMapping, HDR_FORMAT}, transparency::TransparentRenderer, pipeline::{PipelineBuilder, PipelineResource, ShaderError, ShaderReloadError}};
 
 pub mod create_pipeline;
 
@@ -15,35 +17,92 @@ pub mod instances;
 pub mod resizable_buffer;
 pub mod texture;
 pub mod pipeline;
-
+pub mod offscreen;
+pub mod shadows;
+pub mod local_lights;
+pub mod tone_mapping;
+pub mod post_process;
+pub mod multisampling;
+pub mod config;
+pub mod error;
+pub mod handle_report;
+pub mod stats;
+pub mod culling;
+pub mod indirect;
+pub mod lod;
+pub mod transparency;
 
 pub struct Renderer {
-    window: Window,
+    target: RenderTarget,
+    instance: wgpu::Instance,
+    config: RendererConfig,
     device: wgpu::Device,
-    surface: wgpu::Surface<'static>,
+    device_lost: Arc<AtomicBool>,
     surface_config: wgpu::SurfaceConfiguration,
     queue: wgpu::Queue,
-    tri_pipeline: wgpu::RenderPipeline,
-    line_pipeline: wgpu::RenderPipeline,
+    built_in_pipelines: Vec<(PipelineHandle, &'static str)>,
+    shader_hot_reload: bool,
+    frustum_culling: bool,
+    draw_mode: DrawMode,
+    clear_color: Vector3<f32>,
 
     line_renderer: LineRenderer,
+    tone_mapper: ToneMapper,
+    post_process_chain: PostProcessChain,
+    multisample_targets: MultisampleTargets,
+    multisample_support: MultisampleSupport,
+    indirect_renderer: IndirectRenderer,
+    transparent_renderer: TransparentRenderer,
     
     ui_manager: UIManager,
 
     resources: Resources,
+
+    // recovers from device loss as if the new adapter had no msaa
+    #[cfg(test)]
+    recover_without_msaa: bool,
+}
+
+// the surface is declared before the window so that it is dropped first
+enum RenderTarget {
+    Surface {
+        surface: wgpu::Surface<'static>,
+        window: Box<Window>,
+        present_modes: Vec<wgpu::PresentMode>,
+    },
+    Offscreen(OffscreenTarget),
 }
 
 impl Renderer {
-    pub fn window(&self) -> &Window {
-        &self.window
+    /// Returns the window being rendered to, or `None` for a headless renderer.
+    pub fn window(&self) -> Option<&Window> {
+        match &self.target {
+            RenderTarget::Surface { window, .. } => Some(window.as_ref()),
+            RenderTarget::Offscreen(_) => None,
+        }
+    }
+
+    /// The device, for creating buffers and bind groups to pass to `PipelineBuilder`.
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
     }
 
     // pub fn size(&self) -> 
 
+    /// Creates a renderer for `window` with the default `RendererConfig`.
     pub async fn new(window: Window) -> Renderer {
+        Self::with_config(window, RendererConfig::new()).await
+    }
+
+    /// Creates a renderer for `window`, choosing the adapter and device as `config` describes.
+    pub async fn with_config(window: Window, config: RendererConfig) -> Renderer {
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::default();
+        let instance = Self::create_instance(&config);
         
         // todo: consider making this safe
         let surface = unsafe {
@@ -53,25 +112,18 @@ impl Renderer {
         
         let adapter = instance.request_adapter(
             &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
+                power_preference: config.power_preference(),
                 compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
+                force_fallback_adapter: config.force_fallback_adapter(),
             },
         ).await.unwrap();
 
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
-                required_limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
-                    wgpu::Limits::default()
-                },
-                label: None,
-                memory_hints: Default::default(),
-            },
-            None,
-        ).await.unwrap();
+        let required_limits = match config.required_limits() {
+            Some(limits) => limits.clone(),
+            None if cfg!(target_arch = "wasm32") => wgpu::Limits::downlevel_webgl2_defaults(),
+            None => wgpu::Limits::default(),
+        };
+        let (device, queue) = Self::request_device(&adapter, &config, required_limits).await.unwrap();
         
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps.formats.iter()
@@ -85,100 +137,209 @@ impl Renderer {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode: config::choose_present_mode(config.present_mode(), &surface_caps.present_modes),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
         // surface.configure(&device, &surface_config);
 
-        let resources = Resources::new(&device, &surface_config);
+        Self::from_parts(
+            RenderTarget::Surface {
+                surface,
+                window: Box::new(window),
+                present_modes: surface_caps.present_modes,
+            },
+            instance,
+            &adapter,
+            device,
+            queue,
+            surface_config,
+            config,
+        )
+    }
 
-        let depth_format = Some(wgpu::TextureFormat::Depth32Float);
-        
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("render pipeline layout"),
-            bind_group_layouts: &[
-                resources.camera_bind_group_layout(),
-            ],
-            push_constant_ranges: &[],
-        });
-            
-        let tri_pipeline = {
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("tri_shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-            };
-            let shader = device.create_shader_module(shader);
-
-            let tri_primitive = wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            };
+    /// Creates a renderer that draws into an offscreen texture of the given size and format instead
+    /// of a window; read the result back with `read_pixels()`.  Set `force_fallback_adapter` to
+    /// render on a software adapter, e.g. on machines without a gpu.  Returns `None` if no suitable
+    /// adapter or device is available.
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        force_fallback_adapter: bool,
+    ) -> Option<Renderer> {
+        let config = RendererConfig::new().with_fallback_adapter(force_fallback_adapter);
+        Self::new_headless_with_config(width, height, format, config).await
+    }
 
-            create_pipeline::create_render_pipeline(
-                &device,
-                &pipeline_layout,
-                surface_config.format,
-                depth_format,
-                &[ColorNormalVertex::vertex_buffer_layout(), InstanceData::vertex_buffer_layout()],
-                &shader,
-                tri_primitive,
-            )
-        };
-
-        let line_pipeline = {
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("line_shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("line_shader.wgsl").into()),
-            };
-            let shader = device.create_shader_module(shader);
+    /// Like `new_headless()`, choosing the adapter and device as `config` describes.  The present
+    /// mode is ignored.
+    pub async fn new_headless_with_config(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        config: RendererConfig,
+    ) -> Option<Renderer> {
+        let instance = Self::create_instance(&config);
 
-            let line_primitive = wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::LineList,
-                front_face: wgpu::FrontFace::Ccw,
-                .. Default::default()
-            };
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: config.power_preference(),
+                compatible_surface: None,
+                force_fallback_adapter: config.force_fallback_adapter(),
+            },
+        ).await?;
+
+        // software adapters often fall short of the default limits, so ask for what the adapter has
+        let required_limits = config.required_limits().cloned().unwrap_or(adapter.limits());
+        let (device, queue) = Self::request_device(&adapter, &config, required_limits).await.ok()?;
 
-            create_pipeline::create_render_pipeline(
-                &device,
-                &pipeline_layout,
-                surface_config.format,
-                depth_format,
-                &[ColorVertex::vertex_buffer_layout()],
-                &shader,
-                line_primitive,
-            )
+        // not used to configure a surface, but keeps the size and format of the target in one place
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
         };
 
-        let line_renderer = LineRenderer::new(&device);
+        let target = OffscreenTarget::new(&device, &surface_config);
+
+        Some(Self::from_parts(
+            RenderTarget::Offscreen(target),
+            instance,
+            &adapter,
+            device,
+            queue,
+            surface_config,
+            config,
+        ))
+    }
+
+    fn create_instance(config: &RendererConfig) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: config.backends(),
+            ..Default::default()
+        })
+    }
 
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+        config: &RendererConfig,
+        required_limits: wgpu::Limits,
+    ) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+        // lets msaa use every sample count the adapter supports, rather than only 1 and 4
+        let msaa_features = adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        // lets indirect drawing share one instance buffer binding and issue multi-draws
+        let indirect_features = adapter.features() & (wgpu::Features::INDIRECT_FIRST_INSTANCE | wgpu::Features::MULTI_DRAW_INDIRECT);
+
+        adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: config.required_features() | msaa_features | indirect_features,
+                required_limits,
+                label: None,
+                memory_hints: Default::default(),
+            },
+            None,
+        ).await
+    }
+
+    fn from_parts(
+        target: RenderTarget,
+        instance: wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface_config: wgpu::SurfaceConfiguration,
+        config: RendererConfig,
+    ) -> Renderer {
+        let depth_format = config.depth_format();
+        assert!(
+            depth_format.is_depth_stencil_format() && !depth_format.has_stencil_aspect(),
+            "depth format {:?} is not a depth format without stencil",
+            depth_format,
+        );
+
+        let window = match &target {
+            RenderTarget::Surface { window, .. } => Some(window.as_ref()),
+            RenderTarget::Offscreen(_) => None,
+        };
         let ui_manager = UIManager::new(
-            &window,
+            window,
             &device,
             &surface_config,
-            depth_format,
+            None,
+            1,
         );
 
+        let multisample_support = MultisampleSupport::new(
+            adapter,
+            &device,
+            &[HDR_FORMAT, depth_format, surface_config.format],
+        );
+
+        let mut resources = Resources::new(&device, &queue, &surface_config, depth_format);
+        
+        // built-in pipelines, which become the defaults for their vertex types, with the file
+        // names their shaders are reloaded from
+        let built_in_pipelines = [
+            (PipelineBuilder::new::<ColorNormalVertex>("tri_shader", pipeline::COLOR_NORMAL_SHADER), "shader.wgsl"),
+            (PipelineBuilder::new::<TexturedNormalVertex>("textured_tri_shader", pipeline::TEXTURED_NORMAL_SHADER), "textured_shader.wgsl"),
+            (PipelineBuilder::new::<ColorVertex>("unlit_tri_shader", pipeline::UNLIT_COLOR_SHADER), "unlit_shader.wgsl"),
+        ];
+        let built_in_pipelines = built_in_pipelines.into_iter()
+            .map(|(pipeline, file_name)| {
+                let pipeline = pipeline.build(
+                    &device,
+                    &[resources.camera_bind_group_layout(), resources.material_bind_group_layout()],
+                    HDR_FORMAT,
+                    Some(depth_format),
+                    1,
+                );
+                (resources.add_pipeline(pipeline), file_name)
+            })
+            .collect();
+
+        let line_renderer = LineRenderer::new(&device, resources.camera_bind_group_layout(), depth_format, 1);
+        let tone_mapper = ToneMapper::new(&device, &queue, &surface_config, 1);
+        let post_process_chain = PostProcessChain::new(&device, &surface_config);
+        let multisample_targets = MultisampleTargets::new(&device, &surface_config, 1, &multisample_support);
+        let indirect_renderer = IndirectRenderer::new(IndirectSupport::new(adapter, &device), &device);
+        let transparent_renderer = TransparentRenderer::new(&device);
+        let device_lost = Self::watch_device(&device);
+        let clear_color = config.clear_color();
 
         Renderer {
-            window,
+            target,
+            instance,
+            config,
             device,
-            surface,
+            device_lost,
             surface_config,
             queue,
-            tri_pipeline,
-            line_pipeline,
+            built_in_pipelines,
+            shader_hot_reload: false,
+            frustum_culling: true,
+            draw_mode: DrawMode::Direct,
+            clear_color,
 
             line_renderer,
+            tone_mapper,
+            post_process_chain,
+            multisample_targets,
+            multisample_support,
+            indirect_renderer,
+            transparent_renderer,
             ui_manager,
 
             resources,
+
+            #[cfg(test)]
+            recover_without_msaa: false,
         }
     }
 
@@ -186,36 +347,74 @@ impl Renderer {
         if new_size.width > 0 && new_size.height > 0 {
             self.surface_config.width = new_size.width;
             self.surface_config.height = new_size.height;
-            self.surface.configure(&self.device, &self.surface_config);
-            self.resources.resize_depth_texture(&self.device, &self.surface_config);
+            match &mut self.target {
+                RenderTarget::Surface { surface, .. } => surface.configure(&self.device, &self.surface_config),
+                RenderTarget::Offscreen(target) => *target = OffscreenTarget::new(&self.device, &self.surface_config),
+            }
+            self.resources.resize_depth_texture(
+                &self.device,
+                &self.surface_config,
+                self.sample_count(),
+                self.multisample_targets.depth_readable(),
+            );
+            self.tone_mapper.resize(&self.device, &self.surface_config);
+            self.post_process_chain.resize(&self.device, &self.queue, &self.surface_config);
+            self.multisample_targets.resize(&self.device, &self.surface_config);
         }
     }
 
     pub fn egui_event(&mut self, event: &winit::event::WindowEvent) -> bool {
-        self.ui_manager.on_window_event(&self.window, event)
+        match &self.target {
+            RenderTarget::Surface { window, .. } => self.ui_manager.on_window_event(window, event),
+            RenderTarget::Offscreen(_) => false,
+        }
     }
 
-    pub fn render(&mut self) {
+    /// Draws a frame.  A lost or outdated surface is reconfigured, and a frame that still can't be
+    /// acquired is skipped.  If the device was lost, a new one is created first and every mesh,
+    /// texture, material, and instance is uploaded to it again; pipelines with bind groups of their
+    /// own are removed and reported as `RenderError::DeviceRecovered`.
+    pub fn render(&mut self) -> Result<(), RenderError> {
+        // device loss is reported from polling
+        self.device.poll(wgpu::Maintain::Poll);
+        if self.device_lost.load(Ordering::Acquire) {
+            self.recover_device()?;
+        }
 
-        // update instance buffers
-        for instance_list in self.resources.iterate_instance_lists_mut() {
-            instance_list.build_instance_buffer(&self.device, &self.queue);
+        if self.shader_hot_reload {
+            for error in self.reload_shaders() {
+                log::error!("{}", error);
+            }
         }
+
+        self.resources.upload_local_lights(&self.queue);
+
+        // update instance buffers, which are culled on the gpu instead with `DrawMode::GpuCulled`
+        let frusta = self.resources.culling_frusta(self.frustum_culling);
+        let cpu_frusta = if self.draw_mode == DrawMode::GpuCulled { &[] } else { frusta.as_slice() };
+        self.resources.build_instance_buffers(cpu_frusta, &self.device, &self.queue);
+        // the camera's frustum alone; blended instances reach the shadow map through the shadow pass
+        self.transparent_renderer.prepare(&frusta[..frusta.len().min(1)], &self.resources, &self.device, &self.queue);
         
-        let output = match self.surface.get_current_texture() {
-            Ok(surface_texture) => surface_texture,
-            Err(error) => {
-                println!("surface error: {}", error);
-                panic!();
+        let (output, view) = match &self.target {
+            RenderTarget::Surface { surface, .. } => {
+                let output = match Self::acquire_frame(surface, &self.device, &self.surface_config)? {
+                    Some(surface_texture) => surface_texture,
+                    None => {
+                        self.skip_frame();
+                        return Ok(());
+                    },
+                };
+                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(output), view)
             },
+            RenderTarget::Offscreen(target) => (None, target.texture().create_view(&wgpu::TextureViewDescriptor::default())),
         };
 
         // update line renderer
         self.line_renderer.update_buffer_and_clear(&self.device, &self.queue);
+        self.line_renderer.update_viewport(&self.queue, self.surface_config.width, self.surface_config.height);
 
-
-        // let output = self.surface.get_current_texture().unwrap();
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
         
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("render encoder"),
@@ -229,18 +428,28 @@ impl Renderer {
             &self.surface_config,
         );
 
-        // render pass
+        // shared buffers and draw arguments for indirect drawing
+        if self.draw_mode != DrawMode::Direct {
+            self.indirect_renderer.prepare(self.draw_mode, &frusta, &self.resources, &self.device, &self.queue, &mut encoder);
+        }
+
+        // shadow pass
+        if self.resources.light().shadows().is_some() {
+            self.render_shadows(&mut encoder);
+        }
+
+        // scene pass, into the hdr target
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("render pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+                    view: self.multisample_targets.scene_view().unwrap_or(self.tone_mapper.scene_view()),
+                    resolve_target: self.multisample_targets.scene_view().map(|_| self.tone_mapper.scene_view()),
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.01,
-                            g: 0.01,
-                            b: 0.01,
+                            r: self.clear_color.x as f64,
+                            g: self.clear_color.y as f64,
+                            b: self.clear_color.z as f64,
                             a: 1.0,
                         }),
                         store: wgpu::StoreOp::Store,
@@ -259,68 +468,651 @@ impl Renderer {
             });
 
 
-            // draw triangles
-            render_pass.set_pipeline(&self.tri_pipeline);
-            for instance_list in self.resources.iterate_instance_lists() {
-                self.draw_instance_list(&mut render_pass, instance_list, self.resources.camera_bind_group());
+            // draw triangles, one pipeline at a time
+            for (handle, pipeline) in self.resources.iterate_pipelines() {
+                render_pass.set_pipeline(pipeline.pipeline());
+                render_pass.set_bind_group(0, self.resources.camera_bind_group(), &[]);
+                for (i, bind_group) in pipeline.bind_groups().iter().enumerate() {
+                    render_pass.set_bind_group(2 + i as u32, bind_group, &[]);
+                }
+
+                if self.draw_mode == DrawMode::Direct {
+                    for instance_list in self.resources.iterate_instance_lists() {
+                        self.draw_instance_list(&mut render_pass, instance_list, handle, pipeline);
+                    }
+                } else {
+                    self.indirect_renderer.draw(&mut render_pass, handle, &self.resources);
+                }
             }
 
+            // draw instances with blended materials back to front, over the opaque ones
+            self.transparent_renderer.draw(&mut render_pass, &self.resources);
+
             // draw lines
-            render_pass.set_pipeline(&self.line_pipeline);
-            self.line_renderer.render(
-                &mut render_pass,
-                self.resources.camera_bind_group(),
-            );
-            
+            self.line_renderer.render(&mut render_pass, self.resources.camera_bind_group());
+        }
+
+        // post-processing, in hdr
+        let post_processed = if self.post_process_chain.has_enabled_passes() {
+            let depth_view = self.multisample_targets
+                .resolve_depth(&self.device, &mut encoder, self.resources.depth_texture_view())
+                .unwrap_or(self.resources.depth_texture_view());
+            self.post_process_chain.render(&self.device, &mut encoder, self.tone_mapper.scene_view(), depth_view)
+        } else {
+            None
+        };
+
+        // output pass, which tone maps the scene and draws the ui over it
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("output pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: self.multisample_targets.output_view().unwrap_or(&view),
+                    resolve_target: self.multisample_targets.output_view().map(|_| &view),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            self.tone_mapper.render(&mut render_pass, &self.device, post_processed);
 
             // draw ui
             self.ui_manager.render(&mut render_pass.forget_lifetime()); // egui makes us forget lifetime
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        if let Some(output) = output {
+            output.present();
+        }
+
+        Ok(())
+    }
+
+    // gets the next frame's texture, reconfiguring the surface once if it was lost or outdated;
+    // returns `None` if the frame should be skipped
+    fn acquire_frame(
+        surface: &wgpu::Surface,
+        device: &wgpu::Device,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Result<Option<wgpu::SurfaceTexture>, RenderError> {
+        let error = match surface.get_current_texture() {
+            Ok(surface_texture) => return Ok(Some(surface_texture)),
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                surface.configure(device, surface_config);
+                match surface.get_current_texture() {
+                    Ok(surface_texture) => return Ok(Some(surface_texture)),
+                    Err(error) => error,
+                }
+            },
+            Err(error) => error,
+        };
+
+        match error {
+            wgpu::SurfaceError::OutOfMemory => Err(RenderError::OutOfMemory),
+            error => {
+                log::warn!("skipping frame: {}", error);
+                Ok(None)
+            },
+        }
+    }
+
+    // throws away what was queued for a frame that won't be drawn
+    fn skip_frame(&mut self) {
+        self.line_renderer.clear();
+        self.ui_manager.skip_frame(&self.device, &self.queue, &self.surface_config);
+    }
+
+    // flags the device as lost when wgpu reports it, so the next frame can recover; errors after
+    // the loss are expected and only logged
+    fn watch_device(device: &wgpu::Device) -> Arc<AtomicBool> {
+        let device_lost = Arc::new(AtomicBool::new(false));
+
+        let flag = device_lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            match reason {
+                wgpu::DeviceLostReason::Unknown | wgpu::DeviceLostReason::Destroyed => {
+                    log::error!("device lost: {}", message);
+                    flag.store(true, Ordering::Release);
+                },
+                // the device was dropped by us, or the callback replaced on recovery
+                _ => {},
+            }
+        });
+
+        let flag = device_lost.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            if flag.load(Ordering::Acquire) {
+                log::warn!("wgpu error after device loss: {}", error);
+            } else {
+                panic!("wgpu error: {}", error);
+            }
+        }));
+
+        device_lost
+    }
+
+    // replaces a lost device with a new one, recreating every gpu object from cpu-side copies
+    fn recover_device(&mut self) -> Result<(), RenderError> {
+        log::warn!("recreating lost device");
+
+        let compatible_surface = match &self.target {
+            RenderTarget::Surface { surface, .. } => Some(surface),
+            RenderTarget::Offscreen(_) => None,
+        };
+        let adapter = pollster::block_on(self.instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: self.config.power_preference(),
+                compatible_surface,
+                force_fallback_adapter: self.config.force_fallback_adapter(),
+            },
+        )).ok_or_else(|| RenderError::DeviceLost("no suitable adapter".to_string()))?;
+
+        let limits = self.device.limits();
+        let (device, queue) = pollster::block_on(Self::request_device(&adapter, &self.config, limits))
+            .map_err(|error| RenderError::DeviceLost(error.to_string()))?;
+        self.device_lost = Self::watch_device(&device);
+        self.device = device;
+        self.queue = queue;
+
+        match &mut self.target {
+            RenderTarget::Surface { surface, .. } => surface.configure(&self.device, &self.surface_config),
+            RenderTarget::Offscreen(target) => *target = OffscreenTarget::new(&self.device, &self.surface_config),
+        }
+
+        // the new adapter may not support the same sample counts
+        self.multisample_support = MultisampleSupport::new(
+            &adapter,
+            &self.device,
+            &[HDR_FORMAT, self.resources.depth_format(), self.surface_config.format],
+        );
+        #[cfg(test)]
+        if self.recover_without_msaa {
+            self.multisample_support = self.multisample_support.clone().without_msaa();
+        }
+        let mut sample_count = self.sample_count();
+        if !self.supported_sample_counts().contains(&sample_count) {
+            log::warn!("{}x msaa is unsupported on the new device; disabling msaa", sample_count);
+            sample_count = 1;
+        }
+        self.multisample_targets = MultisampleTargets::new(&self.device, &self.surface_config, sample_count, &self.multisample_support);
+
+        self.indirect_renderer = IndirectRenderer::new(IndirectSupport::new(&adapter, &self.device), &self.device);
+        if !self.supported_draw_modes().contains(&self.draw_mode) {
+            log::warn!("draw mode {:?} is unsupported on the new device; drawing directly", self.draw_mode);
+            self.draw_mode = DrawMode::Direct;
+        }
+        self.transparent_renderer = TransparentRenderer::new(&self.device);
+
+        let removed_pipelines = self.resources.recreate(
+            &self.device,
+            &self.queue,
+            &self.surface_config,
+            sample_count,
+            self.multisample_targets.depth_readable(),
+        );
+
+        self.line_renderer.recreate(&self.device, self.resources.camera_bind_group_layout(), self.resources.depth_format(), sample_count);
+
+        let (tone_mapping, exposure) = (self.tone_mapping(), self.exposure());
+        self.tone_mapper = ToneMapper::new(&self.device, &self.queue, &self.surface_config, sample_count);
+        self.tone_mapper.set_tone_mapping(tone_mapping, &self.queue);
+        self.tone_mapper.set_exposure(exposure, &self.queue);
+        self.post_process_chain.recreate(&self.device, &self.surface_config);
+
+        let window = match &self.target {
+            RenderTarget::Surface { window, .. } => Some(window.as_ref()),
+            RenderTarget::Offscreen(_) => None,
+        };
+        self.ui_manager = UIManager::new(window, &self.device, &self.surface_config, None, sample_count);
+
+        if !removed_pipelines.is_empty() {
+            return Err(RenderError::DeviceRecovered { removed_pipelines });
+        }
+        Ok(())
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapper.tone_mapping()
+    }
+
+    /// Chooses how the hdr scene is mapped into the output's range.  The default, `None`, clips
+    /// colors above 1.0.
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapper.set_tone_mapping(tone_mapping, &self.queue);
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.tone_mapper.exposure()
     }
 
+    /// Scales the scene's colors before tone mapping; defaults to 1.0.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.tone_mapper.set_exposure(exposure, &self.queue);
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.surface_config.present_mode
+    }
+
+    /// Reconfigures the surface with a new present mode.  Modes the surface doesn't support fall
+    /// back to the first one it does, with a warning.  Has no effect on headless renderers.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        if let RenderTarget::Surface { surface, present_modes, .. } = &self.target {
+            self.surface_config.present_mode = config::choose_present_mode(Some(present_mode), present_modes);
+            surface.configure(&self.device, &self.surface_config);
+        }
+    }
+
+    /// Shorthand for setting the present mode to `AutoVsync` or `AutoNoVsync`.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(config::present_mode_for_vsync(vsync));
+    }
+
+    pub fn clear_color(&self) -> Vector3<f32> {
+        self.clear_color
+    }
+
+    /// The linear hdr color the scene is cleared to each frame, before tone mapping.
+    pub fn set_clear_color(&mut self, clear_color: Vector3<f32>) {
+        self.clear_color = clear_color;
+    }
+
+    // ================================================================
+    // multisampling
+    // ================================================================
+    /// The number of msaa samples per pixel; 1 when msaa is off, which is the default.
+    pub fn sample_count(&self) -> u32 {
+        self.multisample_targets.sample_count()
+    }
+
+    /// The sample counts this renderer's adapter supports, always including 1.
+    pub fn supported_sample_counts(&self) -> &Vec<u32> {
+        self.multisample_support.sample_counts()
+    }
+
+    /// Sets the number of msaa samples per pixel, recreating the render targets and every
+    /// pipeline that draws into them.  The ui renderer is recreated too, which resets egui's
+    /// state such as window positions.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<(), UnsupportedSampleCount> {
+        if !self.supported_sample_counts().contains(&sample_count) {
+            return Err(UnsupportedSampleCount::new(sample_count, self.supported_sample_counts().clone()));
+        }
+        if sample_count == self.sample_count() {
+            return Ok(());
+        }
+
+        self.multisample_targets = MultisampleTargets::new(&self.device, &self.surface_config, sample_count, &self.multisample_support);
+        self.resources.resize_depth_texture(
+            &self.device,
+            &self.surface_config,
+            sample_count,
+            self.multisample_targets.depth_readable(),
+        );
+
+        for (_, pipeline) in self.resources.iterate_pipelines_mut() {
+            pipeline.set_sample_count(&self.device, sample_count);
+        }
+        self.line_renderer.set_sample_count(&self.device, self.resources.camera_bind_group_layout(), self.resources.depth_format(), sample_count);
+        self.tone_mapper.set_sample_count(&self.device, &self.surface_config, sample_count);
+
+        let window = match &self.target {
+            RenderTarget::Surface { window, .. } => Some(window.as_ref()),
+            RenderTarget::Offscreen(_) => None,
+        };
+        self.ui_manager = UIManager::new(window, &self.device, &self.surface_config, None, sample_count);
+
+        Ok(())
+    }
+
+    // ================================================================
+    // post-processing
+    // ================================================================
+    /// Appends a pass to the end of the post-processing chain, which runs on the hdr scene before
+    /// tone mapping.
+    pub fn add_post_process(&mut self, builder: PostProcessBuilder) -> PostProcessHandle {
+        self.post_process_chain.insert(usize::MAX, builder, &self.device)
+    }
+
+    /// Inserts a pass so that it runs `position`th in the post-processing chain.
+    pub fn insert_post_process(&mut self, position: usize, builder: PostProcessBuilder) -> PostProcessHandle {
+        self.post_process_chain.insert(position, builder, &self.device)
+    }
+
+    pub fn remove_post_process(&mut self, handle: PostProcessHandle) -> Result<(), HandleError> {
+        self.post_process_chain.remove(handle)
+    }
+
+    /// Disabled passes are skipped without being removed from the chain.
+    pub fn set_post_process_enabled(&mut self, handle: PostProcessHandle, enabled: bool) -> Result<(), HandleError> {
+        self.post_process_chain.set_enabled(handle, enabled)
+    }
+
+    /// Replaces the contents of a pass's uniform block at group 0, binding 5.
+    pub fn update_post_process<T: bytemuck::Pod>(&mut self, handle: PostProcessHandle, uniforms: &T) -> Result<(), HandleError> {
+        self.post_process_chain.update_uniforms(handle, bytemuck::bytes_of(uniforms), &self.device, &self.queue)
+    }
+
+    /// Reads back the most recently rendered frame of a headless renderer as tightly packed RGBA8
+    /// rows, top row first.  Returns `None` when rendering to a window.
+    pub fn read_pixels(&self) -> Option<Vec<u8>> {
+        match &self.target {
+            RenderTarget::Surface { .. } => None,
+            RenderTarget::Offscreen(target) => Some(target.read_pixels(&self.device, &self.queue)),
+        }
+    }
+
+    // renders every active instance into the shadow map from the light's point of view
+    fn render_shadows(&self, encoder: &mut wgpu::CommandEncoder) {
+        let shadow_map = self.resources.shadow_map();
+
+        let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("shadow pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: shadow_map.view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        shadow_pass.set_bind_group(0, shadow_map.bind_group(), &[]);
+
+        if self.draw_mode != DrawMode::Direct {
+            self.indirect_renderer.draw_shadows(&mut shadow_pass, shadow_map);
+            return;
+        }
+
+        for instance_list in self.resources.iterate_instance_lists() {
+            if instance_list.buffered_instance_count() == 0 {
+                continue;
+            }
+            let Some(mesh) = self.resources.get_mesh(instance_list.mesh()) else {
+                continue;
+            };
+            let Some(pipeline) = shadow_map.pipeline(mesh.vertex_layout()) else {
+                continue;
+            };
+
+            shadow_pass.set_pipeline(pipeline);
+            shadow_pass.set_vertex_buffer(1, instance_list.instance_buffer().slice(..));
+
+            // each level of detail's batches are consecutive, whatever their materials
+            for batches in instance_list.batches().chunk_by(|a, b| a.lod() == b.lod()) {
+                let level = mesh.level(batches[0].lod());
+                if level.index_count() == 0 {
+                    continue;
+                }
+                shadow_pass.set_vertex_buffer(0, level.vertex_buffer().slice(..));
+                shadow_pass.set_index_buffer(level.index_buffer().slice(..), wgpu::IndexFormat::Uint32);
+                shadow_pass.draw_indexed(0..level.index_count(), 0, batches[0].range().start..batches[batches.len() - 1].range().end);
+            }
+        }
+    }
+
+    // draws the opaque batches of an instance list that use the given pipeline
     fn draw_instance_list(
         &self,
         render_pass: &mut wgpu::RenderPass,
         instance_list: &InstanceListResource,
-        camera_bind_group: &wgpu::BindGroup,
+        pipeline_handle: PipelineHandle,
+        pipeline: &PipelineResource,
     ) {
         if let Some(mesh) = self.resources.get_mesh(instance_list.mesh()) {
+            if mesh.vertex_layout() != pipeline.vertex_layout() {
+                return;
+            }
+
             render_pass.set_vertex_buffer(1, instance_list.instance_buffer().slice(..));
-            render_pass.set_vertex_buffer(0, mesh.vertex_buffer().slice(..));
-            render_pass.set_index_buffer(mesh.index_buffer().slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.set_bind_group(0, camera_bind_group, &[]);
-            render_pass.draw_indexed(0..mesh.index_count(), 0, 0..instance_list.buffered_instance_count());
+
+            let mut bound_level = None;
+            for batch in instance_list.batches() {
+                let level = mesh.level(batch.lod());
+                if level.index_count() == 0 {
+                    continue;
+                }
+                if let Some(material) = self.resources.get_material(batch.material()) {
+                    if !material.blended() && self.resources.resolve_pipeline(mesh, material) == Some(pipeline_handle) {
+                        if bound_level != Some(batch.lod()) {
+                            render_pass.set_vertex_buffer(0, level.vertex_buffer().slice(..));
+                            render_pass.set_index_buffer(level.index_buffer().slice(..), wgpu::IndexFormat::Uint32);
+                            bound_level = Some(batch.lod());
+                        }
+                        render_pass.set_bind_group(1, material.bind_group(), &[]);
+                        render_pass.draw_indexed(0..level.index_count(), 0, batch.range());
+                    }
+                }
+            }
         }
     }
 
     // ================================================================
     // interface for resources
     // ================================================================
-    pub fn add_mesh(&mut self, mesh: &Mesh<ColorNormalVertex>) -> MeshHandle {
-        self.resources.add_mesh(mesh, &self.device)
+    /// Adds a mesh of any vertex type.  Its instances are drawn with the default pipeline for the
+    /// vertex layout: built-in pipelines cover `ColorNormalVertex`, `TexturedNormalVertex`, and
+    /// `ColorVertex`, and the first pipeline added for any other layout becomes its default.
+    /// Meshes without a compatible pipeline are kept but not drawn.
+    pub fn add_mesh<T: Vertex>(&mut self, mesh: &Mesh<T>) -> MeshHandle {
+        self.resources.add_mesh(mesh, None, &self.device, &self.queue)
+    }
+
+    /// Adds a mesh drawn with a pipeline from `add_pipeline()`, which must have been built for
+    /// the vertex layout of `T`.
+    pub fn add_mesh_with_pipeline<T: Vertex>(&mut self, mesh: &Mesh<T>, pipeline: PipelineHandle) -> Result<MeshHandle, HandleError> {
+        self.resources.check_pipeline(pipeline)?;
+        Ok(self.resources.add_mesh(mesh, Some(pipeline), &self.device, &self.queue))
+    }
+
+    /// Adds a mesh with levels of detail, from `levels[0]`, the finest, to the coarsest.  Each
+    /// instance is drawn with the level `lods` chooses for it from the camera, which needs one
+    /// threshold between each pair of levels.  Frustum culling uses the bounds of the finest level.
+    /// Returns an error if `levels` is empty or the number of thresholds doesn't match.
+    pub fn add_mesh_with_lods<T: Vertex>(&mut self, levels: &[Mesh<T>], lods: LodSettings) -> Result<MeshHandle, LodError> {
+        self.resources.add_mesh_with_lods(levels, lods, None, &self.device, &self.queue)
+    }
+
+    /// Replaces a mesh's vertices and indices, rewriting its buffers in place and growing them if
+    /// needed.  Instances of the mesh keep their handles.  The vertex type may change, in which
+    /// case the mesh is drawn with the pipeline for the new layout.  Returns
+    /// `MeshUpdateError::LevelsOfDetail` for a mesh with levels of detail; use `update_mesh_lods()`.
+    pub fn update_mesh<T: Vertex>(&mut self, mesh: MeshHandle, data: &Mesh<T>) -> Result<(), MeshUpdateError> {
+        self.resources.update_mesh(mesh, data, &self.device, &self.queue)
+    }
+
+    /// Replaces all of a mesh's levels of detail and how they are chosen, as in
+    /// `add_mesh_with_lods()`.  The number of levels may change, and a single level turns the mesh
+    /// into one without levels of detail.
+    pub fn update_mesh_lods<T: Vertex>(&mut self, mesh: MeshHandle, levels: &[Mesh<T>], lods: LodSettings) -> Result<(), MeshUpdateError> {
+        self.resources.update_mesh_lods(mesh, levels, lods, &self.device, &self.queue)
+    }
+
+    /// Overwrites the vertices of the mesh starting at `first` without changing how many there
+    /// are.  Returns `MeshUpdateError::LevelsOfDetail` for a mesh with levels of detail.
+    pub fn update_mesh_vertices<T: Vertex>(&mut self, mesh: MeshHandle, first: u32, vertices: &[T]) -> Result<(), MeshUpdateError> {
+        self.resources.update_mesh_vertices(mesh, first, vertices, &self.queue)
+    }
+
+    /// Overwrites the indices of the mesh starting at `first` without changing how many there are.
+    /// Returns `MeshUpdateError::LevelsOfDetail` for a mesh with levels of detail.
+    pub fn update_mesh_indices(&mut self, mesh: MeshHandle, first: u32, indices: &[u32]) -> Result<(), MeshUpdateError> {
+        self.resources.update_mesh_indices(mesh, first, indices, &self.queue)
+    }
+
+    /// Changes the pipeline used for instances of `mesh` whose material does not override it;
+    /// `None` goes back to the default pipeline for its vertex layout.
+    pub fn set_mesh_pipeline(&mut self, mesh: MeshHandle, pipeline: Option<PipelineHandle>) -> Result<(), HandleError> {
+        self.resources.set_mesh_pipeline(mesh, pipeline)
     }
 
-    pub fn remove_mesh(&mut self, mesh: MeshHandle) {
-        self.resources.remove_mesh(mesh);
+    /// Makes `pipeline` the default for meshes with the vertex layout of `T`.
+    pub fn set_default_pipeline<T: Vertex>(&mut self, pipeline: PipelineHandle) -> Result<(), HandleError> {
+        self.resources.set_default_pipeline(T::vertex_buffer_layout(), pipeline)
     }
 
-    pub fn add_instance(&mut self, mesh: MeshHandle, transform: Transform) -> InstanceHandle {
-        self.resources.add_instance(mesh, transform)
+    /// Compiles a pipeline from user WGSL; see `PipelineBuilder` for what the shader can expect.
+    /// The pipeline becomes the default for its vertex layout if there is none yet.  Returns an
+    /// error if the shader or pipeline fails validation.
+    pub fn add_pipeline(&mut self, pipeline: PipelineBuilder) -> Result<PipelineHandle, ShaderError> {
+        let pipeline = pipeline.try_build(
+            &self.device,
+            &[self.resources.camera_bind_group_layout(), self.resources.material_bind_group_layout()],
+            HDR_FORMAT,
+            Some(self.resources.depth_format()),
+            self.sample_count(),
+        )?;
+        Ok(self.resources.add_pipeline(pipeline))
     }
 
-    pub fn update_instance(&mut self, instance: InstanceHandle, transform: Transform) {
-        self.resources.update_instance(instance, transform);
+    /// Development mode: loads the built-in shaders from `directory` instead of the copies compiled
+    /// into the crate, and recompiles any pipeline whose shader file has changed at the start of
+    /// each frame.  Pipelines from `PipelineBuilder::from_file()` or `with_shader_path()` are
+    /// watched too.  Errors are logged and the previous pipeline is kept.  Not available on wasm.
+    pub fn enable_shader_hot_reload(&mut self, directory: impl AsRef<Path>) {
+        for (pipeline, file_name) in &self.built_in_pipelines {
+            if let Some(pipeline) = self.resources.get_pipeline_mut(*pipeline) {
+                pipeline.set_shader_path(Some(directory.as_ref().join(file_name)));
+            }
+        }
+        self.shader_hot_reload = true;
     }
 
-    pub fn set_instance_active(&mut self, instance: InstanceHandle, active: bool) {
-        self.resources.set_instance_active(instance, active);
+    /// Recompiles pipelines whose shader files have changed, returning the errors for those that
+    /// failed.  Called by `render()` when hot reloading is enabled.
+    pub fn reload_shaders(&mut self) -> Vec<ShaderReloadError> {
+        let mut errors = Vec::new();
+        for (_, pipeline) in self.resources.iterate_pipelines_mut() {
+            match pipeline.reload_if_modified(&self.device) {
+                Ok(true) => log::info!("reloaded shader {}", pipeline.shader_path().unwrap().display()),
+                Ok(false) => {},
+                Err(error) => errors.push(error),
+            }
+        }
+        errors
     }
 
-    pub fn remove_instance(&mut self, instance: InstanceHandle) {
-        self.resources.remove_instance(instance);
+    /// Removes a pipeline; meshes and materials still using it are not drawn.
+    pub fn remove_pipeline(&mut self, pipeline: PipelineHandle) -> Result<(), HandleError> {
+        self.resources.remove_pipeline(pipeline)
+    }
+
+    /// Removes a mesh along with its instances, whose handles become stale.
+    pub fn remove_mesh(&mut self, mesh: MeshHandle) -> Result<(), HandleError> {
+        self.resources.remove_mesh(mesh)
+    }
+
+    /// Uploads a texture from tightly packed sRGB RGBA8 rows, top row first.  Returns an error if
+    /// the texture is empty or too large, or `data` isn't `4 * width * height` bytes.
+    pub fn add_texture(&mut self, width: u32, height: u32, data: &[u8]) -> Result<TextureHandle, TextureError> {
+        self.resources.add_texture(width, height, data, &self.device, &self.queue)
+    }
+
+    /// Decodes a PNG or JPEG image and uploads it as a texture.
+    pub fn add_texture_from_image(&mut self, bytes: &[u8]) -> Result<TextureHandle, TextureError> {
+        let image = image::load_from_memory(bytes)?.to_rgba8();
+        self.add_texture(image.width(), image.height(), image.as_raw())
+    }
+
+    /// Removes a texture.  Materials already using it keep drawing with it until they are updated.
+    pub fn remove_texture(&mut self, texture: TextureHandle) -> Result<(), HandleError> {
+        self.resources.remove_texture(texture)
+    }
+
+    /// Adds a material.  Returns an error if its albedo texture or pipeline was removed.
+    pub fn add_material(&mut self, material: &Material) -> Result<MaterialHandle, HandleError> {
+        self.resources.add_material(material, &self.device)
+    }
+
+    pub fn update_material(&mut self, handle: MaterialHandle, material: &Material) -> Result<(), HandleError> {
+        self.resources.update_material(handle, material, &self.device, &self.queue)
+    }
+
+    /// Removes a material; instances still using it are not drawn.
+    pub fn remove_material(&mut self, material: MaterialHandle) -> Result<(), HandleError> {
+        self.resources.remove_material(material)
+    }
+
+    pub fn frustum_culling(&self) -> bool {
+        self.frustum_culling
+    }
+
+    /// Skips instances whose mesh's bounds are outside the camera's view, and outside the shadow
+    /// map when shadows are on.  Enabled by default.
+    pub fn set_frustum_culling(&mut self, frustum_culling: bool) {
+        self.frustum_culling = frustum_culling;
+    }
+
+    pub fn draw_mode(&self) -> DrawMode {
+        self.draw_mode
+    }
+
+    /// The draw modes the device supports, always including `DrawMode::Direct`.
+    pub fn supported_draw_modes(&self) -> &Vec<DrawMode> {
+        self.indirect_renderer.support().draw_modes()
+    }
+
+    /// Chooses between drawing each mesh directly and drawing from shared buffers with indirect
+    /// draws, optionally culled on the gpu.  `DrawMode::Direct` by default.
+    pub fn set_draw_mode(&mut self, draw_mode: DrawMode) -> Result<(), UnsupportedDrawMode> {
+        if !self.supported_draw_modes().contains(&draw_mode) {
+            return Err(UnsupportedDrawMode::new(draw_mode, self.supported_draw_modes().clone()));
+        }
+        self.draw_mode = draw_mode;
+        Ok(())
+    }
+
+    /// Counts live meshes and instances, how many instances the last frame drew and culled, and
+    /// the gpu memory they use, including the shared buffers for indirect and transparent drawing.
+    pub fn stats(&self) -> RendererStats {
+        let stats = self.resources.stats();
+        RendererStats::new(
+            stats.meshes(),
+            stats.instances(),
+            stats.drawn_instances(),
+            stats.culled_instances(),
+            stats.uploaded_instance_bytes(),
+            stats.gpu_bytes() + self.indirect_renderer.gpu_bytes() + self.transparent_renderer.gpu_bytes(),
+        )
+    }
+
+    /// Lists handles kept by meshes, materials, and instances that refer to removed resources, which
+    /// are otherwise silently skipped when drawing.  Only available in debug builds.
+    #[cfg(debug_assertions)]
+    pub fn validate_handles(&self) -> HandleReport {
+        self.resources.validate_handles()
+    }
+
+    /// A plain white material, so instances can be added without setting up materials first.
+    pub fn default_material(&self) -> MaterialHandle {
+        self.resources.default_material()
+    }
+
+    pub fn add_instance(&mut self, mesh: MeshHandle, material: MaterialHandle, transform: Transform) -> Result<InstanceHandle, HandleError> {
+        self.resources.add_instance(mesh, material, transform)
+    }
+
+    pub fn set_instance_material(&mut self, instance: InstanceHandle, material: MaterialHandle) -> Result<(), HandleError> {
+        self.resources.set_instance_material(instance, material)
+    }
+
+    pub fn update_instance(&mut self, instance: InstanceHandle, transform: Transform) -> Result<(), HandleError> {
+        self.resources.update_instance(instance, transform)
+    }
+
+    pub fn set_instance_active(&mut self, instance: InstanceHandle, active: bool) -> Result<(), HandleError> {
+        self.resources.set_instance_active(instance, active)
+    }
+
+    pub fn remove_instance(&mut self, instance: InstanceHandle) -> Result<(), HandleError> {
+        self.resources.remove_instance(instance)
     }
 
     pub fn update_camera(&mut self, camera: &Camera) {
@@ -328,7 +1120,33 @@ impl Renderer {
     }
 
     pub fn update_light(&mut self, directional_light: &DirectionalLight, ambient_light: &AmbientLight) {
-        self.resources.update_light(directional_light, ambient_light, &self.queue);
+        self.resources.update_light(directional_light, ambient_light, &self.device, &self.queue);
+    }
+
+    /// Adds a point light; up to `MAX_POINT_LIGHTS` are drawn at once.
+    pub fn add_point_light(&mut self, light: &PointLight) -> PointLightHandle {
+        self.resources.add_point_light(light)
+    }
+
+    pub fn update_point_light(&mut self, handle: PointLightHandle, light: &PointLight) -> Result<(), HandleError> {
+        self.resources.update_point_light(handle, light)
+    }
+
+    pub fn remove_point_light(&mut self, handle: PointLightHandle) -> Result<(), HandleError> {
+        self.resources.remove_point_light(handle)
+    }
+
+    /// Adds a spot light; up to `MAX_SPOT_LIGHTS` are drawn at once.
+    pub fn add_spot_light(&mut self, light: &SpotLight) -> SpotLightHandle {
+        self.resources.add_spot_light(light)
+    }
+
+    pub fn update_spot_light(&mut self, handle: SpotLightHandle, light: &SpotLight) -> Result<(), HandleError> {
+        self.resources.update_spot_light(handle, light)
+    }
+
+    pub fn remove_spot_light(&mut self, handle: SpotLightHandle) -> Result<(), HandleError> {
+        self.resources.remove_spot_light(handle)
     }
 
     // ================================================================
@@ -337,7 +1155,13 @@ impl Renderer {
     
     ///Draws a line in immediate mode - i.e., this function draws a line on the next `render()` call only.
     pub fn draw_line(&mut self, start: ColorVertex, end: ColorVertex) {
-        self.line_renderer.draw_line(start, end);
+        self.line_renderer.draw_line(start, end, 1.0, false);
+    }
+
+    ///Draws a line in immediate mode, `thickness` pixels wide on screen whatever its distance from
+    ///the camera, and over everything else in the scene if `on_top`.
+    pub fn draw_thick_line(&mut self, start: ColorVertex, end: ColorVertex, thickness: f32, on_top: bool) {
+        self.line_renderer.draw_line(start, end, thickness, on_top);
     }
 
     ///Draws a red line in immediate mode - i.e., this function draws a line on the next `render()` call only.
@@ -364,12 +1188,819 @@ impl Renderer {
         self.draw_line(start, end);
     }
 
+    // ================================================================
+    // persistent lines
+    // ================================================================
+
+    /// Adds lines that are drawn every frame until the set is removed.
+    pub fn add_line_set(&mut self, line_set: &LineSet) -> LineSetHandle {
+        self.line_renderer.add_line_set(line_set, &self.device)
+    }
+
+    pub fn update_line_set(&mut self, handle: LineSetHandle, line_set: &LineSet) -> Result<(), HandleError> {
+        self.line_renderer.update_line_set(handle, line_set, &self.device)
+    }
+
+    pub fn remove_line_set(&mut self, handle: LineSetHandle) -> Result<(), HandleError> {
+        self.line_renderer.remove_line_set(handle)
+    }
+
     // ================================================================
     // immediate mode gui
     // ================================================================
 
     pub fn run_ui<F: FnMut(&Context)>(&mut self, gui: F) {
-        self.ui_manager.run(&self.window, gui);
+        let window = match &self.target {
+            RenderTarget::Surface { window, .. } => Some(window.as_ref()),
+            RenderTarget::Offscreen(_) => None,
+        };
+        self.ui_manager.run(window, gui);
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector4;
+    use crate::scene::material::AlphaMode;
+    use error::HandleKind;
+    #[cfg(debug_assertions)]
+    use handle_report::DanglingHandle;
+
+    // a headless renderer on the fallback adapter, or `None` if there is none and the test should
+    // be skipped; like the golden tests, set `SHADERUNNER_REQUIRE_GPU=1` to fail instead
+    fn headless(width: u32, height: u32) -> Option<Renderer> {
+        let renderer = pollster::block_on(Renderer::new_headless(width, height, wgpu::TextureFormat::Rgba8Unorm, true));
+
+        if renderer.is_none() {
+            if std::env::var_os("SHADERUNNER_REQUIRE_GPU").is_some() {
+                panic!("no fallback adapter available and SHADERUNNER_REQUIRE_GPU is set");
+            }
+            println!("no fallback adapter available; skipping");
+        }
+        renderer
+    }
+
+    // a square camera at `y` on the y axis, looking at the origin with z up
+    fn camera_on_y_axis(y: f32) -> Camera {
+        Camera::new(
+            Vector3::new(0.0, y, 0.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::unit_z(),
+            1.0,
+            std::f32::consts::TAU / 8.0,
+            0.1,
+            100.0,
+        )
+    }
+
+    #[test]
+    fn test_headless_clear() {
+        let Some(mut renderer) = headless(8, 4) else {
+            return;
+        };
+
+        renderer.render().unwrap();
+        let pixels = renderer.read_pixels().unwrap();
+
+        assert_eq!(pixels.len(), 8 * 4 * 4);
+        for pixel in pixels.chunks(4) {
+            assert_eq!(pixel, &[3, 3, 3, 255]);
+        }
+    }
+
+    #[test]
+    fn test_headless_config() {
+        let config = RendererConfig::new()
+            .with_fallback_adapter(true)
+            .with_depth_format(wgpu::TextureFormat::Depth16Unorm)
+            .with_clear_color(Vector3::new(1.0, 0.0, 0.0));
+        let Some(mut renderer) = pollster::block_on(Renderer::new_headless_with_config(
+            8,
+            4,
+            wgpu::TextureFormat::Rgba8Unorm,
+            config,
+        )) else {
+            println!("no fallback adapter available; skipping");
+            return;
+        };
+
+        // meshes draw against the configured depth format
+        let material = renderer.default_material();
+        let sphere = renderer.add_mesh(&crate::test_assets::simple_sphere_mesh(0.1, 4, Vector3::new(1.0, 1.0, 1.0)));
+        renderer.add_instance(sphere, material, Transform::identity()).unwrap();
+
+        renderer.render().unwrap();
+        assert_eq!(&renderer.read_pixels().unwrap()[0..4], &[255, 0, 0, 255]);
+
+        renderer.set_clear_color(Vector3::new(0.0, 0.0, 1.0));
+        renderer.render().unwrap();
+        assert_eq!(&renderer.read_pixels().unwrap()[0..4], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_set_sample_count() {
+        let Some(mut renderer) = headless(8, 4) else {
+            return;
+        };
+
+        assert_eq!(renderer.sample_count(), 1);
+        assert!(renderer.supported_sample_counts().contains(&1));
+
+        let error = renderer.set_sample_count(3).unwrap_err();
+        assert_eq!(error.sample_count(), 3);
+        assert_eq!(renderer.sample_count(), 1);
+
+        // 4 is guaranteed by webgpu, but the fallback adapter may still not offer it
+        if !renderer.supported_sample_counts().contains(&4) {
+            println!("4x msaa not supported; skipping");
+            return;
+        }
+        renderer.set_sample_count(4).unwrap();
+        assert_eq!(renderer.sample_count(), 4);
+
+        // the clear color resolves unchanged, including through post-processing, which reads the
+        // resolved depth
+        renderer.add_post_process(PostProcessBuilder::fxaa());
+        renderer.render().unwrap();
+        for pixel in renderer.read_pixels().unwrap().chunks(4) {
+            assert_eq!(pixel, &[3, 3, 3, 255]);
+        }
+
+        renderer.set_sample_count(1).unwrap();
+        renderer.render().unwrap();
+        for pixel in renderer.read_pixels().unwrap().chunks(4) {
+            assert_eq!(pixel, &[3, 3, 3, 255]);
+        }
+    }
+
+    #[test]
+    fn test_device_loss_recovery() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+
+        let texture = renderer.add_texture(64, 64, &crate::test_assets::checkerboard_texture(64, 8)).unwrap();
+        let material = renderer.add_material(&Material::white().with_albedo_texture(texture)).unwrap();
+        let quad = renderer.add_mesh(&crate::test_assets::textured_quad_mesh());
+        renderer.add_instance(quad, material, Transform::identity()).unwrap();
+        renderer.update_camera(&Camera::new(
+            Vector3::new(0.8, -1.5, 1.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::unit_z(),
+            1.0,
+            std::f32::consts::TAU / 8.0,
+            0.1,
+            100.0,
+        ));
+
+        renderer.render().unwrap();
+        let before = renderer.read_pixels().unwrap();
+        assert!(before.chunks(4).any(|pixel| pixel != [3, 3, 3, 255]));
+
+        // the next frame recreates the device and everything on it
+        renderer.device().destroy();
+        renderer.render().unwrap();
+        let after = renderer.read_pixels().unwrap();
+
+        assert!(!renderer.device_lost.load(Ordering::Acquire));
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn test_device_loss_without_msaa() {
+        let (Some(mut renderer), Some(mut expected)) = (headless(32, 32), headless(32, 32)) else {
+            return;
+        };
+        if renderer.set_sample_count(4).is_err() {
+            println!("4x msaa not supported; skipping");
+            return;
+        }
+
+        // a custom pipeline alongside the built-in ones, all built for 4x msaa
+        for renderer in [&mut renderer, &mut expected] {
+            let pipeline = renderer.add_pipeline(PipelineBuilder::new::<ColorNormalVertex>("custom", pipeline::COLOR_NORMAL_SHADER)).unwrap();
+            let sphere = renderer.add_mesh_with_pipeline(&crate::test_assets::simple_sphere_mesh(0.5, 8, Vector3::new(1.0, 1.0, 1.0)), pipeline).unwrap();
+            let material = renderer.default_material();
+            renderer.add_instance(sphere, material, Transform::identity()).unwrap();
+            renderer.update_camera(&camera_on_y_axis(-3.0));
+        }
+        renderer.render().unwrap();
+
+        // the new device can't do 4x msaa, so every pipeline is rebuilt without it
+        renderer.recover_without_msaa = true;
+        renderer.device().destroy();
+        renderer.render().unwrap();
+        assert_eq!(renderer.sample_count(), 1);
+
+        expected.render().unwrap();
+        expected.set_sample_count(1).unwrap();
+        expected.render().unwrap();
+        assert_eq!(renderer.read_pixels().unwrap(), expected.read_pixels().unwrap());
+    }
+
+    #[test]
+    fn test_device_loss_removes_bound_pipelines() {
+        let Some(mut renderer) = headless(8, 4) else {
+            return;
+        };
+
+        let layout = renderer.device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[],
+        });
+        let bind_group = renderer.device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[],
+        });
+        let bound = renderer.add_pipeline(
+            PipelineBuilder::new::<ColorVertex>("bound", pipeline::UNLIT_COLOR_SHADER).with_bind_group(layout, bind_group),
+        ).unwrap();
+        let unbound = renderer.add_pipeline(PipelineBuilder::new::<ColorVertex>("unbound", pipeline::UNLIT_COLOR_SHADER)).unwrap();
+        renderer.render().unwrap();
+
+        // the pipeline with its own bind group is reported and removed, and the frame is skipped
+        renderer.device().destroy();
+        match renderer.render() {
+            Err(RenderError::DeviceRecovered { removed_pipelines }) => assert_eq!(removed_pipelines, vec![bound]),
+            result => panic!("expected the removed pipeline to be reported, got {:?}", result),
+        }
+        assert_eq!(renderer.remove_pipeline(bound), Err(HandleError::Stale(HandleKind::Pipeline)));
+
+        renderer.render().unwrap();
+        renderer.remove_pipeline(unbound).unwrap();
+    }
+
+    #[test]
+    fn test_stale_handles() {
+        let Some(mut renderer) = headless(8, 4) else {
+            return;
+        };
+
+        let sphere = renderer.add_mesh(&crate::test_assets::simple_sphere_mesh(0.1, 4, Vector3::new(1.0, 1.0, 1.0)));
+        let material = renderer.add_material(&Material::white()).unwrap();
+        let instance = renderer.add_instance(sphere, material, Transform::identity()).unwrap();
+
+        // removing a material leaves its instances dangling
+        renderer.remove_material(material).unwrap();
+        assert_eq!(renderer.remove_material(material), Err(HandleError::Stale(HandleKind::Material)));
+        assert_eq!(
+            renderer.add_instance(sphere, material, Transform::identity()).unwrap_err(),
+            HandleError::Stale(HandleKind::Material),
+        );
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            renderer.validate_handles().dangling(),
+            &vec![DanglingHandle::InstanceMaterial { instance, material }],
+        );
+        renderer.set_instance_material(instance, renderer.default_material()).unwrap();
+        #[cfg(debug_assertions)]
+        assert!(renderer.validate_handles().is_valid());
+
+        renderer.remove_instance(instance).unwrap();
+        assert_eq!(renderer.update_instance(instance, Transform::identity()), Err(HandleError::Stale(HandleKind::Instance)));
+
+        let instance = renderer.add_instance(sphere, renderer.default_material(), Transform::identity()).unwrap();
+        renderer.remove_mesh(sphere).unwrap();
+        assert_eq!(renderer.set_instance_active(instance, false), Err(HandleError::MeshRemoved));
+        assert_eq!(
+            renderer.add_instance(sphere, renderer.default_material(), Transform::identity()).unwrap_err(),
+            HandleError::Stale(HandleKind::Mesh),
+        );
+
+        let light = renderer.add_point_light(&PointLight::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0), 1.0, 1.0));
+        renderer.remove_point_light(light).unwrap();
+        assert_eq!(renderer.remove_point_light(light), Err(HandleError::Stale(HandleKind::PointLight)));
+
+        // materials with removed textures or pipelines are rejected rather than drawn white or
+        // with the default pipeline
+        let texture = renderer.add_texture(1, 1, &[255, 255, 255, 255]).unwrap();
+        let pipeline = renderer.add_pipeline(PipelineBuilder::new::<ColorVertex>("unlit", pipeline::UNLIT_COLOR_SHADER)).unwrap();
+        let material = renderer.add_material(&Material::white()).unwrap();
+        renderer.remove_texture(texture).unwrap();
+        renderer.remove_pipeline(pipeline).unwrap();
+        for stale in [Material::white().with_albedo_texture(texture), Material::white().with_pipeline(pipeline)] {
+            let kind = if stale.albedo_texture().is_some() { HandleKind::Texture } else { HandleKind::Pipeline };
+            assert_eq!(renderer.add_material(&stale), Err(HandleError::Stale(kind)));
+            assert_eq!(renderer.update_material(material, &stale), Err(HandleError::Stale(kind)));
+        }
+
+        // the renderer still draws after all of the above
+        renderer.render().unwrap();
+    }
+
+    #[test]
+    fn test_remove_mesh() {
+        let Some(mut renderer) = headless(8, 4) else {
+            return;
+        };
+
+        let empty = renderer.stats();
+        let material = renderer.default_material();
+        let sphere = renderer.add_mesh(&crate::test_assets::simple_sphere_mesh(0.1, 4, Vector3::new(1.0, 1.0, 1.0)));
+        let cube = renderer.add_mesh(&crate::test_assets::cube_mesh());
+        let instance = renderer.add_instance(sphere, material, Transform::identity()).unwrap();
+        renderer.add_instance(sphere, material, Transform::identity()).unwrap();
+        renderer.add_instance(cube, material, Transform::identity()).unwrap();
+        renderer.render().unwrap();
+
+        let stats = renderer.stats();
+        assert_eq!(stats.meshes(), 2);
+        assert_eq!(stats.instances(), 3);
+        assert!(stats.gpu_bytes() > empty.gpu_bytes());
+
+        // the sphere's instances go with it
+        renderer.remove_mesh(sphere).unwrap();
+        assert_eq!(renderer.remove_instance(instance), Err(HandleError::MeshRemoved));
+        let stats = renderer.stats();
+        assert_eq!(stats.meshes(), 1);
+        assert_eq!(stats.instances(), 1);
+
+        // a mesh reusing the removed mesh's slot gets instances of its own
+        let quad = renderer.add_mesh(&crate::test_assets::textured_quad_mesh());
+        renderer.add_instance(quad, material, Transform::identity()).unwrap();
+        renderer.render().unwrap();
+        assert_eq!(renderer.stats().instances(), 2);
+
+        renderer.remove_mesh(cube).unwrap();
+        renderer.remove_mesh(quad).unwrap();
+        assert_eq!(renderer.stats(), empty);
+    }
+
+    #[test]
+    fn test_update_mesh() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+        let center = |pixels: Vec<u8>| {
+            let i = (16 * 32 + 16) * 4;
+            [pixels[i], pixels[i + 1], pixels[i + 2]]
+        };
+
+        let material = renderer.add_material(&Material::white().with_unlit(true)).unwrap();
+        let mesh = renderer.add_mesh(&Mesh::<ColorNormalVertex>::new());
+        let instance = renderer.add_instance(mesh, material, Transform::identity()).unwrap();
+        renderer.update_camera(&camera_on_y_axis(-3.0));
+        renderer.render().unwrap();
+        assert_eq!(center(renderer.read_pixels().unwrap()), [3, 3, 3]);
+
+        // the empty mesh grows into a white sphere
+        let white = crate::test_assets::simple_sphere_mesh(0.5, 4, Vector3::new(1.0, 1.0, 1.0));
+        renderer.update_mesh(mesh, &white).unwrap();
+        renderer.render().unwrap();
+        assert_eq!(center(renderer.read_pixels().unwrap()), [255, 255, 255]);
+
+        // recoloring every vertex keeps the instance
+        let red = crate::test_assets::simple_sphere_mesh(0.5, 4, Vector3::new(1.0, 0.0, 0.0));
+        renderer.update_mesh_vertices(mesh, 0, red.vertices()).unwrap();
+        renderer.update_instance(instance, Transform::from_scale(1.5)).unwrap();
+        renderer.render().unwrap();
+        assert_eq!(center(renderer.read_pixels().unwrap()), [255, 0, 0]);
+
+        let vertex_count = white.vertices().len() as u32;
+        assert_eq!(
+            renderer.update_mesh_vertices(mesh, 1, red.vertices()),
+            Err(MeshUpdateError::OutOfRange { end: vertex_count + 1, len: vertex_count }),
+        );
+        assert_eq!(
+            renderer.update_mesh_vertices(mesh, 0, &[ColorVertex::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0))]),
+            Err(MeshUpdateError::VertexLayout),
+        );
+        renderer.update_mesh_indices(mesh, 0, &[0, 0, 0]).unwrap();
+    }
+
+    #[test]
+    fn test_frustum_culling() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+
+        let sphere = renderer.add_mesh(&crate::test_assets::simple_sphere_mesh(0.5, 4, Vector3::new(1.0, 1.0, 1.0)));
+        let material = renderer.default_material();
+        renderer.add_instance(sphere, material, Transform::identity()).unwrap();
+        renderer.add_instance(sphere, material, Transform::from_translation(Vector3::new(0.0, -6.0, 0.0))).unwrap();
+        renderer.add_instance(sphere, material, Transform::from_translation(Vector3::new(10.0, 0.0, 0.0))).unwrap();
+        renderer.update_camera(&camera_on_y_axis(-3.0));
+
+        renderer.render().unwrap();
+        let culled = renderer.read_pixels().unwrap();
+        assert_eq!(renderer.stats().drawn_instances(), 1);
+        assert_eq!(renderer.stats().culled_instances(), 2);
+
+        // culling doesn't change what is seen
+        renderer.set_frustum_culling(false);
+        renderer.render().unwrap();
+        assert_eq!(renderer.stats().drawn_instances(), 3);
+        assert_eq!(renderer.stats().culled_instances(), 0);
+        assert_eq!(renderer.read_pixels().unwrap(), culled);
+    }
+
+    #[test]
+    fn test_culling_with_moving_camera() {
+        let (Some(mut renderer), Some(mut expected)) = (headless(32, 32), headless(32, 32)) else {
+            return;
+        };
+
+        let stride = std::mem::size_of::<instances::InstanceData>() as u64;
+        let at = |x: f32, z: f32| Transform::from_translation(Vector3::new(x, 0.0, z));
+        let sphere_mesh = crate::test_assets::simple_sphere_mesh(0.05, 4, Vector3::new(1.0, 1.0, 1.0));
+        let sphere = renderer.add_mesh(&sphere_mesh);
+        let material = renderer.default_material();
+        let mut grid = Vec::new();
+        for i in 0..100 {
+            let transform = at((i % 10) as f32 * 0.2 - 0.9, (i / 10) as f32 * 0.2 - 0.9);
+            grid.push(renderer.add_instance(sphere, material, transform).unwrap());
+        }
+        let far = renderer.add_instance(sphere, material, at(50.0, 0.0)).unwrap();
+
+        renderer.update_camera(&camera_on_y_axis(-8.0));
+        renderer.render().unwrap();
+        assert_eq!(renderer.stats().uploaded_instance_bytes(), 100 * stride);
+
+        // a moving camera that sees the same instances uploads nothing
+        for y in [-7.9, -7.8, -7.7] {
+            renderer.update_camera(&camera_on_y_axis(y));
+            renderer.render().unwrap();
+            assert_eq!(renderer.stats().drawn_instances(), 100);
+            assert_eq!(renderer.stats().culled_instances(), 1);
+            assert_eq!(renderer.stats().uploaded_instance_bytes(), 0);
+        }
+
+        // a changed instance is written alone, and the buffer is only repacked when an instance
+        // comes into view
+        renderer.update_instance(grid[42], at(0.05, 0.05)).unwrap();
+        renderer.update_camera(&camera_on_y_axis(-7.6));
+        renderer.render().unwrap();
+        assert_eq!(renderer.stats().uploaded_instance_bytes(), stride);
+
+        renderer.update_instance(far, at(0.0, 0.0)).unwrap();
+        renderer.render().unwrap();
+        assert_eq!(renderer.stats().drawn_instances(), 101);
+        assert_eq!(renderer.stats().uploaded_instance_bytes(), 101 * stride);
+
+        // the result matches the same scene built from scratch
+        let sphere = expected.add_mesh(&sphere_mesh);
+        let material = expected.default_material();
+        for i in 0..100 {
+            let transform = match i {
+                42 => at(0.05, 0.05),
+                _ => at((i % 10) as f32 * 0.2 - 0.9, (i / 10) as f32 * 0.2 - 0.9),
+            };
+            expected.add_instance(sphere, material, transform).unwrap();
+        }
+        expected.add_instance(sphere, material, at(0.0, 0.0)).unwrap();
+        expected.update_camera(&camera_on_y_axis(-7.6));
+        expected.render().unwrap();
+        assert_eq!(renderer.read_pixels().unwrap(), expected.read_pixels().unwrap());
+    }
+
+    #[test]
+    fn test_incremental_instances() {
+        let (Some(mut renderer), Some(mut expected)) = (headless(32, 32), headless(32, 32)) else {
+            return;
+        };
+
+        let camera = camera_on_y_axis(-8.0);
+        let at = |x: f32, z: f32| Transform::from_translation(Vector3::new(x, 0.0, z));
+        let sphere_mesh = crate::test_assets::simple_sphere_mesh(0.5, 4, Vector3::new(1.0, 1.0, 1.0));
+
+        // move, deactivate, and remove instances over several frames, so slots are reused and moved
+        renderer.set_frustum_culling(false);
+        renderer.update_camera(&camera);
+        let sphere = renderer.add_mesh(&sphere_mesh);
+        let material = renderer.default_material();
+        let instances: Vec<InstanceHandle> = [-2.0, -1.0, 0.0, 1.0, 2.0].into_iter()
+            .map(|x| renderer.add_instance(sphere, material, at(x, 0.0)).unwrap())
+            .collect();
+        renderer.render().unwrap();
+        renderer.set_instance_active(instances[1], false).unwrap();
+        renderer.update_instance(instances[4], at(2.0, 1.5)).unwrap();
+        renderer.render().unwrap();
+        renderer.remove_instance(instances[0]).unwrap();
+        renderer.set_instance_active(instances[1], true).unwrap();
+        renderer.update_instance(instances[1], at(-1.0, -1.5)).unwrap();
+        renderer.render().unwrap();
+        // nothing changed, so nothing is uploaded and the frame is the same
+        let incremental = renderer.read_pixels().unwrap();
+        renderer.render().unwrap();
+        assert_eq!(renderer.read_pixels().unwrap(), incremental);
+        assert_eq!(renderer.stats().drawn_instances(), 4);
+
+        expected.update_camera(&camera);
+        let sphere = expected.add_mesh(&sphere_mesh);
+        let material = expected.default_material();
+        for transform in [at(-1.0, -1.5), at(0.0, 0.0), at(1.0, 0.0), at(2.0, 1.5)] {
+            expected.add_instance(sphere, material, transform).unwrap();
+        }
+        expected.render().unwrap();
+        assert_eq!(expected.read_pixels().unwrap(), incremental);
+
+        // culling repacks the buffer, and turning it off again restores the slots
+        renderer.set_frustum_culling(true);
+        renderer.render().unwrap();
+        assert_eq!(renderer.read_pixels().unwrap(), incremental);
+        renderer.set_frustum_culling(false);
+        renderer.render().unwrap();
+        assert_eq!(renderer.read_pixels().unwrap(), incremental);
+    }
+
+    #[test]
+    fn test_draw_modes() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+
+        // two meshes sharing a vertex layout, two materials, shadows, and an instance out of view
+        let sphere = renderer.add_mesh(&crate::test_assets::simple_sphere_mesh(0.5, 4, Vector3::new(1.0, 1.0, 1.0)));
+        let cube = renderer.add_mesh(&crate::test_assets::cube_mesh());
+        let white = renderer.default_material();
+        let red = renderer.add_material(&Material::new(cgmath::Vector4::new(1.0, 0.2, 0.2, 1.0))).unwrap();
+        let at = |x: f32, z: f32| Transform::from_translation(Vector3::new(x, 0.0, z));
+        renderer.add_instance(sphere, white, at(-1.5, 0.0)).unwrap();
+        renderer.add_instance(sphere, red, at(0.0, 1.0)).unwrap();
+        renderer.add_instance(sphere, white, at(20.0, 0.0)).unwrap();
+        renderer.add_instance(cube, red, at(1.5, 0.0)).unwrap();
+        renderer.add_instance(cube, white, at(0.0, -1.5)).unwrap();
+        renderer.update_camera(&camera_on_y_axis(-6.0));
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 0.8)
+                .with_shadows(crate::scene::light::ShadowSettings::new(256, 0.005, 20.0)),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.1),
+        );
+
+        renderer.render().unwrap();
+        let direct = renderer.read_pixels().unwrap();
+
+        // every supported mode draws the same frame, also after a mesh changes
+        assert_eq!(renderer.supported_draw_modes()[0], DrawMode::Direct);
+        for draw_mode in renderer.supported_draw_modes().clone() {
+            renderer.set_draw_mode(draw_mode).unwrap();
+            renderer.render().unwrap();
+            assert_eq!(renderer.read_pixels().unwrap(), direct, "{:?}", draw_mode);
+
+            renderer.update_mesh(cube, &crate::test_assets::simple_sphere_mesh(0.5, 4, Vector3::new(1.0, 1.0, 1.0))).unwrap();
+            renderer.render().unwrap();
+            let changed = renderer.read_pixels().unwrap();
+            assert_ne!(changed, direct, "{:?}", draw_mode);
+            renderer.update_mesh(cube, &crate::test_assets::cube_mesh()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_lod_meshes() {
+        let (Some(mut renderer), Some(mut expected)) = (headless(64, 64), headless(64, 64)) else {
+            return;
+        };
+
+        let levels = [crate::test_assets::simple_sphere_mesh(0.5, 8, Vector3::new(1.0, 1.0, 1.0)), crate::test_assets::cube_mesh()];
+        let near = Transform::from_translation(Vector3::new(-0.5, 0.0, 0.0));
+        let far = Transform::from_translation(Vector3::new(0.5, 14.0, 0.0));
+
+        // the near instance is drawn with the sphere and the far one with the cube
+        assert_eq!(renderer.add_mesh_with_lods::<ColorNormalVertex>(&[], LodSettings::distance(Vec::new())).unwrap_err(), LodError::NoLevels);
+        assert_eq!(
+            renderer.add_mesh_with_lods(&levels, LodSettings::distance(vec![10.0, 20.0])).unwrap_err(),
+            LodError::ThresholdCount { levels: 2, thresholds: 2 },
+        );
+        assert_eq!(renderer.stats().meshes(), 0);
+
+        let lods = renderer.add_mesh_with_lods(&levels, LodSettings::distance(vec![10.0]).with_hysteresis(0.1)).unwrap();
+        let material = renderer.default_material();
+        renderer.add_instance(lods, material, near).unwrap();
+        renderer.add_instance(lods, material, far).unwrap();
+        renderer.update_camera(&camera_on_y_axis(-6.0));
+
+        let sphere = expected.add_mesh(&levels[0]);
+        let cube = expected.add_mesh(&levels[1]);
+        let material = expected.default_material();
+        expected.add_instance(sphere, material, near).unwrap();
+        expected.add_instance(cube, material, far).unwrap();
+        expected.update_camera(&camera_on_y_axis(-6.0));
+        expected.render().unwrap();
+        let pixels = expected.read_pixels().unwrap();
+
+        for draw_mode in renderer.supported_draw_modes().clone() {
+            renderer.set_draw_mode(draw_mode).unwrap();
+            renderer.render().unwrap();
+            assert_eq!(renderer.read_pixels().unwrap(), pixels, "{:?}", draw_mode);
+        }
+
+        // moving the camera back pushes the near instance past the threshold, but not past the
+        // hysteresis, so nothing changes
+        renderer.update_camera(&camera_on_y_axis(-10.5));
+        expected.update_camera(&camera_on_y_axis(-10.5));
+        renderer.render().unwrap();
+        expected.render().unwrap();
+        assert_eq!(renderer.read_pixels().unwrap(), expected.read_pixels().unwrap());
+
+        // the levels can only be replaced together
+        assert_eq!(renderer.update_mesh(lods, &levels[0]), Err(MeshUpdateError::LevelsOfDetail));
+        assert_eq!(renderer.update_mesh_vertices(lods, 0, &levels[0].vertices()[..1]), Err(MeshUpdateError::LevelsOfDetail));
+        assert_eq!(renderer.update_mesh_indices(lods, 0, &[0, 0, 0]), Err(MeshUpdateError::LevelsOfDetail));
+        assert_eq!(
+            renderer.update_mesh_lods(lods, &levels, LodSettings::distance(Vec::new())),
+            Err(MeshUpdateError::Lods(LodError::ThresholdCount { levels: 2, thresholds: 0 })),
+        );
+
+        // swapping the levels swaps what each instance is drawn with, and they choose their levels
+        // again without the hysteresis of the old settings
+        renderer.update_camera(&camera_on_y_axis(-6.0));
+        expected.update_camera(&camera_on_y_axis(-6.0));
+        renderer.render().unwrap();
+        let swapped = [crate::test_assets::cube_mesh(), crate::test_assets::simple_sphere_mesh(0.5, 8, Vector3::new(1.0, 1.0, 1.0))];
+        renderer.update_mesh_lods(lods, &swapped, LodSettings::distance(vec![10.0])).unwrap();
+        expected.update_mesh(sphere, &levels[1]).unwrap();
+        expected.update_mesh(cube, &levels[0]).unwrap();
+        renderer.render().unwrap();
+        expected.render().unwrap();
+        assert_eq!(renderer.read_pixels().unwrap(), expected.read_pixels().unwrap());
+
+        // a single level leaves a plain mesh, which can be updated as usual
+        renderer.update_mesh_lods(lods, &levels[..1], LodSettings::distance(Vec::new())).unwrap();
+        renderer.update_mesh(lods, &levels[0]).unwrap();
+        expected.update_mesh(sphere, &levels[0]).unwrap();
+        renderer.render().unwrap();
+        expected.render().unwrap();
+        assert_eq!(renderer.read_pixels().unwrap(), expected.read_pixels().unwrap());
+    }
+
+    #[test]
+    fn test_transparent_instances() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        // quads facing the camera, one with half transparent vertices
+        let quad = |alpha: f32| {
+            let normal = -Vector3::unit_y();
+            let vertex = |x: f32, z: f32| ColorNormalVertex::new_white(Vector3::new(x, 0.0, z), normal).with_alpha(alpha);
+            let mut mesh = Mesh::new();
+            mesh.add_quad_facing(vertex(-1.0, -1.0), vertex(1.0, -1.0), vertex(1.0, 1.0), vertex(-1.0, 1.0), normal);
+            mesh
+        };
+        let opaque_quad = renderer.add_mesh(&quad(1.0));
+        let translucent_quad = renderer.add_mesh(&quad(0.5));
+
+        let material = |color: Vector4<f32>, alpha_mode: AlphaMode| Material::new(color).with_unlit(true).with_alpha_mode(alpha_mode);
+        let red = renderer.add_material(&material(Vector4::new(1.0, 0.0, 0.0, 1.0), AlphaMode::Opaque)).unwrap();
+        let green = renderer.add_material(&material(Vector4::new(0.0, 1.0, 0.0, 0.5), AlphaMode::Blend)).unwrap();
+        let blue = renderer.add_material(&material(Vector4::new(0.0, 0.0, 1.0, 1.0), AlphaMode::Blend)).unwrap();
+
+        // the nearest is added first, so only sorting draws them in the right order
+        let at = |y: f32| Transform::from_translation(Vector3::new(0.0, y, 0.0));
+        renderer.add_instance(opaque_quad, green, at(-2.0)).unwrap();
+        renderer.add_instance(translucent_quad, blue, at(0.0)).unwrap();
+        renderer.add_instance(opaque_quad, red, at(2.0)).unwrap();
+        renderer.update_camera(&camera_on_y_axis(-6.0));
+
+        let center = |pixels: Vec<u8>| {
+            let i = 4 * (32 * 64 + 32);
+            [pixels[i], pixels[i + 1], pixels[i + 2]]
+        };
+        let assert_near = |actual: [u8; 3], expected: [f32; 3]| {
+            for (actual, expected) in actual.iter().zip(expected) {
+                assert!((*actual as f32 - expected * 255.0).abs() <= 2.0, "{:?} != {:?}", actual, expected);
+            }
+        };
+
+        // green over blue over red, each blended by half
+        for draw_mode in renderer.supported_draw_modes().clone() {
+            renderer.set_draw_mode(draw_mode).unwrap();
+            renderer.render().unwrap();
+            assert_near(center(renderer.read_pixels().unwrap()), [0.25, 0.5, 0.25]);
+        }
+
+        // an opaque material ignores alpha and hides the red quad
+        renderer.update_material(blue, &material(Vector4::new(0.0, 0.0, 1.0, 1.0), AlphaMode::Opaque)).unwrap();
+        renderer.render().unwrap();
+        assert_near(center(renderer.read_pixels().unwrap()), [0.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_line_sets() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        // a white quad in front of the left half of a red line through the center
+        let normal = -Vector3::unit_y();
+        let vertex = |x: f32, z: f32| ColorNormalVertex::new_white(Vector3::new(x, -2.0, z), normal);
+        let mut quad = Mesh::new();
+        quad.add_quad_facing(vertex(-3.0, -1.0), vertex(0.0, -1.0), vertex(0.0, 1.0), vertex(-3.0, 1.0), normal);
+        let quad = renderer.add_mesh(&quad);
+        let white = renderer.add_material(&Material::white().with_unlit(true)).unwrap();
+        renderer.add_instance(quad, white, Transform::from_translation(Vector3::new(0.0, 0.0, 0.0))).unwrap();
+        renderer.update_camera(&camera_on_y_axis(-6.0));
+
+        let red = Vector3::new(1.0, 0.0, 0.0);
+        let mut lines = LineSet::new().with_thickness(9.0);
+        lines.add_line(ColorVertex::new(Vector3::new(-10.0, 0.0, 0.0), red), ColorVertex::new(Vector3::new(10.0, 0.0, 0.0), red));
+        let handle = renderer.add_line_set(&lines);
+
+        let render = |renderer: &mut Renderer| {
+            renderer.render().unwrap();
+            let pixels = renderer.read_pixels().unwrap();
+            move |row: usize, column: usize| {
+                let i = 4 * (row * 64 + column);
+                [pixels[i], pixels[i + 1], pixels[i + 2]]
+            }
+        };
+
+        // the line is about nine pixels thick, and hidden by the quad; it stays across frames
+        for _ in 0..2 {
+            let pixel = render(&mut renderer);
+            assert_eq!(pixel(29, 48), [255, 0, 0]);
+            assert_eq!(pixel(34, 48), [255, 0, 0]);
+            assert_ne!(pixel(24, 48), [255, 0, 0]);
+            assert_ne!(pixel(40, 48), [255, 0, 0]);
+            assert_eq!(pixel(32, 16), [255, 255, 255]);
+        }
+
+        // on top, the line is drawn over the quad
+        renderer.update_line_set(handle, &lines.clone().with_on_top(true)).unwrap();
+        let pixel = render(&mut renderer);
+        assert_eq!(pixel(32, 16), [255, 0, 0]);
+
+        renderer.remove_line_set(handle).unwrap();
+        let pixel = render(&mut renderer);
+        assert_ne!(pixel(32, 48), [255, 0, 0]);
+        assert_eq!(pixel(32, 16), [255, 255, 255]);
+        assert_eq!(renderer.remove_line_set(handle), Err(HandleError::Stale(HandleKind::LineSet)));
+    }
+
+    #[test]
+    fn test_shader_hot_reload() {
+        let Some(mut renderer) = headless(8, 4) else {
+            return;
+        };
+
+        let directory = std::env::temp_dir().join(format!("shaderunner_hot_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("unlit_shader.wgsl");
+        let write = |source: &str, seconds: u64| {
+            std::fs::write(&path, source).unwrap();
+            // set the modification time explicitly, since writes in quick succession can share one
+            let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        };
+
+        // the built-in unlit shader is read from the directory; the other built-ins are missing
+        write(include_str!("unlit_shader.wgsl"), 1);
+        renderer.enable_shader_hot_reload(&directory);
+        let errors = renderer.reload_shaders();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|error| matches!(error, ShaderReloadError::Io { .. })));
+
+        // unchanged and missing files are not reported again
+        assert!(renderer.reload_shaders().is_empty());
+
+        write("this is not wgsl", 2);
+        let errors = renderer.reload_shaders();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], ShaderReloadError::Validation { path: error_path, .. } if *error_path == path));
+
+        // the previous pipeline is kept and still draws
+        renderer.render().unwrap();
+
+        write(include_str!("unlit_shader.wgsl"), 3);
+        assert!(renderer.reload_shaders().is_empty());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_textures() {
+        let Some(mut renderer) = headless(8, 4) else {
+            return;
+        };
+
+        assert!(matches!(
+            renderer.add_texture(2, 2, &[255; 12]),
+            Err(TextureError::DataLength { expected: 16, actual: 12 }),
+        ));
+        assert!(matches!(renderer.add_texture(0, 2, &[]), Err(TextureError::Empty)));
+        assert!(matches!(renderer.add_texture(u32::MAX, 1, &[]), Err(TextureError::TooLarge { .. })));
+        assert!(matches!(renderer.add_texture_from_image(&[1, 2, 3]), Err(TextureError::Image(_))));
+
+        // the renderer is unaffected
+        renderer.add_texture(2, 2, &[255; 16]).unwrap();
+        renderer.render().unwrap();
+    }
+
+    #[test]
+    fn test_invalid_pipeline() {
+        let Some(mut renderer) = headless(8, 4) else {
+            return;
+        };
+
+        let error = renderer.add_pipeline(PipelineBuilder::new::<ColorVertex>("broken", "this is not wgsl")).unwrap_err();
+        assert_eq!(error.label(), "broken");
+
+        // the renderer is unaffected and valid pipelines can still be added
+        renderer.render().unwrap();
+        renderer.add_pipeline(PipelineBuilder::new::<ColorVertex>("unlit", include_str!("unlit_shader.wgsl"))).unwrap();
+        renderer.render().unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use cgmath::Vector3;
+
+/// Options for creating a renderer with `Renderer::with_config()`.  The defaults match
+/// `Renderer::new()`.
+#[derive(Debug, Clone)]
+pub struct RendererConfig {
+    power_preference: wgpu::PowerPreference,
+    backends: wgpu::Backends,
+    force_fallback_adapter: bool,
+    required_features: wgpu::Features,
+    required_limits: Option<wgpu::Limits>,
+    present_mode: Option<wgpu::PresentMode>,
+    depth_format: wgpu::TextureFormat,
+    clear_color: Vector3<f32>,
+}
+
+impl RendererConfig {
+    pub fn power_preference(&self) -> wgpu::PowerPreference {
+        self.power_preference
+    }
+
+    pub fn backends(&self) -> wgpu::Backends {
+        self.backends
+    }
+
+    pub fn force_fallback_adapter(&self) -> bool {
+        self.force_fallback_adapter
+    }
+
+    pub fn required_features(&self) -> wgpu::Features {
+        self.required_features
+    }
+
+    /// `None` requests the default limits, or the adapter's own limits for headless renderers.
+    pub fn required_limits(&self) -> Option<&wgpu::Limits> {
+        self.required_limits.as_ref()
+    }
+
+    /// `None` uses the first mode the surface supports.
+    pub fn present_mode(&self) -> Option<wgpu::PresentMode> {
+        self.present_mode
+    }
+
+    pub fn depth_format(&self) -> wgpu::TextureFormat {
+        self.depth_format
+    }
+
+    /// The linear hdr color the scene is cleared to each frame.
+    pub fn clear_color(&self) -> Vector3<f32> {
+        self.clear_color
+    }
+
+    pub fn new() -> RendererConfig {
+        RendererConfig {
+            power_preference: wgpu::PowerPreference::default(),
+            backends: wgpu::Backends::all(),
+            force_fallback_adapter: false,
+            required_features: wgpu::Features::empty(),
+            required_limits: None,
+            present_mode: None,
+            depth_format: wgpu::TextureFormat::Depth32Float,
+            clear_color: Vector3::new(0.01, 0.01, 0.01),
+        }
+    }
+
+    pub fn with_power_preference(mut self, power_preference: wgpu::PowerPreference) -> RendererConfig {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// Restricts which graphics apis adapters are chosen from.
+    pub fn with_backends(mut self, backends: wgpu::Backends) -> RendererConfig {
+        self.backends = backends;
+        self
+    }
+
+    /// Chooses a software adapter, e.g. on machines without a gpu.
+    pub fn with_fallback_adapter(mut self, force_fallback_adapter: bool) -> RendererConfig {
+        self.force_fallback_adapter = force_fallback_adapter;
+        self
+    }
+
+    /// Features beyond those the renderer enables itself.  Creation fails if the adapter lacks
+    /// them.
+    pub fn with_features(mut self, required_features: wgpu::Features) -> RendererConfig {
+        self.required_features = required_features;
+        self
+    }
+
+    pub fn with_limits(mut self, required_limits: wgpu::Limits) -> RendererConfig {
+        self.required_limits = Some(required_limits);
+        self
+    }
+
+    /// Modes the surface doesn't support fall back to the first one it does, with a warning.
+    pub fn with_present_mode(mut self, present_mode: wgpu::PresentMode) -> RendererConfig {
+        self.present_mode = Some(present_mode);
+        self
+    }
+
+    /// Shorthand for `AutoVsync` or `AutoNoVsync`, which every surface supports.
+    pub fn with_vsync(self, vsync: bool) -> RendererConfig {
+        self.with_present_mode(present_mode_for_vsync(vsync))
+    }
+
+    /// One of `Depth16Unorm`, `Depth24Plus`, or `Depth32Float`; formats with stencil aren't
+    /// supported.
+    pub fn with_depth_format(mut self, depth_format: wgpu::TextureFormat) -> RendererConfig {
+        self.depth_format = depth_format;
+        self
+    }
+
+    pub fn with_clear_color(mut self, clear_color: Vector3<f32>) -> RendererConfig {
+        self.clear_color = clear_color;
+        self
+    }
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn present_mode_for_vsync(vsync: bool) -> wgpu::PresentMode {
+    match vsync {
+        true => wgpu::PresentMode::AutoVsync,
+        false => wgpu::PresentMode::AutoNoVsync,
+    }
+}
+
+/// `requested` if the surface supports it, else the first supported mode.
+pub fn choose_present_mode(requested: Option<wgpu::PresentMode>, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    match requested {
+        None => supported[0],
+        Some(mode @ (wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync)) => mode,
+        Some(mode) if supported.contains(&mode) => mode,
+        Some(mode) => {
+            log::warn!("present mode {:?} is not supported; using {:?}", mode, supported[0]);
+            supported[0]
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_present_mode() {
+        let supported = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox];
+
+        assert_eq!(choose_present_mode(None, &supported), wgpu::PresentMode::Fifo);
+        assert_eq!(choose_present_mode(Some(wgpu::PresentMode::Mailbox), &supported), wgpu::PresentMode::Mailbox);
+        assert_eq!(choose_present_mode(Some(wgpu::PresentMode::Immediate), &supported), wgpu::PresentMode::Fifo);
+        assert_eq!(choose_present_mode(Some(wgpu::PresentMode::AutoNoVsync), &supported), wgpu::PresentMode::AutoNoVsync);
+    }
+}
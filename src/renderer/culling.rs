@@ -0,0 +1,176 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Vector3, Vector4};
+
+/// An axis-aligned box around a mesh's vertices in model space.  The bounding sphere is the one
+/// around the box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bounds {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+impl Bounds {
+    pub fn min(&self) -> Vector3<f32> {
+        self.min
+    }
+
+    pub fn max(&self) -> Vector3<f32> {
+        self.max
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).magnitude() / 2.0
+    }
+
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Bounds {
+        Bounds {
+            min,
+            max,
+        }
+    }
+
+    /// The bounds of the positions, or `None` if there are none.
+    pub fn from_positions(positions: impl IntoIterator<Item = Vector3<f32>>) -> Option<Bounds> {
+        positions.into_iter()
+            .map(|position| Bounds::new(position, position))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds::new(
+            Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        )
+    }
+
+    /// The axis-aligned box around these bounds after transforming them by `model`.
+    pub fn transform(&self, model: &Matrix4<f32>) -> Bounds {
+        // each output axis is spanned independently by the matrix's columns (Arvo's method)
+        let center = (model * self.center().extend(1.0)).truncate();
+        let half = (self.max - self.min) / 2.0;
+        let extent = Vector3::new(
+            model.x.x.abs() * half.x + model.y.x.abs() * half.y + model.z.x.abs() * half.z,
+            model.x.y.abs() * half.x + model.y.y.abs() * half.y + model.z.y.abs() * half.z,
+            model.x.z.abs() * half.x + model.y.z.abs() * half.y + model.z.z.abs() * half.z,
+        );
+        Bounds::new(center - extent, center + extent)
+    }
+}
+
+/// The six planes of a view-projection matrix's clip volume, facing inwards.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn planes(&self) -> &[Vector4<f32>; 6] {
+        &self.planes
+    }
+
+    /// Extracts the planes from the rows of `view_projection` (Gribb and Hartmann).  The near
+    /// plane is taken at opengl's -1 clip depth, which is conservative for matrices that map
+    /// depth onto wgpu's 0 to 1.
+    pub fn from_view_projection(view_projection: Matrix4<f32>) -> Frustum {
+        let row = |i| view_projection.row(i);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(3) + row(2),
+            row(3) - row(2),
+        ].map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > 0.0 { plane / length } else { plane }
+        });
+
+        Frustum {
+            planes,
+        }
+    }
+
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+
+    pub fn intersects_box(&self, bounds: &Bounds) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { bounds.max.x } else { bounds.min.x },
+                if plane.y >= 0.0 { bounds.max.y } else { bounds.min.y },
+                if plane.z >= 0.0 { bounds.max.z } else { bounds.min.z },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    /// Whether model-space `bounds` transformed by `model` may be inside the frustum.  The
+    /// bounding sphere rejects most instances cheaply; the rest are tested with their box.
+    pub fn intersects(&self, bounds: &Bounds, model: &Matrix4<f32>) -> bool {
+        let center = (model * bounds.center().extend(1.0)).truncate();
+        self.intersects_sphere(center, bounds.radius() * max_scale(model))
+            && self.intersects_box(&bounds.transform(model))
+    }
+}
+
+/// The largest factor `model` scales lengths by, for scaling bounding spheres.
+pub fn max_scale(model: &Matrix4<f32>) -> f32 {
+    model.x.truncate().magnitude()
+        .max(model.y.truncate().magnitude())
+        .max(model.z.truncate().magnitude())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::camera::Camera;
+
+    fn frustum() -> Frustum {
+        let camera = Camera::new(
+            Vector3::new(0.0, -5.0, 0.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::unit_z(),
+            1.0,
+            std::f32::consts::TAU / 8.0,
+            0.1,
+            100.0,
+        );
+        Frustum::from_view_projection(camera.build_view_projection_matrix())
+    }
+
+    #[test]
+    fn test_bounds() {
+        let bounds = Bounds::from_positions([
+            Vector3::new(1.0, -1.0, 0.0),
+            Vector3::new(-1.0, 2.0, 0.5),
+        ]).unwrap();
+        assert_eq!(bounds, Bounds::new(Vector3::new(-1.0, -1.0, 0.0), Vector3::new(1.0, 2.0, 0.5)));
+        assert!(Bounds::from_positions([]).is_none());
+
+        let moved = bounds.transform(&Matrix4::from_translation(Vector3::new(1.0, 0.0, 0.0)));
+        assert_eq!(moved, Bounds::new(Vector3::new(0.0, -1.0, 0.0), Vector3::new(2.0, 2.0, 0.5)));
+    }
+
+    #[test]
+    fn test_frustum_culling() {
+        let frustum = frustum();
+        let unit = Bounds::new(Vector3::new(-0.5, -0.5, -0.5), Vector3::new(0.5, 0.5, 0.5));
+        let at = |x, y, z| Matrix4::from_translation(Vector3::new(x, y, z));
+
+        assert!(frustum.intersects(&unit, &at(0.0, 0.0, 0.0)));
+        // behind the camera, beyond the far plane, and off to the side
+        assert!(!frustum.intersects(&unit, &at(0.0, -10.0, 0.0)));
+        assert!(!frustum.intersects(&unit, &at(0.0, 200.0, 0.0)));
+        assert!(!frustum.intersects(&unit, &at(10.0, 0.0, 0.0)));
+        // straddling the edge of the view
+        assert!(frustum.intersects(&unit, &at(2.4, 0.0, 0.0)));
+        // scaling up brings it back into view
+        assert!(frustum.intersects(&unit, &(at(10.0, 0.0, 0.0) * Matrix4::from_scale(20.0))));
+    }
+}
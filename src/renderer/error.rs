@@ -0,0 +1,179 @@
+use std::fmt;
+
+use super::{gpu_resources::PipelineHandle, lod::LodError};
+
+/// An error from `Renderer::render()`.  Routine surface errors, such as an outdated surface after
+/// a resize, are handled by reconfiguring the surface or skipping the frame and aren't reported.
+#[derive(Debug, Clone)]
+pub enum RenderError {
+    /// There wasn't enough memory to acquire the next frame.
+    OutOfMemory,
+    /// The device was lost and a new one couldn't be created.  Rendering tries again next frame.
+    DeviceLost(String),
+    /// The device was lost and recreated, but these pipelines had bind groups of their own, made
+    /// on the old device, and were removed.  Their handles are stale; add them again with new bind
+    /// groups.  The frame is skipped.
+    DeviceRecovered { removed_pipelines: Vec<PipelineHandle> },
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::OutOfMemory => write!(f, "out of memory acquiring the next frame"),
+            RenderError::DeviceLost(message) => write!(f, "device lost and not recovered: {}", message),
+            RenderError::DeviceRecovered { removed_pipelines } => write!(
+                f,
+                "device lost and recovered, but {} pipelines with their own bind groups were removed",
+                removed_pipelines.len(),
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+
+/// The kind of resource a handle refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HandleKind {
+    Mesh,
+    Instance,
+    Material,
+    Texture,
+    Pipeline,
+    PointLight,
+    SpotLight,
+    PostProcess,
+    LineSet,
+}
+
+impl fmt::Display for HandleKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HandleKind::Mesh => "mesh",
+            HandleKind::Instance => "instance",
+            HandleKind::Material => "material",
+            HandleKind::Texture => "texture",
+            HandleKind::Pipeline => "pipeline",
+            HandleKind::PointLight => "point light",
+            HandleKind::SpotLight => "spot light",
+            HandleKind::PostProcess => "post-process",
+            HandleKind::LineSet => "line set",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An error from a `Renderer` method given a handle that doesn't refer to a live resource.  The
+/// call has no effect.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HandleError {
+    /// The resource was removed, or the handle came from another renderer.
+    Stale(HandleKind),
+    /// The instance's mesh was removed, taking its instances with it.
+    MeshRemoved,
+    /// The instance was looked up among the instances of a different mesh than its own.
+    WrongMesh,
+}
+
+impl fmt::Display for HandleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandleError::Stale(kind) => write!(f, "stale {} handle", kind),
+            HandleError::MeshRemoved => write!(f, "the instance's mesh was removed"),
+            HandleError::WrongMesh => write!(f, "the instance belongs to a different mesh"),
+        }
+    }
+}
+
+impl std::error::Error for HandleError {}
+
+
+/// An error from updating part of a mesh.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MeshUpdateError {
+    Handle(HandleError),
+    /// The range ends at `end`, past the mesh's `len` vertices or indices.
+    OutOfRange { end: u32, len: u32 },
+    /// The vertices are of a different type than the mesh's.
+    VertexLayout,
+    /// The mesh has levels of detail, which are only replaced together, with
+    /// `Renderer::update_mesh_lods()`.
+    LevelsOfDetail,
+    /// The new levels of detail don't fit their `LodSettings`.
+    Lods(LodError),
+}
+
+impl fmt::Display for MeshUpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshUpdateError::Handle(error) => write!(f, "{}", error),
+            MeshUpdateError::OutOfRange { end, len } => write!(f, "range ends at {} but the mesh has {}", end, len),
+            MeshUpdateError::VertexLayout => write!(f, "vertex type differs from the mesh's"),
+            MeshUpdateError::LevelsOfDetail => write!(f, "the mesh has levels of detail, which must be updated together"),
+            MeshUpdateError::Lods(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for MeshUpdateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MeshUpdateError::Handle(error) => Some(error),
+            MeshUpdateError::Lods(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<HandleError> for MeshUpdateError {
+    fn from(error: HandleError) -> Self {
+        MeshUpdateError::Handle(error)
+    }
+}
+
+impl From<LodError> for MeshUpdateError {
+    fn from(error: LodError) -> Self {
+        MeshUpdateError::Lods(error)
+    }
+}
+
+
+/// An error from adding a texture.
+#[derive(Debug)]
+pub enum TextureError {
+    /// The width or height is zero.
+    Empty,
+    /// The width or height is larger than the device allows.
+    TooLarge { width: u32, height: u32, max: u32 },
+    /// The data isn't `4 * width * height` bytes long.
+    DataLength { expected: usize, actual: usize },
+    /// The image couldn't be decoded.
+    Image(image::ImageError),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Empty => write!(f, "texture has no pixels"),
+            TextureError::TooLarge { width, height, max } => write!(f, "{}x{} texture exceeds the maximum size of {}", width, height, max),
+            TextureError::DataLength { expected, actual } => write!(f, "texture needs {} bytes but was given {}", expected, actual),
+            TextureError::Image(error) => write!(f, "error decoding texture: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Image(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(error: image::ImageError) -> Self {
+        TextureError::Image(error)
+    }
+}
@@ -1,18 +1,26 @@
-use std::mem;
+use std::{collections::{BTreeMap, HashMap}, mem};
+
+use wgpu::util::DeviceExt;
 
 use cgmath::{Point3, EuclideanSpace, InnerSpace};
 use generational_arena::{Arena, Index};
 
-use crate::{mesh::Mesh, color_normal_vertex::ColorNormalVertex, scene::{Transform, camera::{Camera, create_camera_bind_group_and_layout}, light::{DirectionalLight, AmbientLight}}};
+use crate::{mesh::{Mesh, Vertex}, scene::{Transform, camera::{Camera, create_camera_bind_group, create_camera_bind_group_layout}, light::{DirectionalLight, AmbientLight, PointLight, ShadowSettings, SpotLight}, material::{Material, MaterialData, create_material_bind_group, create_material_bind_group_layout}}};
 
-use super::{instances::{InstanceListResource, InstanceHandle}, texture::create_depth_texture};
+use super::{culling::{Bounds, Frustum}, error::{HandleError, HandleKind, MeshUpdateError, TextureError}, handle_report::{DanglingHandle, HandleReport}, stats::RendererStats, instances::{InstanceListResource, InstanceHandle}, lod::{LodError, LodSettings, LodView}, local_lights::{LocalLightsResource, PointLightHandle, SpotLightHandle}, pipeline::PipelineResource, resizable_buffer::ResizableBuffer, shadows::{ShadowMap, fit_light_view_projection}, texture::{create_depth_texture, create_rgba8_texture}};
 
 pub struct CameraResource {
+    camera: Option<Camera>,
     camera_data: CameraData,
     camera_buffer: wgpu::Buffer,
 }
 
 impl CameraResource {
+    /// The camera from the last `update()`, kept to fit the shadow map to its frustum.
+    pub fn camera(&self) -> Option<&Camera> {
+        self.camera.as_ref()
+    }
+
     pub fn camera_buffer(&self) -> &wgpu::Buffer {
         &self.camera_buffer
     }
@@ -37,12 +45,23 @@ impl CameraResource {
         );
 
         Self {
+            camera: None,
             camera_buffer,
             camera_data,
         }
     }
 
+    /// Recreates the buffer on a new device, with the data from the last `update()`.
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        self.camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("camera buffer"),
+            contents: bytemuck::cast_slice(&[self.camera_data]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+    }
+
     pub fn update(&mut self, camera: &Camera, queue: &wgpu::Queue) {
+        self.camera = Some(*camera);
         self.camera_data.position = Point3::from_vec(camera.eye()).to_homogeneous().into();
         self.camera_data.view_proj = camera.build_view_projection_matrix().into();
         queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_data]));
@@ -60,6 +79,8 @@ pub struct CameraData {
 pub struct LightResource {
     light_data: LightData,
     light_buffer: wgpu::Buffer,
+    direction: cgmath::Vector3<f32>,
+    shadows: Option<ShadowSettings>,
 }
 
 impl LightResource {
@@ -67,6 +88,15 @@ impl LightResource {
         &self.light_buffer
     }
 
+    pub fn shadows(&self) -> Option<ShadowSettings> {
+        self.shadows
+    }
+
+    /// The shadow map's view-projection matrix, while shadows are enabled.
+    pub fn shadow_view_projection(&self) -> Option<cgmath::Matrix4<f32>> {
+        (self.light_data.shadows_enabled != 0).then(|| self.light_data.view_proj.into())
+    }
+
     pub fn new(device: &wgpu::Device) -> LightResource {
         let light_data = LightData {
             direction: [0.0, 0.0, 0.0],
@@ -74,9 +104,15 @@ impl LightResource {
 
             ambient_color: [0.0, 0.0, 0.0],
 
+            view_proj: [[0.0; 4]; 4],
+            shadow_bias: 0.0,
+            shadows_enabled: 0,
+            shadow_texel_size: 0.0,
+
             _padding0: 0.0,
             _padding1: 0.0,
             _padding2: 0.0,
+            _padding3: 0.0,
         };
 
         let light_buffer = device.create_buffer( &wgpu::BufferDescriptor {
@@ -89,15 +125,51 @@ impl LightResource {
         LightResource {
             light_data,
             light_buffer,
+            direction: cgmath::Vector3::new(0.0, 0.0, -1.0),
+            shadows: None,
         }
     }
 
+    /// Recreates the buffer on a new device, with the data from the last update.
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        self.light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("directional light buffer"),
+            contents: bytemuck::cast_slice(&[self.light_data]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+    }
+
     pub fn update(&mut self, directional_light: &DirectionalLight, ambient_light: &AmbientLight, queue: &wgpu::Queue) {
         self.light_data.direction = directional_light.direction().normalize().into();
         self.light_data.color = (directional_light.intensity() * directional_light.color()).into();
 
         self.light_data.ambient_color = (ambient_light.intensity() * ambient_light.color()).into();
 
+        self.direction = directional_light.direction();
+        self.shadows = directional_light.shadows();
+        if let Some(shadows) = self.shadows {
+            self.light_data.shadow_bias = shadows.bias();
+            self.light_data.shadow_texel_size = 1.0 / shadows.resolution() as f32;
+        }
+
+        queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[self.light_data]),
+        );
+    }
+
+    /// Fits the shadow map to the camera.  Shadows stay disabled in the shaders until there is a
+    /// camera to fit them to.
+    pub fn update_shadows(&mut self, camera: Option<&Camera>, queue: &wgpu::Queue) {
+        match (self.shadows, camera) {
+            (Some(shadows), Some(camera)) => {
+                self.light_data.view_proj = fit_light_view_projection(camera, self.direction, &shadows).into();
+                self.light_data.shadows_enabled = 1;
+            },
+            _ => self.light_data.shadows_enabled = 0,
+        }
+
         queue.write_buffer(
             &self.light_buffer,
             0,
@@ -115,81 +187,463 @@ pub struct LightData {
     _padding1: f32,
     ambient_color: [f32; 3],
     _padding2: f32,
+    view_proj: [[f32; 4]; 4],
+    shadow_bias: f32,
+    shadows_enabled: u32,
+    shadow_texel_size: f32,
+    _padding3: f32,
 }
 
+/// A mesh's buffers for each of its levels of detail, along with cpu-side copies of its vertices
+/// and indices for recreating them after device loss.  The buffers grow as the mesh is updated.
+/// Accessors without a level refer to level 0, the finest.
 pub struct MeshResource {
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    index_count: u32,
+    levels: Vec<MeshGeometry>,
+    lods: Option<LodSettings>,
+    vertex_layout: wgpu::VertexBufferLayout<'static>,
+    pipeline: Option<PipelineHandle>,
+    revision: u32,
 }
 
 impl MeshResource {
     pub fn vertex_buffer(&self) -> &wgpu::Buffer {
-        &self.vertex_buffer
+        self.levels[0].vertex_buffer()
     }
 
     pub fn index_buffer(&self) -> &wgpu::Buffer {
-        &self.index_buffer
+        self.levels[0].index_buffer()
     }
 
     pub fn index_count(&self) -> u32 {
-        self.index_count
+        self.levels[0].index_count()
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.levels[0].vertex_count()
+    }
+
+    pub fn vertex_layout(&self) -> &wgpu::VertexBufferLayout<'static> {
+        &self.vertex_layout
+    }
+
+    /// The bounds of the vertices in model space, or `None` for a mesh without vertices.  Partial
+    /// updates only grow them.
+    pub fn bounds(&self) -> Option<&Bounds> {
+        self.levels[0].bounds()
+    }
+
+    /// The level of detail `level`, from 0, the finest, to `level_count() - 1`.
+    pub fn level(&self, level: usize) -> &MeshGeometry {
+        &self.levels[level]
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// How instances choose a level, or `None` for a mesh with one level.
+    pub fn lods(&self) -> Option<&LodSettings> {
+        self.lods.as_ref()
+    }
+
+    /// Counts the updates to the vertices and indices, so copies of the buffers can tell when
+    /// they are out of date.
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// The pipeline assigned to the mesh, or `None` to use the default pipeline for its vertex
+    /// layout.  Materials can override either.
+    pub fn pipeline(&self) -> Option<PipelineHandle> {
+        self.pipeline
     }
 
-    pub fn new(vertex_buffer: wgpu::Buffer, index_buffer: wgpu::Buffer, index_count: u32) -> Self {
+    pub fn new<T: Vertex>(mesh: &Mesh<T>, pipeline: Option<PipelineHandle>, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
         MeshResource {
-            vertex_buffer,
-            index_buffer,
-            index_count,
+            levels: vec![MeshGeometry::new(mesh, device, queue)],
+            lods: None,
+            vertex_layout: T::vertex_buffer_layout(),
+            pipeline,
+            revision: 0,
+        }
+    }
+
+    /// A mesh with a level of detail for each of `levels`, chosen as `lods` describes; with a
+    /// single level, `lods` is ignored.  Fails if `lods` doesn't fit the levels.
+    pub fn with_lods<T: Vertex>(levels: &[Mesh<T>], lods: LodSettings, pipeline: Option<PipelineHandle>, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self, LodError> {
+        lods.check_levels(levels.len())?;
+
+        Ok(MeshResource {
+            levels: levels.iter().map(|mesh| MeshGeometry::new(mesh, device, queue)).collect(),
+            lods: (levels.len() > 1).then_some(lods),
+            vertex_layout: T::vertex_buffer_layout(),
+            pipeline,
+            revision: 0,
+        })
+    }
+
+    /// Replaces the vertices and indices, growing the buffers if they don't fit.  Fails for a
+    /// mesh with levels of detail, which are replaced with `update_lods()`.
+    pub fn update<T: Vertex>(&mut self, mesh: &Mesh<T>, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        self.check_single_level()?;
+        self.levels[0].update(mesh, device, queue);
+        self.vertex_layout = T::vertex_buffer_layout();
+        self.revision = self.revision.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Replaces every level of detail and how they are chosen, reusing the buffers of existing
+    /// levels.  With a single level, `lods` is ignored.
+    pub fn update_lods<T: Vertex>(&mut self, levels: &[Mesh<T>], lods: LodSettings, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), LodError> {
+        lods.check_levels(levels.len())?;
+
+        if self.levels.len() > levels.len() {
+            for level in self.levels.drain(levels.len()..) {
+                level.destroy();
+            }
+        }
+        for (i, mesh) in levels.iter().enumerate() {
+            match self.levels.get_mut(i) {
+                Some(level) => level.update(mesh, device, queue),
+                None => self.levels.push(MeshGeometry::new(mesh, device, queue)),
+            }
+        }
+        self.lods = (levels.len() > 1).then_some(lods);
+        self.vertex_layout = T::vertex_buffer_layout();
+        self.revision = self.revision.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Overwrites vertices starting at `first`, which must already exist and be of type `T`.
+    /// Fails for a mesh with levels of detail, whose levels would no longer match.
+    pub fn update_vertices<T: Vertex>(&mut self, first: u32, vertices: &[T], queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        self.check_single_level()?;
+        if T::vertex_buffer_layout() != self.vertex_layout {
+            return Err(MeshUpdateError::VertexLayout);
+        }
+        self.levels[0].update_vertices(first, vertices, queue)?;
+        self.revision = self.revision.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Overwrites indices starting at `first`, which must already exist.  Fails for a mesh with
+    /// levels of detail, whose levels would no longer match.
+    pub fn update_indices(&mut self, first: u32, indices: &[u32], queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        self.check_single_level()?;
+        self.levels[0].update_indices(first, indices, queue)?;
+        self.revision = self.revision.wrapping_add(1);
+        Ok(())
+    }
+
+    pub fn gpu_bytes(&self) -> u64 {
+        self.levels.iter().map(MeshGeometry::gpu_bytes).sum()
+    }
+
+    fn check_single_level(&self) -> Result<(), MeshUpdateError> {
+        match self.levels.len() {
+            1 => Ok(()),
+            _ => Err(MeshUpdateError::LevelsOfDetail),
+        }
+    }
+
+    /// Frees the buffers now rather than when the last reference is dropped.
+    pub fn destroy(self) {
+        for level in self.levels {
+            level.destroy();
+        }
+    }
+
+    /// Recreates the buffers on a new device from the cpu-side copies.
+    pub fn recreate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        for level in &mut self.levels {
+            level.recreate(device, queue);
+        }
+    }
+}
+
+/// The buffers of one level of detail of a mesh.
+pub struct MeshGeometry {
+    vertex_buffer: ResizableBuffer,
+    index_buffer: ResizableBuffer,
+    vertex_data: Vec<u8>,
+    indices: Vec<u32>,
+    vertex_count: u32,
+    bounds: Option<Bounds>,
+}
+
+impl MeshGeometry {
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        self.vertex_buffer.buffer()
+    }
+
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        self.index_buffer.buffer()
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.indices.len() as u32
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn bounds(&self) -> Option<&Bounds> {
+        self.bounds.as_ref()
+    }
+
+    fn new<T: Vertex>(mesh: &Mesh<T>, device: &wgpu::Device, queue: &wgpu::Queue) -> MeshGeometry {
+        let mut geometry = MeshGeometry {
+            vertex_buffer: Self::create_vertex_buffer(device),
+            index_buffer: Self::create_index_buffer(device),
+            vertex_data: Vec::new(),
+            indices: Vec::new(),
+            vertex_count: 0,
+            bounds: None,
+        };
+        geometry.update(mesh, device, queue);
+        geometry
+    }
+
+    fn update<T: Vertex>(&mut self, mesh: &Mesh<T>, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.vertex_data = bytemuck::cast_slice(mesh.vertices()).to_vec();
+        self.indices = mesh.indices().clone();
+        self.vertex_count = mesh.vertices().len() as u32;
+        self.bounds = Bounds::from_positions(mesh.vertices().iter().map(Vertex::position));
+        self.upload(device, queue);
+    }
+
+    fn update_vertices<T: Vertex>(&mut self, first: u32, vertices: &[T], queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        let end = first as usize + vertices.len();
+        if end > self.vertex_count as usize {
+            return Err(MeshUpdateError::OutOfRange { end: end as u32, len: self.vertex_count });
+        }
+
+        let data: &[u8] = bytemuck::cast_slice(vertices);
+        let offset = first as usize * mem::size_of::<T>();
+        self.vertex_data[offset..offset + data.len()].copy_from_slice(data);
+        if let Some(bounds) = Bounds::from_positions(vertices.iter().map(Vertex::position)) {
+            self.bounds = Some(self.bounds.map_or(bounds, |old| old.union(&bounds)));
+        }
+        self.vertex_buffer.write(queue, offset as u64, data);
+        Ok(())
+    }
+
+    fn update_indices(&mut self, first: u32, indices: &[u32], queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        let end = first as usize + indices.len();
+        if end > self.indices.len() {
+            return Err(MeshUpdateError::OutOfRange { end: end as u32, len: self.index_count() });
         }
+
+        self.indices[first as usize..end].copy_from_slice(indices);
+        self.index_buffer.write(queue, first as u64 * 4, bytemuck::cast_slice(indices));
+        Ok(())
+    }
+
+    fn gpu_bytes(&self) -> u64 {
+        self.vertex_buffer.capacity() as u64 + self.index_buffer.capacity() as u64
+    }
+
+    fn destroy(self) {
+        self.vertex_buffer.buffer().destroy();
+        self.index_buffer.buffer().destroy();
+    }
+
+    fn recreate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.vertex_buffer = Self::create_vertex_buffer(device);
+        self.index_buffer = Self::create_index_buffer(device);
+        self.upload(device, queue);
+    }
+
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.vertex_buffer.update(device, queue, &self.vertex_data);
+        self.index_buffer.update(device, queue, bytemuck::cast_slice(&self.indices));
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device) -> ResizableBuffer {
+        // copied from into the shared buffers for indirect drawing
+        ResizableBuffer::new(0, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC, device)
+    }
+
+    fn create_index_buffer(device: &wgpu::Device) -> ResizableBuffer {
+        ResizableBuffer::new(0, wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC, device)
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct MeshHandle(Index);
 
+/// An RGBA8 texture, along with a cpu-side copy of its pixels for recreating it after device loss.
+pub struct TextureResource {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl TextureResource {
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn new(width: u32, height: u32, data: &[u8], device: &wgpu::Device, queue: &wgpu::Queue) -> TextureResource {
+        let (texture, view) = create_rgba8_texture(device, queue, width, height, data);
+        TextureResource {
+            texture,
+            view,
+            width,
+            height,
+            data: data.to_vec(),
+        }
+    }
+
+    pub fn gpu_bytes(&self) -> u64 {
+        self.width as u64 * self.height as u64 * 4
+    }
+
+    /// Recreates the texture on a new device from the cpu-side copy.
+    pub fn recreate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        (self.texture, self.view) = create_rgba8_texture(device, queue, self.width, self.height, &self.data);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TextureHandle(Index);
+
+pub struct MaterialResource {
+    material: Material,
+    material_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl MaterialResource {
+    pub fn material_buffer(&self) -> &wgpu::Buffer {
+        &self.material_buffer
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn pipeline(&self) -> Option<PipelineHandle> {
+        self.material.pipeline()
+    }
+
+    /// Whether the material's instances are drawn in the transparent pass.
+    pub fn blended(&self) -> bool {
+        self.material.blended()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MaterialHandle(Index);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PipelineHandle(Index);
+
 
 // manages buffers, bind group layouts, and bind groups
 pub struct Resources {
-    meshes: Arena<Option<MeshResource>>,
-    instances: Arena<InstanceListResource>,
+    meshes: Arena<MeshResource>,
+    instances: BTreeMap<MeshHandle, InstanceListResource>, // ordered so meshes draw in a stable order
+    textures: Arena<TextureResource>,
+    materials: Arena<MaterialResource>,
+    pipelines: Arena<PipelineResource>,
+    default_pipelines: HashMap<wgpu::VertexBufferLayout<'static>, PipelineHandle>,
     camera: CameraResource,
     light: LightResource,
+    shadow_map: ShadowMap,
+    local_lights: LocalLightsResource,
 
     camera_bind_group_layout: wgpu::BindGroupLayout,
     camera_bind_group: wgpu::BindGroup,
 
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    texture_sampler: wgpu::Sampler,
+    white_texture: TextureResource, // bound by materials without an albedo texture
+    default_material: MaterialHandle,
+
+    depth_format: wgpu::TextureFormat,
     depth_texture: wgpu::Texture,
     depth_texture_view: wgpu::TextureView,
 }
 
 impl Resources {
-    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Resources {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        depth_format: wgpu::TextureFormat,
+    ) -> Resources {
         let meshes = Arena::new();
-        let instances = Arena::new();
+        let instances = BTreeMap::new();
+        let textures = Arena::new();
+        let mut materials = Arena::new();
+        let pipelines = Arena::new();
+        let default_pipelines = HashMap::new();
         let camera = CameraResource::new(device);
         let light = LightResource::new(device);
 
+        // the shadow map is a placeholder until a light with shadows is set
+        let shadow_map = ShadowMap::new(1, &light.light_buffer, device);
+        let local_lights = LocalLightsResource::new(device);
+
         // todo: consider refactoring bind groups into their own struct
-        let (
-            camera_bind_group_layout,
-            camera_bind_group
-        ) = create_camera_bind_group_and_layout(&camera.camera_buffer, &light.light_buffer, device);
+        let camera_bind_group_layout = create_camera_bind_group_layout(device);
+        let camera_bind_group = create_camera_bind_group(
+            device,
+            &camera_bind_group_layout,
+            &camera.camera_buffer,
+            &light.light_buffer,
+            shadow_map.view(),
+            shadow_map.sampler(),
+            local_lights.buffer(),
+        );
+
+        let material_bind_group_layout = create_material_bind_group_layout(device);
+        let texture_sampler = Self::create_texture_sampler(device);
+
+        let white_texture = TextureResource::new(1, 1, &[255, 255, 255, 255], device, queue);
+
+        let default_material = MaterialHandle(materials.insert(Self::create_material_resource(
+            &Material::white(),
+            &white_texture,
+            &material_bind_group_layout,
+            &texture_sampler,
+            device,
+        )));
 
         let (
             depth_texture,
             depth_texture_view,
-        ) = create_depth_texture(device, config);
+        ) = create_depth_texture(device, config, depth_format, 1, true);
 
         Resources {
             meshes,
             instances,
+            textures,
+            materials,
+            pipelines,
+            default_pipelines,
             camera,
             light,
+            shadow_map,
+            local_lights,
 
             camera_bind_group_layout,
             camera_bind_group,
 
+            material_bind_group_layout,
+            texture_sampler,
+            white_texture,
+            default_material,
+
+            depth_format,
             depth_texture,
             depth_texture_view,
         }
@@ -206,9 +660,60 @@ impl Resources {
         &self.camera_bind_group
     }
 
+    pub fn material_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.material_bind_group_layout
+    }
+
+    // ================================================================
+    // lights and shadows
+    // ================================================================
+    pub fn light(&self) -> &LightResource {
+        &self.light
+    }
+
+    pub fn shadow_map(&self) -> &ShadowMap {
+        &self.shadow_map
+    }
+
+    pub fn local_lights(&self) -> &LocalLightsResource {
+        &self.local_lights
+    }
+
+    pub fn add_point_light(&mut self, light: &PointLight) -> PointLightHandle {
+        self.local_lights.add_point_light(light)
+    }
+
+    pub fn update_point_light(&mut self, handle: PointLightHandle, light: &PointLight) -> Result<(), HandleError> {
+        self.local_lights.update_point_light(handle, light)
+    }
+
+    pub fn remove_point_light(&mut self, handle: PointLightHandle) -> Result<(), HandleError> {
+        self.local_lights.remove_point_light(handle)
+    }
+
+    pub fn add_spot_light(&mut self, light: &SpotLight) -> SpotLightHandle {
+        self.local_lights.add_spot_light(light)
+    }
+
+    pub fn update_spot_light(&mut self, handle: SpotLightHandle, light: &SpotLight) -> Result<(), HandleError> {
+        self.local_lights.update_spot_light(handle, light)
+    }
+
+    pub fn remove_spot_light(&mut self, handle: SpotLightHandle) -> Result<(), HandleError> {
+        self.local_lights.remove_spot_light(handle)
+    }
+
+    pub fn upload_local_lights(&mut self, queue: &wgpu::Queue) {
+        self.local_lights.upload(queue);
+    }
+
     // ================================================================
     // bind groups and layouts
     // ================================================================
+    pub fn depth_format(&self) -> wgpu::TextureFormat {
+        self.depth_format
+    }
+
     pub fn depth_texture(&self) -> &wgpu::Texture {
         &self.depth_texture
     }
@@ -217,37 +722,34 @@ impl Resources {
         &self.depth_texture_view
     }
 
-    pub fn resize_depth_texture(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
-        (self.depth_texture, self.depth_texture_view) = create_depth_texture(device, config);
+    /// Recreates the depth texture; it can only be bound as a texture if `readable`.
+    pub fn resize_depth_texture(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32, readable: bool) {
+        (self.depth_texture, self.depth_texture_view) = create_depth_texture(device, config, self.depth_format, sample_count, readable);
     }
 
     // ================================================================
     // meshes
     // ================================================================
-    pub fn add_mesh(&mut self, mesh: &Mesh<ColorNormalVertex>, device: &wgpu::Device) -> MeshHandle {
-        let skip = mesh.indices().len() == 0;
-        
-        let mesh_resource = if skip {
-            None
-        } else {
-            // set up mesh
-            let vertex_buffer = mesh.export_vertex_buffer(device);
-            let index_buffer = mesh.export_index_buffer(device);
-            let index_count = mesh.index_count();
-     
-            Some(MeshResource {
-                vertex_buffer,
-                index_buffer,
-                index_count,
-            })
-        };
-        
-        let index = self.meshes.insert(mesh_resource);
+    pub fn add_mesh<T: Vertex>(&mut self, mesh: &Mesh<T>, pipeline: Option<PipelineHandle>, device: &wgpu::Device, queue: &wgpu::Queue) -> MeshHandle {
+        // set up mesh
+        self.shadow_map.prepare_pipeline(&T::vertex_buffer_layout(), device);
+        let mesh = MeshResource::new(mesh, pipeline, device, queue);
+        self.insert_mesh(mesh, device)
+    }
+
+    pub fn add_mesh_with_lods<T: Vertex>(&mut self, levels: &[Mesh<T>], lods: LodSettings, pipeline: Option<PipelineHandle>, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<MeshHandle, LodError> {
+        let mesh = MeshResource::with_lods(levels, lods, pipeline, device, queue)?;
+        self.shadow_map.prepare_pipeline(&T::vertex_buffer_layout(), device);
+        Ok(self.insert_mesh(mesh, device))
+    }
+
+    fn insert_mesh(&mut self, mesh: MeshResource, device: &wgpu::Device) -> MeshHandle {
+        let index = self.meshes.insert(mesh);
         let mesh_handle =  MeshHandle(index);
 
         // set up instance list
         let instance_list = InstanceListResource::new(mesh_handle, device);
-        self.instances.insert(instance_list);
+        self.instances.insert(mesh_handle, instance_list);
 
         // return handle
         mesh_handle
@@ -255,58 +757,512 @@ impl Resources {
 
     pub fn get_mesh(&self, handle: MeshHandle) -> Option<&MeshResource> {
         let MeshHandle(index) = handle;
-        self.meshes.get(index).unwrap().as_ref()
+        self.meshes.get(index)
+    }
+
+    pub fn iterate_meshes(&self) -> impl Iterator<Item = (MeshHandle, &MeshResource)> {
+        self.meshes.iter().map(|(index, mesh)| (MeshHandle(index), mesh))
+    }
+
+    pub fn set_mesh_pipeline(&mut self, handle: MeshHandle, pipeline: Option<PipelineHandle>) -> Result<(), HandleError> {
+        if let Some(pipeline) = pipeline {
+            self.check_pipeline(pipeline)?;
+        }
+        self.get_mesh_mut(handle)?.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// Replaces a mesh's vertices and indices in place; its instances keep their handles.
+    pub fn update_mesh<T: Vertex>(&mut self, handle: MeshHandle, mesh: &Mesh<T>, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        self.get_mesh_mut(handle)?.update(mesh, device, queue)?;
+        self.shadow_map.prepare_pipeline(&T::vertex_buffer_layout(), device);
+        Ok(())
+    }
+
+    pub fn update_mesh_lods<T: Vertex>(&mut self, handle: MeshHandle, levels: &[Mesh<T>], lods: LodSettings, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        self.get_mesh_mut(handle)?.update_lods(levels, lods, device, queue)?;
+        self.shadow_map.prepare_pipeline(&T::vertex_buffer_layout(), device);
+        // the instances' levels were chosen from the old settings
+        if let Some(instance_list) = self.instances.get_mut(&handle) {
+            instance_list.reset_levels();
+        }
+        Ok(())
+    }
+
+    pub fn update_mesh_vertices<T: Vertex>(&mut self, handle: MeshHandle, first: u32, vertices: &[T], queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        self.get_mesh_mut(handle)?.update_vertices(first, vertices, queue)
+    }
+
+    pub fn update_mesh_indices(&mut self, handle: MeshHandle, first: u32, indices: &[u32], queue: &wgpu::Queue) -> Result<(), MeshUpdateError> {
+        self.get_mesh_mut(handle)?.update_indices(first, indices, queue)
+    }
+
+    fn get_mesh_mut(&mut self, handle: MeshHandle) -> Result<&mut MeshResource, HandleError> {
+        let MeshHandle(index) = handle;
+        self.meshes.get_mut(index).ok_or(HandleError::Stale(HandleKind::Mesh))
     }
 
-    pub fn remove_mesh(&mut self, handle: MeshHandle) {
+    /// Removes the mesh along with its instances, freeing their buffers.
+    pub fn remove_mesh(&mut self, handle: MeshHandle) -> Result<(), HandleError> {
         let MeshHandle(index) = handle;
-        self.meshes.remove(index);
+        self.meshes.remove(index).ok_or(HandleError::Stale(HandleKind::Mesh))?.destroy();
+        if let Some(instance_list) = self.instances.remove(&handle) {
+            instance_list.destroy();
+        }
+        Ok(())
+    }
+
+    // ================================================================
+    // textures
+    // ================================================================
+    /// Checks the size and data before uploading, since wgpu would otherwise raise a validation
+    /// error.
+    pub fn add_texture(&mut self, width: u32, height: u32, data: &[u8], device: &wgpu::Device, queue: &wgpu::Queue) -> Result<TextureHandle, TextureError> {
+        if width == 0 || height == 0 {
+            return Err(TextureError::Empty);
+        }
+        let max = device.limits().max_texture_dimension_2d;
+        if width > max || height > max {
+            return Err(TextureError::TooLarge { width, height, max });
+        }
+        let expected = 4 * width as usize * height as usize;
+        if data.len() != expected {
+            return Err(TextureError::DataLength { expected, actual: data.len() });
+        }
+
+        let index = self.textures.insert(TextureResource::new(width, height, data, device, queue));
+        Ok(TextureHandle(index))
+    }
+
+    pub fn get_texture(&self, handle: TextureHandle) -> Option<&TextureResource> {
+        let TextureHandle(index) = handle;
+        self.textures.get(index)
+    }
+
+    pub fn remove_texture(&mut self, handle: TextureHandle) -> Result<(), HandleError> {
+        let TextureHandle(index) = handle;
+        self.textures.remove(index).ok_or(HandleError::Stale(HandleKind::Texture))?;
+        Ok(())
+    }
+
+    // ================================================================
+    // materials
+    // ================================================================
+    pub fn add_material(&mut self, material: &Material, device: &wgpu::Device) -> Result<MaterialHandle, HandleError> {
+        let albedo_texture = self.material_texture(material)?;
+
+        let material_resource = Self::create_material_resource(
+            material,
+            albedo_texture,
+            &self.material_bind_group_layout,
+            &self.texture_sampler,
+            device,
+        );
+
+        Ok(MaterialHandle(self.materials.insert(material_resource)))
+    }
+
+    pub fn get_material(&self, handle: MaterialHandle) -> Option<&MaterialResource> {
+        let MaterialHandle(index) = handle;
+        self.materials.get(index)
+    }
+
+    pub fn update_material(&mut self, handle: MaterialHandle, material: &Material, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), HandleError> {
+        let MaterialHandle(index) = handle;
+        let material_resource = self.materials.get(index).ok_or(HandleError::Stale(HandleKind::Material))?;
+        let albedo_texture = self.material_texture(material)?;
+
+        queue.write_buffer(
+            &material_resource.material_buffer,
+            0,
+            bytemuck::cast_slice(&[MaterialData::new(material)]),
+        );
+        let bind_group = create_material_bind_group(
+            device,
+            &self.material_bind_group_layout,
+            &material_resource.material_buffer,
+            albedo_texture.view(),
+            &self.texture_sampler,
+        );
+
+        let material_resource = &mut self.materials[index];
+        material_resource.bind_group = bind_group;
+        material_resource.material = *material;
+        Ok(())
+    }
+
+    pub fn remove_material(&mut self, handle: MaterialHandle) -> Result<(), HandleError> {
+        let MaterialHandle(index) = handle;
+        self.materials.remove(index).ok_or(HandleError::Stale(HandleKind::Material))?;
+        Ok(())
+    }
+
+    pub fn default_material(&self) -> MaterialHandle {
+        self.default_material
+    }
+
+    // the texture a material samples, white without one, checking its texture and pipeline
+    // handles
+    fn material_texture(&self, material: &Material) -> Result<&TextureResource, HandleError> {
+        if let Some(pipeline) = material.pipeline() {
+            self.check_pipeline(pipeline)?;
+        }
+        match material.albedo_texture() {
+            Some(TextureHandle(index)) => self.textures.get(index).ok_or(HandleError::Stale(HandleKind::Texture)),
+            None => Ok(&self.white_texture),
+        }
+    }
+
+    fn create_material_resource(
+        material: &Material,
+        albedo_texture: &TextureResource,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        device: &wgpu::Device,
+    ) -> MaterialResource {
+        let material_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("material buffer"),
+            contents: bytemuck::cast_slice(&[MaterialData::new(material)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = create_material_bind_group(
+            device,
+            layout,
+            &material_buffer,
+            albedo_texture.view(),
+            sampler,
+        );
+
+        MaterialResource {
+            material: *material,
+            material_buffer,
+            bind_group,
+        }
+    }
+
+    // ================================================================
+    // pipelines
+    // ================================================================
+    /// Adds a pipeline, which also becomes the default for its vertex layout if there is none yet.
+    pub fn add_pipeline(&mut self, pipeline: PipelineResource) -> PipelineHandle {
+        let vertex_layout = pipeline.vertex_layout().clone();
+        let handle = PipelineHandle(self.pipelines.insert(pipeline));
+        self.default_pipelines.entry(vertex_layout).or_insert(handle);
+        handle
+    }
+
+    pub fn set_default_pipeline(&mut self, vertex_layout: wgpu::VertexBufferLayout<'static>, pipeline: PipelineHandle) -> Result<(), HandleError> {
+        self.check_pipeline(pipeline)?;
+        self.default_pipelines.insert(vertex_layout, pipeline);
+        Ok(())
+    }
+
+    pub fn default_pipeline(&self, vertex_layout: &wgpu::VertexBufferLayout<'static>) -> Option<PipelineHandle> {
+        self.default_pipelines.get(vertex_layout).copied()
+    }
+
+    /// The pipeline for instances of `mesh` with `material`: the material's override, else the
+    /// mesh's pipeline, else the default for the mesh's vertex layout.
+    pub fn resolve_pipeline(&self, mesh: &MeshResource, material: &MaterialResource) -> Option<PipelineHandle> {
+        material.pipeline()
+            .or(mesh.pipeline())
+            .or_else(|| self.default_pipeline(mesh.vertex_layout()))
+    }
+
+    pub fn get_pipeline(&self, handle: PipelineHandle) -> Option<&PipelineResource> {
+        let PipelineHandle(index) = handle;
+        self.pipelines.get(index)
+    }
+
+    pub fn get_pipeline_mut(&mut self, handle: PipelineHandle) -> Option<&mut PipelineResource> {
+        let PipelineHandle(index) = handle;
+        self.pipelines.get_mut(index)
+    }
+
+    pub fn check_pipeline(&self, handle: PipelineHandle) -> Result<(), HandleError> {
+        self.get_pipeline(handle).map(|_| ()).ok_or(HandleError::Stale(HandleKind::Pipeline))
+    }
+
+    pub fn remove_pipeline(&mut self, handle: PipelineHandle) -> Result<(), HandleError> {
+        let PipelineHandle(index) = handle;
+        self.pipelines.remove(index).ok_or(HandleError::Stale(HandleKind::Pipeline))?;
+        self.default_pipelines.retain(|_, pipeline| *pipeline != handle);
+        Ok(())
+    }
+
+    pub fn iterate_pipelines(&self) -> impl Iterator<Item = (PipelineHandle, &PipelineResource)> {
+        self.pipelines.iter().map(|(index, pipeline)| (PipelineHandle(index), pipeline))
+    }
+
+    pub fn iterate_pipelines_mut(&mut self) -> impl Iterator<Item = (PipelineHandle, &mut PipelineResource)> {
+        self.pipelines.iter_mut().map(|(index, pipeline)| (PipelineHandle(index), pipeline))
     }
 
     // ================================================================
     // instances
     // ================================================================
-    pub fn add_instance(&mut self, mesh: MeshHandle, transform: Transform) -> InstanceHandle {
-        self.get_instance_list_mut(mesh).add_instance(transform)
+    pub fn add_instance(&mut self, mesh: MeshHandle, material: MaterialHandle, transform: Transform) -> Result<InstanceHandle, HandleError> {
+        self.check_material(material)?;
+        let instance_list = self.get_instance_list_mut(mesh)
+            .map_err(|_| HandleError::Stale(HandleKind::Mesh))?;
+        Ok(instance_list.add_instance(material, transform))
+    }
+
+    pub fn set_instance_material(&mut self, instance: InstanceHandle, material: MaterialHandle) -> Result<(), HandleError> {
+        self.check_material(material)?;
+        self.get_instance_list_mut(instance.mesh())?.set_instance_material(instance, material)
     }
 
-    pub fn update_instance(&mut self, instance: InstanceHandle, transform: Transform) {
-        self.get_instance_list_mut(instance.mesh()).update_instance(instance, transform);
+    pub fn update_instance(&mut self, instance: InstanceHandle, transform: Transform) -> Result<(), HandleError> {
+        self.get_instance_list_mut(instance.mesh())?.update_instance(instance, transform)
     }
 
-    pub fn set_instance_active(&mut self, instance: InstanceHandle, active: bool) {
-        self.get_instance_list_mut(instance.mesh()).set_instance_active(instance, active)
+    pub fn set_instance_active(&mut self, instance: InstanceHandle, active: bool) -> Result<(), HandleError> {
+        self.get_instance_list_mut(instance.mesh())?.set_instance_active(instance, active)
     }
 
-    pub fn remove_instance(&mut self, instance: InstanceHandle) {
-        self.get_instance_list_mut(instance.mesh()).remove_instance(instance);
+    pub fn remove_instance(&mut self, instance: InstanceHandle) -> Result<(), HandleError> {
+        self.get_instance_list_mut(instance.mesh())?.remove_instance(instance)
     }
 
-    pub fn iterate_instance_lists(&self) -> ArenaIterator<InstanceListResource> {
-        ArenaIterator::iterate(&self.instances)
+    pub fn iterate_instance_lists(&self) -> impl Iterator<Item = &InstanceListResource> {
+        self.instances.values()
     }
 
-    pub fn iterate_instance_lists_mut(&mut self) -> ArenaIteratorMut<InstanceListResource> {
-        ArenaIteratorMut::iterate(&mut self.instances)
+    /// The frusta to cull instances against: none without `culling` or a camera, otherwise the
+    /// camera's, and the shadow map's when shadows are on, since instances inside it may cast
+    /// shadows into view.
+    pub fn culling_frusta(&self, culling: bool) -> Vec<Frustum> {
+        let mut frusta = Vec::new();
+        if let (true, Some(camera)) = (culling, self.camera.camera()) {
+            frusta.push(Frustum::from_view_projection(camera.build_view_projection_matrix()));
+            if let Some(view_projection) = self.light.shadow_view_projection() {
+                frusta.push(Frustum::from_view_projection(view_projection));
+            }
+        }
+        frusta
+    }
+
+    /// Packs each mesh's active instances into its instance buffer, leaving out instances outside
+    /// all of `frusta` unless it is empty, and choosing levels of detail from the camera.
+    pub fn build_instance_buffers(&mut self, frusta: &[Frustum], device: &wgpu::Device, queue: &wgpu::Queue) {
+        let camera = self.camera.camera();
+        for (mesh, instance_list) in self.instances.iter_mut() {
+            let mesh = self.meshes.get(mesh.0);
+            let bounds = mesh.and_then(|mesh| mesh.bounds());
+            let lod = mesh.and_then(|mesh| mesh.lods())
+                .zip(camera)
+                .map(|(lods, camera)| LodView::new(lods, camera));
+            instance_list.build_instance_buffer(device, queue, bounds, frusta, lod.as_ref());
+        }
+    }
+
+    // ================================================================
+    // stats
+    // ================================================================
+    /// Counts live meshes and instances, and the bytes of the gpu buffers and textures they use.
+    pub fn stats(&self) -> RendererStats {
+        let mesh_bytes: u64 = self.meshes.iter()
+            .map(|(_, mesh)| mesh.gpu_bytes())
+            .sum();
+        let instance_bytes: u64 = self.instances.values()
+            .map(|instance_list| instance_list.gpu_bytes())
+            .sum();
+        let texture_bytes: u64 = self.textures.iter()
+            .map(|(_, texture)| texture.gpu_bytes())
+            .sum();
+        let material_bytes: u64 = self.materials.iter()
+            .map(|(_, material)| material.material_buffer.size())
+            .sum();
+
+        RendererStats::new(
+            self.meshes.len() as u32,
+            self.instances.values().map(|instance_list| instance_list.instance_count()).sum(),
+            self.instances.values().map(|instance_list| instance_list.buffered_instance_count()).sum(),
+            self.instances.values().map(|instance_list| instance_list.culled_count()).sum(),
+            self.instances.values().map(|instance_list| instance_list.uploaded_bytes()).sum(),
+            mesh_bytes + instance_bytes + texture_bytes + material_bytes,
+        )
+    }
+
+    // ================================================================
+    // validation
+    // ================================================================
+    /// Finds handles kept by meshes, materials, and instances whose resources were removed.
+    #[cfg(debug_assertions)]
+    pub fn validate_handles(&self) -> HandleReport {
+        let mut dangling = Vec::new();
+
+        for (index, mesh) in self.meshes.iter() {
+            if let Some(pipeline) = mesh.pipeline() {
+                if self.get_pipeline(pipeline).is_none() {
+                    dangling.push(DanglingHandle::MeshPipeline { mesh: MeshHandle(index), pipeline });
+                }
+            }
+        }
+
+        for (index, material) in self.materials.iter() {
+            let handle = MaterialHandle(index);
+            if let Some(pipeline) = material.material.pipeline() {
+                if self.get_pipeline(pipeline).is_none() {
+                    dangling.push(DanglingHandle::MaterialPipeline { material: handle, pipeline });
+                }
+            }
+            if let Some(texture) = material.material.albedo_texture() {
+                if self.get_texture(texture).is_none() {
+                    dangling.push(DanglingHandle::MaterialTexture { material: handle, texture });
+                }
+            }
+        }
+
+        for instance_list in self.instances.values() {
+            for (instance, data) in instance_list.iterate_instances() {
+                if self.get_material(data.material()).is_none() {
+                    dangling.push(DanglingHandle::InstanceMaterial { instance, material: data.material() });
+                }
+            }
+        }
+
+        HandleReport::new(dangling)
     }
 
     // ================================================================
     // camera and lights
     // ================================================================
+    /// The camera set with `update_camera()`, if any.
+    pub fn camera(&self) -> Option<&Camera> {
+        self.camera.camera()
+    }
+
     pub fn update_camera(&mut self, camera: &Camera, queue: &wgpu::Queue) {
         self.camera.update(camera, queue);
+        self.light.update_shadows(self.camera.camera(), queue);
     }
 
-    pub fn update_light(&mut self, directional_light: &DirectionalLight, ambient_light: &AmbientLight, queue: &wgpu::Queue) {
+    pub fn update_light(&mut self, directional_light: &DirectionalLight, ambient_light: &AmbientLight, device: &wgpu::Device, queue: &wgpu::Queue) {
         self.light.update(directional_light, ambient_light, queue);
+
+        if let Some(shadows) = directional_light.shadows() {
+            if shadows.resolution() != self.shadow_map.resolution() {
+                self.shadow_map.resize(shadows.resolution(), device);
+                self.camera_bind_group = create_camera_bind_group(
+                    device,
+                    &self.camera_bind_group_layout,
+                    &self.camera.camera_buffer,
+                    &self.light.light_buffer,
+                    self.shadow_map.view(),
+                    self.shadow_map.sampler(),
+                    self.local_lights.buffer(),
+                );
+            }
+        }
+
+        self.light.update_shadows(self.camera.camera(), queue);
     }
     
 
+    // ================================================================
+    // device loss
+    // ================================================================
+    /// Recreates every gpu object on a new device after the old one was lost, from the cpu-side
+    /// copies of meshes, textures, materials, instances, and lights, so handles stay valid.
+    /// Pipelines with bind groups of their own can't be recreated, since the caller made those
+    /// bind groups on the old device; they are removed, and their handles returned.
+    pub fn recreate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        depth_readable: bool,
+    ) -> Vec<PipelineHandle> {
+        self.camera_bind_group_layout = create_camera_bind_group_layout(device);
+        self.material_bind_group_layout = create_material_bind_group_layout(device);
+        self.texture_sampler = Self::create_texture_sampler(device);
+        self.white_texture.recreate(device, queue);
+
+        // camera and lights
+        self.camera.recreate(device);
+        self.light.recreate(device);
+        self.shadow_map = ShadowMap::new(self.shadow_map.resolution(), &self.light.light_buffer, device);
+        self.local_lights.recreate(device);
+        self.camera_bind_group = create_camera_bind_group(
+            device,
+            &self.camera_bind_group_layout,
+            &self.camera.camera_buffer,
+            &self.light.light_buffer,
+            self.shadow_map.view(),
+            self.shadow_map.sampler(),
+            self.local_lights.buffer(),
+        );
+
+        // meshes and instances
+        for (_, mesh) in self.meshes.iter_mut() {
+            mesh.recreate(device, queue);
+            self.shadow_map.prepare_pipeline(mesh.vertex_layout(), device);
+        }
+        for instance_list in self.instances.values_mut() {
+            instance_list.recreate(device);
+        }
+
+        // textures and materials, which bind them
+        for (_, texture) in self.textures.iter_mut() {
+            texture.recreate(device, queue);
+        }
+        for (_, material_resource) in self.materials.iter_mut() {
+            // a texture removed after the material was added has nothing left to upload from
+            let albedo_texture = material_resource.material.albedo_texture()
+                .and_then(|TextureHandle(index)| self.textures.get(index))
+                .unwrap_or(&self.white_texture);
+            *material_resource = Self::create_material_resource(
+                &material_resource.material,
+                albedo_texture,
+                &self.material_bind_group_layout,
+                &self.texture_sampler,
+                device,
+            );
+        }
+
+        // pipelines
+        let shared_layouts = [&self.camera_bind_group_layout, &self.material_bind_group_layout];
+        let mut removed = Vec::new();
+        self.pipelines.retain(|index, pipeline| {
+            let recreated = pipeline.recreate(device, &shared_layouts, sample_count);
+            if !recreated {
+                removed.push(PipelineHandle(index));
+            }
+            recreated
+        });
+        self.default_pipelines.retain(|_, pipeline| !removed.contains(pipeline));
+
+        self.resize_depth_texture(device, config, sample_count, depth_readable);
+
+        removed
+    }
+
     // ================================================================
     // utility
     // ================================================================
-    fn get_instance_list_mut(&mut self, mesh: MeshHandle) -> &mut InstanceListResource {
-        self.instances.get_mut(mesh.0).unwrap()
+    fn create_texture_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("texture sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        })
+    }
+
+    fn check_material(&self, handle: MaterialHandle) -> Result<(), HandleError> {
+        self.get_material(handle).map(|_| ()).ok_or(HandleError::Stale(HandleKind::Material))
+    }
+
+    fn get_instance_list_mut(&mut self, mesh: MeshHandle) -> Result<&mut InstanceListResource, HandleError> {
+        self.instances.get_mut(&mesh).ok_or(HandleError::MeshRemoved)
     }
 }
 
@@ -0,0 +1,65 @@
+use std::fmt;
+
+use super::{gpu_resources::{MaterialHandle, MeshHandle, PipelineHandle, TextureHandle}, instances::InstanceHandle};
+
+/// A handle kept by the renderer that refers to a removed resource.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DanglingHandle {
+    /// The instance's material was removed, so the instance isn't drawn.
+    InstanceMaterial { instance: InstanceHandle, material: MaterialHandle },
+    /// The mesh's pipeline was removed, so its instances are only drawn by materials that
+    /// override it.
+    MeshPipeline { mesh: MeshHandle, pipeline: PipelineHandle },
+    /// The material's pipeline was removed, so instances using it aren't drawn.
+    MaterialPipeline { material: MaterialHandle, pipeline: PipelineHandle },
+    /// The material's albedo texture was removed; it draws with the old texture until updated.
+    MaterialTexture { material: MaterialHandle, texture: TextureHandle },
+}
+
+impl fmt::Display for DanglingHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DanglingHandle::InstanceMaterial { instance, material } => write!(f, "instance {:?} uses removed material {:?}", instance, material),
+            DanglingHandle::MeshPipeline { mesh, pipeline } => write!(f, "mesh {:?} uses removed pipeline {:?}", mesh, pipeline),
+            DanglingHandle::MaterialPipeline { material, pipeline } => write!(f, "material {:?} uses removed pipeline {:?}", material, pipeline),
+            DanglingHandle::MaterialTexture { material, texture } => write!(f, "material {:?} uses removed texture {:?}", material, texture),
+        }
+    }
+}
+
+/// The result of `Renderer::validate_handles()`.
+#[derive(Debug, Clone)]
+pub struct HandleReport {
+    dangling: Vec<DanglingHandle>,
+}
+
+impl HandleReport {
+    pub fn dangling(&self) -> &Vec<DanglingHandle> {
+        &self.dangling
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.dangling.is_empty()
+    }
+
+    pub fn new(dangling: Vec<DanglingHandle>) -> HandleReport {
+        HandleReport {
+            dangling,
+        }
+    }
+}
+
+impl fmt::Display for HandleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            return write!(f, "no dangling handles");
+        }
+        for (i, dangling) in self.dangling.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", dangling)?;
+        }
+        Ok(())
+    }
+}
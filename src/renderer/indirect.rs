@@ -0,0 +1,603 @@
+use std::{collections::BTreeMap, fmt, mem, ops::Range};
+
+use super::{culling::{Bounds, Frustum}, gpu_resources::{MaterialHandle, MeshHandle, PipelineHandle, Resources}, instances::InstanceData, resizable_buffer::ResizableBuffer, shadows::ShadowMap};
+
+/// How instances are drawn.  The indirect modes copy every mesh into shared buffers, one per
+/// vertex layout, and draw from `DrawIndexedIndirect` arguments with one multi-draw call per
+/// pipeline and material where the device supports it, which suits scenes with thousands of
+/// meshes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DrawMode {
+    /// One `draw_indexed()` per mesh and material.
+    Direct,
+    /// Draw arguments written on the cpu, for instances culled on the cpu as with `Direct`.
+    Indirect,
+    /// Draw arguments written by a compute shader that culls each instance, in place of culling
+    /// on the cpu.  `RendererStats` then counts every active instance as drawn.
+    GpuCulled,
+}
+
+/// What indirect drawing the device supports.
+#[derive(Debug, Clone)]
+pub struct IndirectSupport {
+    draw_modes: Vec<DrawMode>,
+    multi_draw: bool,
+    first_instance: bool,
+}
+
+impl IndirectSupport {
+    /// The usable draw modes, always including `Direct`.
+    pub fn draw_modes(&self) -> &Vec<DrawMode> {
+        &self.draw_modes
+    }
+
+    /// Whether runs of draws are issued with one `multi_draw_indexed_indirect()`, rather than a
+    /// loop of `draw_indexed_indirect()`.
+    pub fn multi_draw(&self) -> bool {
+        self.multi_draw
+    }
+
+    /// Whether draw arguments can start past the first instance, so every draw shares one
+    /// binding of the instance buffer.
+    pub fn first_instance(&self) -> bool {
+        self.first_instance
+    }
+
+    /// Multi-draw and first instances need the device to have been created with
+    /// `MULTI_DRAW_INDIRECT` and `INDIRECT_FIRST_INSTANCE`.
+    pub fn new(adapter: &wgpu::Adapter, device: &wgpu::Device) -> IndirectSupport {
+        let downlevel = adapter.get_downlevel_capabilities().flags;
+        let limits = device.limits();
+
+        let mut draw_modes = vec![DrawMode::Direct];
+        if downlevel.contains(wgpu::DownlevelFlags::INDIRECT_EXECUTION) {
+            draw_modes.push(DrawMode::Indirect);
+            if downlevel.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+                && limits.max_storage_buffers_per_shader_stage >= 4
+                && limits.max_compute_invocations_per_workgroup >= CULL_WORKGROUP_SIZE
+                && limits.max_compute_workgroup_size_x >= CULL_WORKGROUP_SIZE
+            {
+                draw_modes.push(DrawMode::GpuCulled);
+            }
+        }
+
+        IndirectSupport {
+            draw_modes,
+            multi_draw: device.features().contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+            first_instance: device.features().contains(wgpu::Features::INDIRECT_FIRST_INSTANCE),
+        }
+    }
+}
+
+
+/// An error from requesting a draw mode the device cannot render with.
+#[derive(Debug, Clone)]
+pub struct UnsupportedDrawMode {
+    draw_mode: DrawMode,
+    supported: Vec<DrawMode>,
+}
+
+impl UnsupportedDrawMode {
+    pub fn draw_mode(&self) -> DrawMode {
+        self.draw_mode
+    }
+
+    pub fn supported(&self) -> &Vec<DrawMode> {
+        &self.supported
+    }
+
+    pub fn new(draw_mode: DrawMode, supported: Vec<DrawMode>) -> UnsupportedDrawMode {
+        UnsupportedDrawMode {
+            draw_mode,
+            supported,
+        }
+    }
+}
+
+impl fmt::Display for UnsupportedDrawMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported draw mode {:?}; supported modes are {:?}", self.draw_mode, self.supported)
+    }
+}
+
+impl std::error::Error for UnsupportedDrawMode {}
+
+
+const CULL_WORKGROUP_SIZE: u32 = 64;
+const MAX_FRUSTA: usize = 2;
+const INSTANCE_STRIDE: u64 = mem::size_of::<InstanceData>() as u64;
+const COMMAND_STRIDE: u64 = mem::size_of::<DrawCommand>() as u64;
+
+/// Draws every instance list from shared buffers with indirect draws.  `prepare()` rebuilds the
+/// draw arguments each frame, copying meshes into the shared buffers only when they change.
+pub struct IndirectRenderer {
+    support: IndirectSupport,
+    pools: Vec<MeshPool>,
+    pooled: BTreeMap<(MeshHandle, usize), PooledMesh>, // by mesh and level of detail
+    instance_buffer: ResizableBuffer,
+    source_buffer: ResizableBuffer, // the unculled instances read by the cull shader
+    command_buffer: ResizableBuffer,
+    job_buffer: ResizableBuffer,
+    cull_buffer: wgpu::Buffer,
+    cull_pipeline: Option<(wgpu::ComputePipeline, wgpu::BindGroupLayout)>,
+    first_instances: Vec<u32>,
+    runs: Vec<DrawRun>,
+}
+
+impl IndirectRenderer {
+    pub fn support(&self) -> &IndirectSupport {
+        &self.support
+    }
+
+    pub fn new(support: IndirectSupport, device: &wgpu::Device) -> IndirectRenderer {
+        let gpu_culling = support.draw_modes().contains(&DrawMode::GpuCulled);
+        // storage usage only where the cull shader can run, since downlevel devices may lack it
+        let storage = if gpu_culling { wgpu::BufferUsages::STORAGE } else { wgpu::BufferUsages::empty() };
+
+        let cull_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cull buffer"),
+            size: mem::size_of::<CullData>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        IndirectRenderer {
+            pools: Vec::new(),
+            pooled: BTreeMap::new(),
+            instance_buffer: ResizableBuffer::new(INSTANCE_STRIDE as u32, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | storage, device),
+            source_buffer: ResizableBuffer::new(INSTANCE_STRIDE as u32, wgpu::BufferUsages::COPY_DST | storage, device),
+            command_buffer: ResizableBuffer::new(COMMAND_STRIDE as u32, wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST | storage, device),
+            job_buffer: ResizableBuffer::new(mem::size_of::<CullJob>() as u32, wgpu::BufferUsages::COPY_DST | storage, device),
+            cull_buffer,
+            cull_pipeline: gpu_culling.then(|| Self::create_cull_pipeline(device)),
+            first_instances: Vec::new(),
+            runs: Vec::new(),
+            support,
+        }
+    }
+
+    /// Brings the shared buffers and draw arguments up to date with `resources`, whose instance
+    /// buffers must already be built.  With `DrawMode::GpuCulled` and a non-empty `frusta`, a
+    /// compute pass culls the instances against them.
+    pub fn prepare(
+        &mut self,
+        draw_mode: DrawMode,
+        frusta: &[Frustum],
+        resources: &Resources,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.update_pools(resources, device, encoder);
+
+        let gpu_culled = draw_mode == DrawMode::GpuCulled && !frusta.is_empty() && self.cull_pipeline.is_some();
+        let instance_count: u32 = resources.iterate_instance_lists()
+            .map(|instance_list| instance_list.buffered_instance_count())
+            .sum();
+        let instance_bytes = (instance_count as u64 * INSTANCE_STRIDE) as u32;
+        self.instance_buffer.reserve(device, instance_bytes);
+        if gpu_culled {
+            self.source_buffer.reserve(device, instance_bytes);
+        }
+
+        // gather the draws, copying the instance buffers one after another; culled on the gpu,
+        // the batches of each mesh, level, and material share a draw whose range they fill
+        let copy_target = if gpu_culled { &self.source_buffer } else { &self.instance_buffer };
+        let mut pending: Vec<PendingDraw> = Vec::new();
+        let mut merged: BTreeMap<(MeshHandle, usize, MaterialHandle), usize> = BTreeMap::new();
+        let mut first = 0;
+        for instance_list in resources.iterate_instance_lists() {
+            let count = instance_list.buffered_instance_count();
+            if count == 0 {
+                continue;
+            }
+            encoder.copy_buffer_to_buffer(
+                instance_list.instance_buffer(),
+                0,
+                copy_target.buffer(),
+                first as u64 * INSTANCE_STRIDE,
+                count as u64 * INSTANCE_STRIDE,
+            );
+
+            let mesh_handle = instance_list.mesh();
+            let Some(mesh) = resources.get_mesh(mesh_handle) else {
+                first += count;
+                continue;
+            };
+            for batch in instance_list.batches() {
+                let Some(pooled) = self.pooled.get(&(mesh_handle, batch.lod())) else {
+                    continue;
+                };
+                let level = mesh.level(batch.lod());
+                let Some(material) = resources.get_material(batch.material()) else {
+                    continue;
+                };
+                let Some(pipeline) = resources.resolve_pipeline(mesh, material) else {
+                    continue;
+                };
+                let Some(bounds) = mesh.bounds().copied() else {
+                    continue;
+                };
+                if level.index_count() == 0 || resources.get_pipeline(pipeline).map(|pipeline| pipeline.vertex_layout()) != Some(mesh.vertex_layout()) {
+                    continue;
+                }
+
+                let draw = PendingDraw {
+                    key: (pooled.pool, pipeline, batch.material()),
+                    index_count: level.index_count(),
+                    mesh: *pooled,
+                    bounds,
+                    sources: Vec::new(),
+                };
+                let index = if gpu_culled {
+                    *merged.entry((mesh_handle, batch.lod(), batch.material())).or_insert_with(|| {
+                        pending.push(draw);
+                        pending.len() - 1
+                    })
+                } else {
+                    pending.push(draw);
+                    pending.len() - 1
+                };
+                let range = batch.range();
+                pending[index].sources.push(first + range.start..first + range.end);
+            }
+            first += count;
+        }
+
+        // sorted so each pipeline and material's draws are consecutive, and each pool's
+        pending.sort_by_key(|draw| draw.key);
+
+        let mut commands = Vec::with_capacity(pending.len());
+        let mut jobs = Vec::new();
+        let mut output = 0;
+        self.first_instances.clear();
+        self.runs.clear();
+        for (index, draw) in pending.iter().enumerate() {
+            let capacity: u32 = draw.sources.iter().map(|source| source.len() as u32).sum();
+            let first_instance = if gpu_culled { output } else { draw.sources[0].start };
+            self.first_instances.push(first_instance);
+            commands.push(DrawCommand {
+                index_count: draw.index_count,
+                instance_count: if gpu_culled { 0 } else { capacity },
+                first_index: draw.mesh.first_index,
+                base_vertex: draw.mesh.base_vertex,
+                // without first instances the instance buffer is bound at the draw's range instead
+                first_instance: if self.support.first_instance { first_instance } else { 0 },
+            });
+
+            if gpu_culled {
+                for source in &draw.sources {
+                    jobs.push(CullJob {
+                        bounds_min: draw.bounds.min().extend(0.0).into(),
+                        bounds_max: draw.bounds.max().extend(0.0).into(),
+                        source_first: source.start,
+                        count: source.len() as u32,
+                        output_first: output,
+                        command: index as u32,
+                    });
+                }
+                output += capacity;
+            }
+
+            let index = index as u32;
+            match self.runs.last_mut() {
+                Some(run) if run.key() == draw.key => run.commands.end = index + 1,
+                _ => self.runs.push(DrawRun {
+                    pool: draw.key.0,
+                    pipeline: draw.key.1,
+                    material: draw.key.2,
+                    commands: index..index + 1,
+                }),
+            }
+        }
+        self.command_buffer.update(device, queue, bytemuck::cast_slice(&commands));
+
+        if gpu_culled && !jobs.is_empty() {
+            jobs.sort_by_key(|job| job.source_first);
+            self.job_buffer.update(device, queue, bytemuck::cast_slice(&jobs));
+            self.cull(frusta, instance_count, jobs.len() as u32, device, queue, encoder);
+        }
+    }
+
+    /// Draws the opaque instances using `pipeline`, which is already set along with group 0.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, pipeline: PipelineHandle, resources: &Resources) {
+        for run in self.runs.iter().filter(|run| run.pipeline == pipeline) {
+            // blended materials are left to the transparent pass
+            let Some(material) = resources.get_material(run.material).filter(|material| !material.blended()) else {
+                continue;
+            };
+            self.set_pool(render_pass, run.pool);
+            render_pass.set_bind_group(1, material.bind_group(), &[]);
+            self.draw_commands(render_pass, run.commands.clone());
+        }
+    }
+
+    /// Draws every instance into the shadow map, whose group 0 is already set.
+    pub fn draw_shadows(&self, shadow_pass: &mut wgpu::RenderPass, shadow_map: &ShadowMap) {
+        // each pool's draws are consecutive, whatever their materials
+        for runs in self.runs.chunk_by(|a, b| a.pool == b.pool) {
+            let pool = runs[0].pool;
+            let Some(pipeline) = shadow_map.pipeline(&self.pools[pool].layout) else {
+                continue;
+            };
+            shadow_pass.set_pipeline(pipeline);
+            self.set_pool(shadow_pass, pool);
+            self.draw_commands(shadow_pass, runs[0].commands.start..runs[runs.len() - 1].commands.end);
+        }
+    }
+
+    /// The capacity of the shared buffers in bytes.
+    pub fn gpu_bytes(&self) -> u64 {
+        let pool_bytes: u64 = self.pools.iter()
+            .map(|pool| pool.vertex_buffer.capacity() as u64 + pool.index_buffer.capacity() as u64)
+            .sum();
+        let buffers = [&self.instance_buffer, &self.source_buffer, &self.command_buffer, &self.job_buffer];
+        pool_bytes + buffers.iter().map(|buffer| buffer.capacity() as u64).sum::<u64>()
+    }
+
+    fn set_pool(&self, render_pass: &mut wgpu::RenderPass, pool: usize) {
+        let pool = &self.pools[pool];
+        render_pass.set_vertex_buffer(0, pool.vertex_buffer.buffer().slice(..));
+        render_pass.set_index_buffer(pool.index_buffer.buffer().slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    fn draw_commands(&self, render_pass: &mut wgpu::RenderPass, commands: Range<u32>) {
+        let command_buffer = self.command_buffer.buffer();
+        if !self.support.first_instance {
+            for command in commands {
+                let first_instance = self.first_instances[command as usize] as u64 * INSTANCE_STRIDE;
+                render_pass.set_vertex_buffer(1, self.instance_buffer.buffer().slice(first_instance..));
+                render_pass.draw_indexed_indirect(command_buffer, command as u64 * COMMAND_STRIDE);
+            }
+            return;
+        }
+
+        render_pass.set_vertex_buffer(1, self.instance_buffer.buffer().slice(..));
+        if self.support.multi_draw {
+            render_pass.multi_draw_indexed_indirect(command_buffer, commands.start as u64 * COMMAND_STRIDE, commands.len() as u32);
+        } else {
+            for command in commands {
+                render_pass.draw_indexed_indirect(command_buffer, command as u64 * COMMAND_STRIDE);
+            }
+        }
+    }
+
+    // copies the meshes of each vertex layout into its pool, when any of them has changed
+    fn update_pools(&mut self, resources: &Resources, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let mut contents: Vec<Vec<(MeshHandle, u32)>> = vec![Vec::new(); self.pools.len()];
+        for (handle, mesh) in resources.iterate_meshes() {
+            let pool = match self.pools.iter().position(|pool| pool.layout == *mesh.vertex_layout()) {
+                Some(pool) => pool,
+                None => {
+                    self.pools.push(MeshPool::new(mesh.vertex_layout().clone(), device));
+                    contents.push(Vec::new());
+                    self.pools.len() - 1
+                },
+            };
+            contents[pool].push((handle, mesh.revision()));
+        }
+
+        for (index, (pool, meshes)) in self.pools.iter_mut().zip(contents).enumerate() {
+            if pool.meshes == meshes {
+                continue;
+            }
+            self.pooled.retain(|_, pooled| pooled.pool != index);
+
+            // every level of detail of every mesh
+            let stride = pool.layout.array_stride;
+            let levels = || meshes.iter()
+                .filter_map(|(handle, _)| resources.get_mesh(*handle).map(|mesh| (*handle, mesh)))
+                .flat_map(|(handle, mesh)| (0..mesh.level_count()).map(move |level| ((handle, level), mesh.level(level))));
+            let vertex_count: u64 = levels().map(|(_, level)| level.vertex_count() as u64).sum();
+            let index_count: u64 = levels().map(|(_, level)| level.index_count() as u64).sum();
+            pool.vertex_buffer.reserve(device, (vertex_count * stride) as u32);
+            pool.index_buffer.reserve(device, (index_count * 4) as u32);
+
+            let (mut base_vertex, mut first_index) = (0, 0);
+            for (key, level) in levels() {
+                let vertex_bytes = level.vertex_count() as u64 * stride;
+                if vertex_bytes > 0 {
+                    encoder.copy_buffer_to_buffer(level.vertex_buffer(), 0, pool.vertex_buffer.buffer(), base_vertex as u64 * stride, vertex_bytes);
+                }
+                if level.index_count() > 0 {
+                    encoder.copy_buffer_to_buffer(level.index_buffer(), 0, pool.index_buffer.buffer(), first_index as u64 * 4, level.index_count() as u64 * 4);
+                }
+                self.pooled.insert(key, PooledMesh {
+                    pool: index,
+                    base_vertex: base_vertex as i32,
+                    first_index,
+                });
+                base_vertex += level.vertex_count();
+                first_index += level.index_count();
+            }
+            pool.meshes = meshes;
+        }
+    }
+
+    // dispatches the cull shader over every instance in the source buffer
+    fn cull(&self, frusta: &[Frustum], instance_count: u32, job_count: u32, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder) {
+        let Some((pipeline, bind_group_layout)) = &self.cull_pipeline else {
+            return;
+        };
+
+        if frusta.len() > MAX_FRUSTA {
+            log::warn!("culling against the first {} of {} frusta", MAX_FRUSTA, frusta.len());
+        }
+        let mut planes = [[0.0; 4]; 6 * MAX_FRUSTA];
+        for (i, plane) in frusta.iter().take(MAX_FRUSTA).flat_map(|frustum| frustum.planes()).enumerate() {
+            planes[i] = (*plane).into();
+        }
+
+        // workgroups past the per-dimension limit wrap into further rows
+        let workgroups = instance_count.div_ceil(CULL_WORKGROUP_SIZE);
+        let row = workgroups.min(device.limits().max_compute_workgroups_per_dimension).max(1);
+        let rows = workgroups.div_ceil(row);
+
+        let cull_data = CullData {
+            planes,
+            frustum_count: frusta.len().min(MAX_FRUSTA) as u32,
+            row_length: row * CULL_WORKGROUP_SIZE,
+            instance_count,
+            job_count,
+        };
+        queue.write_buffer(&self.cull_buffer, 0, bytemuck::cast_slice(&[cull_data]));
+
+        let buffers = [&self.cull_buffer, self.job_buffer.buffer(), self.source_buffer.buffer(), self.instance_buffer.buffer(), self.command_buffer.buffer()];
+        let entries: Vec<wgpu::BindGroupEntry> = buffers.iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: bind_group_layout,
+            entries: &entries,
+            label: Some("cull bind group"),
+        });
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("cull pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(row, rows, 1);
+    }
+
+    fn create_cull_pipeline(device: &wgpu::Device) -> (wgpu::ComputePipeline, wgpu::BindGroupLayout) {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+            ],
+            label: Some("cull bind group layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("cull_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../cull_shader.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("cull pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("cull pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        (pipeline, bind_group_layout)
+    }
+}
+
+
+// the meshes of one vertex layout, one after another in shared buffers
+struct MeshPool {
+    layout: wgpu::VertexBufferLayout<'static>,
+    vertex_buffer: ResizableBuffer,
+    index_buffer: ResizableBuffer,
+    meshes: Vec<(MeshHandle, u32)>, // with their revisions when copied
+}
+
+impl MeshPool {
+    fn new(layout: wgpu::VertexBufferLayout<'static>, device: &wgpu::Device) -> MeshPool {
+        MeshPool {
+            layout,
+            vertex_buffer: ResizableBuffer::new(0, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST, device),
+            index_buffer: ResizableBuffer::new(0, wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST, device),
+            meshes: Vec::new(),
+        }
+    }
+}
+
+// where a mesh sits in its pool
+#[derive(Debug, Copy, Clone)]
+struct PooledMesh {
+    pool: usize,
+    base_vertex: i32,
+    first_index: u32,
+}
+
+// a draw being gathered, with the ranges of the instances it may draw
+struct PendingDraw {
+    key: (usize, PipelineHandle, MaterialHandle),
+    index_count: u32,
+    mesh: PooledMesh,
+    bounds: Bounds,
+    sources: Vec<Range<u32>>,
+}
+
+// consecutive draws sharing a pool, pipeline, and material
+struct DrawRun {
+    pool: usize,
+    pipeline: PipelineHandle,
+    material: MaterialHandle,
+    commands: Range<u32>,
+}
+
+impl DrawRun {
+    fn key(&self) -> (usize, PipelineHandle, MaterialHandle) {
+        (self.pool, self.pipeline, self.material)
+    }
+}
+
+
+// the arguments of `draw_indexed_indirect()`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawCommand {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullJob {
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+    source_first: u32,
+    count: u32,
+    output_first: u32,
+    command: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullData {
+    planes: [[f32; 4]; 6 * MAX_FRUSTA],
+    frustum_count: u32,
+    row_length: u32,
+    instance_count: u32,
+    job_count: u32,
+}
@@ -1,12 +1,17 @@
+use std::{mem, ops::Range};
+
 use generational_arena::{Index, Arena};
 use crate::scene::Transform;
-use crate::renderer::gpu_resources::MeshHandle;
-use super::resizable_buffer::ResizableBuffer;
+use crate::renderer::gpu_resources::{MaterialHandle, MeshHandle};
+use super::{culling::{Bounds, Frustum}, error::{HandleError, HandleKind}, lod::LodView, resizable_buffer::ResizableBuffer};
 
 
 pub struct Instance {
     transform: Transform,
+    material: MaterialHandle,
     active: bool,
+    slot: Option<u32>, // position in the instance list's dense data while active
+    lod: Option<usize>, // the level of detail chosen last, for hysteresis
 }
 
 impl Instance {
@@ -14,21 +19,29 @@ impl Instance {
         self.active
     }
 
-    pub fn new(transform: Transform) -> Instance {
+    pub fn material(&self) -> MaterialHandle {
+        self.material
+    }
+
+    pub fn new(material: MaterialHandle, transform: Transform) -> Instance {
         Instance {
             transform,
+            material,
             active: true,
+            slot: None,
+            lod: None,
         }
     }
 
-    pub fn to_data(&self) -> InstanceData {
-        let model = 
-            cgmath::Matrix4::from_translation(self.transform.translation()) *
-            cgmath::Matrix4::from(self.transform.rotation()) * 
-            cgmath::Matrix4::from_scale(self.transform.scale());
+    pub fn model(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.transform.translation()) *
+        cgmath::Matrix4::from(self.transform.rotation()) * 
+        cgmath::Matrix4::from_scale(self.transform.scale())
+    }
 
+    pub fn to_data(&self) -> InstanceData {
         InstanceData {
-            model: model.into(),
+            model: self.model().into(),
             rotation: cgmath::Matrix3::from(self.transform.rotation()).into(),
         }
     }
@@ -40,6 +53,32 @@ impl Instance {
     pub fn set_active(&mut self, active: bool) {
         self.active = active;
     }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.material = material;
+    }
+}
+
+/// A range of the instance buffer whose instances share a material and level of detail.
+pub struct InstanceBatch {
+    lod: usize,
+    material: MaterialHandle,
+    range: Range<u32>,
+}
+
+impl InstanceBatch {
+    /// The level of detail of the mesh to draw the instances with, 0 for meshes without levels.
+    pub fn lod(&self) -> usize {
+        self.lod
+    }
+
+    pub fn material(&self) -> MaterialHandle {
+        self.material
+    }
+
+    pub fn range(&self) -> Range<u32> {
+        self.range.clone()
+    }
 }
 
 
@@ -56,6 +95,10 @@ impl InstanceData {
         rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
     };
 
+    pub fn model(&self) -> cgmath::Matrix4<f32> {
+        self.model.into()
+    }
+
     pub fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
@@ -102,11 +145,27 @@ impl InstanceData {
     }
 }
 
+/// The instances of one mesh.  Each active instance keeps a stable slot in a dense array that
+/// mirrors the instance buffer, so changes are uploaded as dirty ranges and an unchanged list
+/// costs nothing per frame.  While culling or choosing levels of detail, the buffer holds only
+/// the visible instances instead, grouped by level.  They are found again whenever the instances,
+/// the frusta, or the camera change, but the buffer is only rewritten if they differ from the
+/// last frame's; otherwise just the changed instances are written.
 pub struct InstanceListResource {
     mesh: MeshHandle,
     instances: Arena<Instance>,
+    slots: Vec<Index>,
     instance_data: Vec<InstanceData>,
+    packed_slots: Vec<u32>, // the slot of each buffered instance while packed
     instance_buffer: ResizableBuffer,
+    batches: Vec<InstanceBatch>,
+    buffered_count: u32,
+    culled_count: u32,
+    dirty: DirtySlots,
+    layout_dirty: bool, // slots were added, removed, or moved, or changed material
+    full_upload: bool, // the buffer's contents are unknown, e.g. on a new device
+    packed_for: Option<PackedView>,
+    uploaded_bytes: u64,
 }
 
 impl InstanceListResource {
@@ -115,7 +174,7 @@ impl InstanceListResource {
     }
 
     pub fn instance_buffer(&self) -> &wgpu::Buffer {
-        &self.instance_buffer.buffer()
+        self.instance_buffer.buffer()
     }
 
     pub fn instance_count(&self) -> u32 {
@@ -123,72 +182,373 @@ impl InstanceListResource {
     }
 
     pub fn buffered_instance_count(&self) -> u32 {
-        self.instance_data.len() as u32
+        self.buffered_count
     }
 
-    pub fn new(mesh: MeshHandle, device: &wgpu::Device) -> InstanceListResource {
-        let instances = Arena::new();
-        let instance_data = Vec::new();
-        // let instance_buffer = device.create_buffer_init(
-        //     &wgpu::util::BufferInitDescriptor {
-        //         label: Some("instance buffer"),
-        //         contents: bytemuck::cast_slice(&instance_data),
-        //         usage: wgpu::BufferUsages::VERTEX,
-        //     }
-        // );
-
-        let instance_buffer = ResizableBuffer::new(
-            100,
-            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            device,
-        );
+    /// Active instances left out of the last `build_instance_buffer()` by frustum culling.
+    pub fn culled_count(&self) -> u32 {
+        self.culled_count
+    }
+
+    /// Bytes written to the instance buffer by the last `build_instance_buffer()`.
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.uploaded_bytes
+    }
+
+    /// The buffered instances grouped by material, as of the last `build_instance_buffer()`.
+    pub fn batches(&self) -> &Vec<InstanceBatch> {
+        &self.batches
+    }
+
+    /// The data of the instance at `index` in the instance buffer, as of the last
+    /// `build_instance_buffer()`.
+    pub fn buffered_data(&self, index: u32) -> &InstanceData {
+        match self.packed_for {
+            Some(_) => &self.instance_data[self.packed_slots[index as usize] as usize],
+            None => &self.instance_data[index as usize],
+        }
+    }
 
+    pub fn new(mesh: MeshHandle, device: &wgpu::Device) -> InstanceListResource {
         InstanceListResource {
             mesh,
-            instances,
-            instance_data,
-            instance_buffer,
+            instances: Arena::new(),
+            slots: Vec::new(),
+            instance_data: Vec::new(),
+            packed_slots: Vec::new(),
+            instance_buffer: Self::create_instance_buffer(device),
+            batches: Vec::new(),
+            buffered_count: 0,
+            culled_count: 0,
+            dirty: DirtySlots::default(),
+            layout_dirty: false,
+            full_upload: true,
+            packed_for: None,
+            uploaded_bytes: 0,
         }
     }
 
-    pub fn add_instance(&mut self, transform: Transform) -> InstanceHandle {
-        let instance = Instance::new(transform);
+    /// Recreates the buffer on a new device; the instances are uploaded again on the next
+    /// `build_instance_buffer()`.
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        self.instance_buffer = Self::create_instance_buffer(device);
+        self.full_upload = true;
+        self.packed_for = None;
+    }
+
+    /// The capacity of the instance buffer in bytes.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.instance_buffer.capacity() as u64
+    }
+
+    /// Forgets the levels of detail the instances chose, so they choose again on the next
+    /// `build_instance_buffer()` after the mesh's levels change.
+    pub fn reset_levels(&mut self) {
+        for (_, instance) in self.instances.iter_mut() {
+            instance.lod = None;
+        }
+        self.packed_for = None;
+        self.full_upload = true;
+    }
+
+    /// Frees the instance buffer now rather than when the last reference is dropped.
+    pub fn destroy(self) {
+        self.instance_buffer.buffer().destroy();
+    }
+
+    pub fn add_instance(&mut self, material: MaterialHandle, transform: Transform) -> InstanceHandle {
+        let instance = Instance::new(material, transform);
         let index = self.instances.insert(instance);
+        self.activate(index);
         InstanceHandle::new(self.mesh(), index)
     }
 
-    pub fn update_instance(&mut self, instance: InstanceHandle, transform: Transform) {
-        self.instances.get_mut(instance.index()).unwrap().set_transform(transform);
+    pub fn update_instance(&mut self, instance: InstanceHandle, transform: Transform) -> Result<(), HandleError> {
+        let instance = self.get_instance_mut(instance)?;
+        instance.set_transform(transform);
+        if let Some(slot) = instance.slot {
+            self.instance_data[slot as usize] = instance.to_data();
+            self.dirty.insert(slot);
+        }
+        Ok(())
     }
 
-    pub fn set_instance_active(&mut self, instance: InstanceHandle, active: bool) {
-        self.instances.get_mut(instance.index()).unwrap().set_active(active);
+    pub fn set_instance_active(&mut self, instance: InstanceHandle, active: bool) -> Result<(), HandleError> {
+        let index = instance.index();
+        let instance = self.get_instance_mut(instance)?;
+        if instance.active() != active {
+            instance.set_active(active);
+            if active {
+                self.activate(index);
+            } else {
+                self.deactivate(index);
+            }
+        }
+        Ok(())
     }
 
-    pub fn remove_instance(&mut self, instance: InstanceHandle) {
+    pub fn set_instance_material(&mut self, instance: InstanceHandle, material: MaterialHandle) -> Result<(), HandleError> {
+        let instance = self.get_instance_mut(instance)?;
+        instance.set_material(material);
+        self.layout_dirty |= instance.slot.is_some();
+        Ok(())
+    }
+
+    pub fn remove_instance(&mut self, instance: InstanceHandle) -> Result<(), HandleError> {
+        self.get_instance_mut(instance)?;
+        self.deactivate(instance.index());
         self.instances.remove(instance.index());
+        Ok(())
+    }
+
+    /// The instances with their handles, including inactive ones.
+    pub fn iterate_instances(&self) -> impl Iterator<Item = (InstanceHandle, &Instance)> {
+        self.instances.iter().map(|(index, instance)| (InstanceHandle::new(self.mesh, index), instance))
+    }
+
+    fn get_instance_mut(&mut self, instance: InstanceHandle) -> Result<&mut Instance, HandleError> {
+        self.check_mesh(instance)?;
+        self.instances.get_mut(instance.index()).ok_or(HandleError::Stale(HandleKind::Instance))
+    }
+
+    fn check_mesh(&self, instance: InstanceHandle) -> Result<(), HandleError> {
+        if instance.mesh() == self.mesh {
+            Ok(())
+        } else {
+            Err(HandleError::WrongMesh)
+        }
+    }
+
+    // gives an instance the slot at the end of the dense data
+    fn activate(&mut self, index: Index) {
+        let slot = self.slots.len() as u32;
+        let instance = &mut self.instances[index];
+        instance.slot = Some(slot);
+        self.instance_data.push(instance.to_data());
+        self.slots.push(index);
+        self.dirty.insert(slot);
+        self.layout_dirty = true;
+    }
+
+    // frees an instance's slot, moving the last slot into it to keep the data dense
+    fn deactivate(&mut self, index: Index) {
+        let Some(slot) = self.instances[index].slot.take() else {
+            return;
+        };
+        self.slots.swap_remove(slot as usize);
+        self.instance_data.swap_remove(slot as usize);
+        if let Some(&moved) = self.slots.get(slot as usize) {
+            self.instances[moved].slot = Some(slot);
+            self.dirty.insert(slot);
+        }
+        self.layout_dirty = true;
+    }
+
+    /// Brings the instance buffer up to date.  With `bounds` for the mesh and a non-empty `frusta`,
+    /// only instances inside one of the frusta are packed into the buffer, and with `lod`, they
+    /// are grouped by the level of detail they choose; otherwise the buffer mirrors the slots.
+    /// Either way, only the dirty ranges are written if the buffer's layout is unchanged.
+    pub fn build_instance_buffer(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, bounds: Option<&Bounds>, frusta: &[Frustum], lod: Option<&LodView>) {
+        self.uploaded_bytes = 0;
+        let changed = self.layout_dirty || !self.dirty.is_empty();
+        let frusta = if bounds.is_some() { frusta } else { &[] };
+        if frusta.is_empty() && lod.is_none() {
+            // a packed buffer doesn't mirror the slots
+            if self.packed_for.take().is_some() {
+                self.full_upload = true;
+            }
+            if changed || self.full_upload {
+                self.build_slots(device, queue);
+            }
+        } else {
+            let eye = lod.map(|lod| (lod.eye(), lod.tan_half_fovy()));
+            let packed = self.packed_for.as_ref()
+                .is_some_and(|view| view.frusta == frusta && view.eye == eye);
+            if !packed {
+                // nor does a buffer mirroring the slots hold the packed instances
+                self.full_upload |= self.packed_for.is_none();
+                self.packed_for = Some(PackedView {
+                    frusta: frusta.to_vec(),
+                    eye,
+                });
+            }
+            if changed || !packed || self.full_upload {
+                self.build_packed(bounds, frusta, lod, device, queue);
+            }
+        }
+        self.full_upload = false;
+
+        self.dirty.clear();
+        self.layout_dirty = false;
+    }
+
+    // uploads the dense slots, writing only dirty ranges when the buffer already mirrors them
+    fn build_slots(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.layout_dirty || self.full_upload {
+            let keys = self.slots.iter().map(|index| (0, self.instances[*index].material()));
+            self.batches = Self::batch_runs(keys);
+        }
+        self.buffered_count = self.slots.len() as u32;
+        self.culled_count = 0;
+
+        let data: &[u8] = bytemuck::cast_slice(&self.instance_data);
+        if self.full_upload || data.len() as u32 > self.instance_buffer.capacity() {
+            self.instance_buffer.update(device, queue, data);
+            self.uploaded_bytes = data.len() as u64;
+            return;
+        }
+        let stride = mem::size_of::<InstanceData>();
+        for range in self.dirty.ranges(self.slots.len() as u32) {
+            let bytes = range.start as usize * stride..range.end as usize * stride;
+            self.instance_buffer.write(queue, bytes.start as u64, &data[bytes.clone()]);
+            self.uploaded_bytes += bytes.len() as u64;
+        }
+    }
+
+    // packs the visible instances, sorted by level of detail and material so each is drawn with
+    // one call.  If they are the ones already in the buffer, only the dirty ones are written.
+    fn build_packed(&mut self, bounds: Option<&Bounds>, frusta: &[Frustum], lod: Option<&LodView>, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut visible: Vec<(usize, MaterialHandle, u32)> = Vec::new();
+        for (slot, index) in self.slots.iter().enumerate() {
+            let instance = &mut self.instances[*index];
+            let model = instance.model();
+            if let (Some(bounds), false) = (bounds, frusta.is_empty()) {
+                if !frusta.iter().any(|frustum| frustum.intersects(bounds, &model)) {
+                    continue;
+                }
+            }
+            let level = match lod {
+                Some(lod) => {
+                    let level = lod.select(instance.lod, bounds, &model);
+                    instance.lod = Some(level);
+                    level
+                },
+                None => 0,
+            };
+            visible.push((level, instance.material(), slot as u32));
+        }
+        visible.sort_by_key(|(level, material, _)| (*level, *material));
+
+        self.batches = Self::batch_runs(visible.iter().map(|(level, material, _)| (*level, *material)));
+        self.buffered_count = visible.len() as u32;
+        self.culled_count = (self.slots.len() - visible.len()) as u32;
+
+        let packed_slots: Vec<u32> = visible.iter().map(|(_, _, slot)| *slot).collect();
+        if !self.full_upload && packed_slots == self.packed_slots {
+            self.write_dirty_packed(queue);
+            return;
+        }
+
+        self.packed_slots = packed_slots;
+        let packed: Vec<InstanceData> = self.packed_slots.iter()
+            .map(|slot| self.instance_data[*slot as usize])
+            .collect();
+        let data: &[u8] = bytemuck::cast_slice(&packed);
+        self.instance_buffer.update(device, queue, data);
+        self.uploaded_bytes = data.len() as u64;
+    }
+
+    // writes the packed instances whose slots are dirty, for a buffer that already holds the
+    // visible instances
+    fn write_dirty_packed(&mut self, queue: &wgpu::Queue) {
+        if self.dirty.is_empty() {
+            return;
+        }
+
+        let mut positions = vec![None; self.slots.len()];
+        for (position, slot) in self.packed_slots.iter().enumerate() {
+            positions[*slot as usize] = Some(position as u32);
+        }
+        let mut dirty = DirtySlots::default();
+        for slot in &self.dirty.slots {
+            if let Some(Some(position)) = positions.get(*slot as usize) {
+                dirty.insert(*position);
+            }
+        }
+
+        let stride = mem::size_of::<InstanceData>();
+        for range in dirty.ranges(self.packed_slots.len() as u32) {
+            let data: Vec<InstanceData> = self.packed_slots[range.start as usize..range.end as usize].iter()
+                .map(|slot| self.instance_data[*slot as usize])
+                .collect();
+            let data: &[u8] = bytemuck::cast_slice(&data);
+            self.instance_buffer.write(queue, (range.start as usize * stride) as u64, data);
+            self.uploaded_bytes += data.len() as u64;
+        }
     }
 
-    pub fn build_instance_buffer(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        // copy all instances into buffer
-        self.instance_data.clear();
-        for (_, instance) in &self.instances {
-            if instance.active() {
-                // todo: make separate list of active instances
-                self.instance_data.push(instance.to_data());
+    // groups consecutive instances with the same level of detail and material into batches
+    fn batch_runs(keys: impl Iterator<Item = (usize, MaterialHandle)>) -> Vec<InstanceBatch> {
+        let mut batches: Vec<InstanceBatch> = Vec::new();
+        for (index, (lod, material)) in keys.enumerate() {
+            let index = index as u32;
+            match batches.last_mut() {
+                Some(batch) if batch.lod == lod && batch.material == material => batch.range.end = index + 1,
+                _ => batches.push(InstanceBatch {
+                    lod,
+                    material,
+                    range: index..index + 1,
+                }),
             }
         }
+        batches
+    }
 
-        // upload buffer to gpu
-        self.instance_buffer.update(
+    fn create_instance_buffer(device: &wgpu::Device) -> ResizableBuffer {
+        ResizableBuffer::new(
+            100,
+            // copied from into the shared instance buffer for indirect drawing
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
             device,
-            &queue,
-            bytemuck::cast_slice(&self.instance_data),
-        );
+        )
     }
 }
 
-#[derive(Copy, Clone)]
+// what a packed instance buffer was built for
+struct PackedView {
+    frusta: Vec<Frustum>,
+    eye: Option<(cgmath::Vector3<f32>, f32)>,
+}
+
+/// Slots written since the last upload, coalesced into ranges when uploading.
+#[derive(Default)]
+struct DirtySlots {
+    slots: Vec<u32>,
+}
+
+impl DirtySlots {
+    // dirty ranges closer than this are written together, trading bytes for fewer writes
+    const MERGE_GAP: u32 = 16;
+
+    fn insert(&mut self, slot: u32) {
+        self.slots.push(slot);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+    }
+
+    // sorted, disjoint ranges covering the dirty slots below `len`
+    fn ranges(&mut self, len: u32) -> Vec<Range<u32>> {
+        self.slots.sort_unstable();
+        self.slots.dedup();
+
+        let mut ranges: Vec<Range<u32>> = Vec::new();
+        for &slot in self.slots.iter().take_while(|slot| **slot < len) {
+            match ranges.last_mut() {
+                Some(range) if slot <= range.end + Self::MERGE_GAP => range.end = slot + 1,
+                _ => ranges.push(slot..slot + 1),
+            }
+        }
+        ranges
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct InstanceHandle {
     mesh: MeshHandle,
     index: Index,
@@ -209,4 +569,24 @@ impl InstanceHandle {
             index,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirty_ranges() {
+        let mut dirty = DirtySlots::default();
+        assert!(dirty.ranges(100).is_empty());
+
+        for slot in [40, 3, 2, 3, 20, 90, 99] {
+            dirty.insert(slot);
+        }
+        // 2 and 3 merge with 20, which is within the gap; slots past the end are dropped
+        assert_eq!(dirty.ranges(95), vec![2..21, 40..41, 90..91]);
+
+        dirty.clear();
+        assert!(dirty.is_empty());
+    }
+}
@@ -1,63 +1,424 @@
-use crate::color_vertex::ColorVertex;
+use std::ops::Range;
 
-use super::resizable_buffer::ResizableBuffer;
+use generational_arena::{Arena, Index};
+use wgpu::util::DeviceExt;
 
+use crate::{color_vertex::ColorVertex, scene::line_set::LineSet};
+
+use super::{create_pipeline, error::{HandleError, HandleKind}, resizable_buffer::ResizableBuffer, tone_mapping::HDR_FORMAT};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LineSetHandle(Index);
+
+/// Immediate mode lines, drawn for one frame, and line sets, which persist until removed.  Both
+/// are drawn as quads of any thickness, optionally on top.
 pub struct LineRenderer {
-    vertices: Vec<ColorVertex>,
+    immediate_lines: ImmediateLines,
     buffer: ResizableBuffer,
-    count: u32,
+
+    line_sets: Arena<LineSetResource>,
+    viewport: [f32; 2],
+    viewport_buffer: wgpu::Buffer,
+    viewport_bind_group_layout: wgpu::BindGroupLayout,
+    viewport_bind_group: wgpu::BindGroup,
+    depth_tested_pipeline: wgpu::RenderPipeline,
+    on_top_pipeline: wgpu::RenderPipeline,
 }
 
 impl LineRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
-        let vertices = Vec::new();
-        
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
         let buffer = ResizableBuffer::new(
-            32,
+            std::mem::size_of::<LineData>() as u32,
             wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             device,
         );
 
-        let count = vertices.len() as u32;
+        let viewport = [0.0; 2];
+        let viewport_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("line viewport buffer"),
+            contents: bytemuck::cast_slice(&[0.0_f32; 4]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let viewport_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("line viewport bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let viewport_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("line viewport bind group"),
+            layout: &viewport_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: viewport_buffer.as_entire_binding(),
+            }],
+        });
+
+        let [depth_tested_pipeline, on_top_pipeline] = [true, false].map(|depth_test| Self::create_line_pipeline(
+            device,
+            camera_bind_group_layout,
+            &viewport_bind_group_layout,
+            depth_format,
+            sample_count,
+            depth_test,
+        ));
 
         Self {
-            vertices,
+            immediate_lines: ImmediateLines::default(),
             buffer,
-            count,
+
+            line_sets: Arena::new(),
+            viewport,
+            viewport_buffer,
+            viewport_bind_group_layout,
+            viewport_bind_group,
+            depth_tested_pipeline,
+            on_top_pipeline,
         }
     }
 
-    pub fn draw_line(&mut self, start: ColorVertex, end: ColorVertex) {
-        self.vertices.push(start);
-        self.vertices.push(end);
+    /// Recreates the buffers and pipelines on a new device, keeping lines drawn since the last
+    /// update and uploading the line sets again.
+    pub fn recreate(
+        &mut self,
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) {
+        let immediate_lines = std::mem::take(&mut self.immediate_lines);
+        let mut line_sets = std::mem::take(&mut self.line_sets);
+        *self = Self::new(device, camera_bind_group_layout, depth_format, sample_count);
+        self.immediate_lines.pending = immediate_lines.pending;
+        self.immediate_lines.pending_on_top = immediate_lines.pending_on_top;
+
+        for (_, line_set) in line_sets.iter_mut() {
+            *line_set = LineSetResource::new(&line_set.line_set, device);
+        }
+        self.line_sets = line_sets;
+    }
+
+    /// Recreates the line pipelines for targets with a different msaa sample count.
+    pub fn set_sample_count(
+        &mut self,
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) {
+        [self.depth_tested_pipeline, self.on_top_pipeline] = [true, false].map(|depth_test| Self::create_line_pipeline(
+            device,
+            camera_bind_group_layout,
+            &self.viewport_bind_group_layout,
+            depth_format,
+            sample_count,
+            depth_test,
+        ));
+    }
+
+    /// Drops lines drawn since the last update, e.g. when a frame is skipped.
+    pub fn clear(&mut self) {
+        self.immediate_lines.clear();
+    }
+
+    pub fn draw_line(&mut self, start: ColorVertex, end: ColorVertex, thickness: f32, on_top: bool) {
+        self.immediate_lines.draw_line(LineData { start, end, thickness }, on_top);
     }
 
+    /// Uploads the lines drawn since the last update, which replace the last frame's lines even
+    /// if there are none.
     pub fn update_buffer_and_clear(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
     ) {
-        if self.vertices.len() > 0 {
+        let lines = self.immediate_lines.next_frame();
+        if !lines.is_empty() {
             self.buffer.update(
                 device,
                 queue,
-                bytemuck::cast_slice(&self.vertices),
+                bytemuck::cast_slice(lines),
             );
-            self.count = self.vertices.len() as u32;
-            self.vertices.clear();
         }
+    }
+
+    /// Sets the size of the target in pixels, which line thicknesses are measured in.
+    pub fn update_viewport(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        let viewport = [width as f32, height as f32];
+        if viewport != self.viewport {
+            queue.write_buffer(&self.viewport_buffer, 0, bytemuck::cast_slice(&[viewport[0], viewport[1], 0.0, 0.0]));
+            self.viewport = viewport;
+        }
+    }
+
+    // ================================================================
+    // line sets
+    // ================================================================
+    pub fn line_set_count(&self) -> usize {
+        self.line_sets.len()
+    }
 
+    pub fn add_line_set(&mut self, line_set: &LineSet, device: &wgpu::Device) -> LineSetHandle {
+        LineSetHandle(self.line_sets.insert(LineSetResource::new(line_set, device)))
     }
 
+    /// Replaces the lines and options of a set, uploading its lines again.
+    pub fn update_line_set(&mut self, handle: LineSetHandle, line_set: &LineSet, device: &wgpu::Device) -> Result<(), HandleError> {
+        let LineSetHandle(index) = handle;
+        let resource = self.line_sets.get_mut(index).ok_or(HandleError::Stale(HandleKind::LineSet))?;
+        *resource = LineSetResource::new(line_set, device);
+        Ok(())
+    }
+
+    pub fn remove_line_set(&mut self, handle: LineSetHandle) -> Result<(), HandleError> {
+        let LineSetHandle(index) = handle;
+        self.line_sets.remove(index).ok_or(HandleError::Stale(HandleKind::LineSet))?;
+        Ok(())
+    }
+
+    /// Draws the depth-tested lines, then the ones on top, with the immediate mode lines of each
+    /// before the line sets.
     pub fn render(
         &self,
         render_pass: &mut wgpu::RenderPass,
         camera_bind_group: &wgpu::BindGroup,
     ) {
-        if self.buffer.size() > 0 {
-            render_pass.set_vertex_buffer(0, self.buffer.buffer().slice(0..self.buffer.size() as u64));
-            render_pass.set_bind_group(0, camera_bind_group, &[]);
-            render_pass.draw(0..self.count, 0..1);
+        let immediate_lines = self.immediate_lines.drawn();
+        if immediate_lines.iter().all(|lines| lines.is_empty()) && self.line_sets.is_empty() {
+            return;
+        }
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.viewport_bind_group, &[]);
+
+        let line_size = std::mem::size_of::<LineData>() as u64;
+        let pipelines = [(&self.depth_tested_pipeline, false), (&self.on_top_pipeline, true)];
+        for ((pipeline, on_top), lines) in pipelines.into_iter().zip(immediate_lines) {
+            render_pass.set_pipeline(pipeline);
+            if !lines.is_empty() {
+                let bytes = lines.start as u64 * line_size..lines.end as u64 * line_size;
+                render_pass.set_vertex_buffer(0, self.buffer.buffer().slice(bytes));
+                render_pass.draw(0..6, 0..lines.len() as u32);
+            }
+            for (_, line_set) in self.line_sets.iter() {
+                if line_set.line_set.on_top() != on_top {
+                    continue;
+                }
+                if let Some(buffer) = &line_set.buffer {
+                    render_pass.set_vertex_buffer(0, buffer.slice(..));
+                    render_pass.draw(0..6, 0..line_set.line_set.lines().len() as u32);
+                }
+            }
         }
     }
-}
\ No newline at end of file
+
+    fn create_line_pipeline(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        viewport_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        depth_test: bool,
+    ) -> wgpu::RenderPipeline {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("line pipeline layout"),
+            bind_group_layouts: &[camera_bind_group_layout, viewport_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("line_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../line_shader.wgsl").into()),
+        });
+
+        // the quads face either way depending on the line's direction on screen
+        let primitive = wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            .. Default::default()
+        };
+
+        create_pipeline::create_render_pipeline(
+            device,
+            &layout,
+            HDR_FORMAT,
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+            Some(create_pipeline::depth_stencil_state(depth_format, depth_test)),
+            &[LineData::vertex_buffer_layout()],
+            &shader,
+            primitive,
+            sample_count,
+        )
+    }
+}
+
+
+// the cpu side of immediate mode lines: those drawn since the last frame, kept apart by whether
+// they are on top, and those of the frame being drawn, the depth-tested ones first
+#[derive(Default)]
+struct ImmediateLines {
+    pending: Vec<LineData>,
+    pending_on_top: Vec<LineData>,
+    drawn: Vec<LineData>,
+    drawn_depth_tested: usize,
+}
+
+impl ImmediateLines {
+    fn draw_line(&mut self, line: LineData, on_top: bool) {
+        if on_top {
+            self.pending_on_top.push(line);
+        } else {
+            self.pending.push(line);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pending.clear();
+        self.pending_on_top.clear();
+    }
+
+    // starts a frame with the pending lines, returning them
+    fn next_frame(&mut self) -> &[LineData] {
+        self.drawn.clear();
+        self.drawn.append(&mut self.pending);
+        self.drawn_depth_tested = self.drawn.len();
+        self.drawn.append(&mut self.pending_on_top);
+        &self.drawn
+    }
+
+    // the ranges of the current frame's lines that are depth-tested and on top
+    fn drawn(&self) -> [Range<usize>; 2] {
+        [0..self.drawn_depth_tested, self.drawn_depth_tested..self.drawn.len()]
+    }
+}
+
+
+// a line set with its lines uploaded, one instance per line
+struct LineSetResource {
+    line_set: LineSet,
+    buffer: Option<wgpu::Buffer>, // none without lines
+}
+
+impl LineSetResource {
+    fn new(line_set: &LineSet, device: &wgpu::Device) -> LineSetResource {
+        let data: Vec<LineData> = line_set.lines().iter()
+            .map(|(start, end)| LineData {
+                start: *start,
+                end: *end,
+                thickness: line_set.thickness(),
+            })
+            .collect();
+        let buffer = (!data.is_empty()).then(|| device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("line set buffer"),
+            contents: bytemuck::cast_slice(&data),
+            usage: wgpu::BufferUsages::VERTEX,
+        }));
+
+        LineSetResource {
+            line_set: line_set.clone(),
+            buffer,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineData {
+    start: ColorVertex,
+    end: ColorVertex,
+    thickness: f32,
+}
+
+impl LineData {
+    fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x4,
+            2 => Float32x3,
+            3 => Float32x4,
+            4 => Float32,
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineData>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::mesh::Vertex;
+    use super::*;
+
+    fn line(x: f32) -> LineData {
+        LineData {
+            start: ColorVertex::new_white(Vector3::new(x, 0.0, 0.0)),
+            end: ColorVertex::new_white(Vector3::new(x, 1.0, 0.0)),
+            thickness: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_immediate_lines_last_one_frame() {
+        let mut lines = ImmediateLines::default();
+        lines.draw_line(line(0.0), false);
+        lines.draw_line(line(0.0), false);
+        assert_eq!(lines.drawn(), [0..0, 0..0]);
+
+        assert_eq!(lines.next_frame().len(), 2);
+        assert_eq!(lines.drawn(), [0..2, 2..2]);
+
+        // a frame without lines draws none, rather than the last frame's again
+        assert!(lines.next_frame().is_empty());
+        assert_eq!(lines.drawn(), [0..0, 0..0]);
+
+        // fewer lines than before replace all of the old ones
+        lines.draw_line(line(1.0), false);
+        let drawn = lines.next_frame();
+        assert_eq!(drawn.len(), 1);
+        assert_eq!(drawn[0].start.position(), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(lines.drawn(), [0..1, 1..1]);
+    }
+
+    #[test]
+    fn test_immediate_lines_on_top_last() {
+        let mut lines = ImmediateLines::default();
+        lines.draw_line(line(0.0), true);
+        lines.draw_line(line(1.0), false);
+        lines.draw_line(line(2.0), true);
+
+        let drawn = lines.next_frame();
+        let xs: Vec<f32> = drawn.iter().map(|line| line.start.position().x).collect();
+        assert_eq!(xs, [1.0, 0.0, 2.0]);
+        assert_eq!(lines.drawn(), [0..1, 1..3]);
+    }
+
+    #[test]
+    fn test_immediate_lines_clear() {
+        let mut lines = ImmediateLines::default();
+        lines.draw_line(line(0.0), false);
+        lines.next_frame();
+
+        // lines cleared before a frame, e.g. for a skipped frame, are never drawn
+        lines.draw_line(line(0.0), false);
+        lines.draw_line(line(0.0), true);
+        lines.clear();
+        assert!(lines.next_frame().is_empty());
+        assert_eq!(lines.drawn(), [0..0, 0..0]);
+    }
+}
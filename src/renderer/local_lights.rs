@@ -0,0 +1,248 @@
+use std::mem;
+
+use cgmath::InnerSpace;
+use generational_arena::{Arena, Index};
+
+use crate::scene::light::{PointLight, SpotLight};
+
+use super::error::{HandleError, HandleKind};
+
+/// The most point lights and the most spot lights drawn at once.  Lights beyond these are kept but
+/// not drawn.  The lights live in a uniform buffer rather than a storage buffer so they work on
+/// WebGL.
+pub const MAX_POINT_LIGHTS: usize = 64;
+pub const MAX_SPOT_LIGHTS: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PointLightHandle(Index);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpotLightHandle(Index);
+
+/// Point and spot lights, uploaded to the gpu as arrays with counts when they change.
+pub struct LocalLightsResource {
+    point_lights: Arena<PointLight>,
+    spot_lights: Arena<SpotLight>,
+    buffer: wgpu::Buffer,
+    dirty: bool,
+}
+
+impl LocalLightsResource {
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn point_light_count(&self) -> usize {
+        self.point_lights.len()
+    }
+
+    pub fn spot_light_count(&self) -> usize {
+        self.spot_lights.len()
+    }
+
+    pub fn new(device: &wgpu::Device) -> LocalLightsResource {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("local lights buffer"),
+            size: mem::size_of::<LocalLightsData>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        LocalLightsResource {
+            point_lights: Arena::new(),
+            spot_lights: Arena::new(),
+            buffer,
+            dirty: true,
+        }
+    }
+
+    /// Recreates the buffer on a new device; the lights are uploaded again on the next `upload()`.
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        *self = LocalLightsResource {
+            point_lights: mem::take(&mut self.point_lights),
+            spot_lights: mem::take(&mut self.spot_lights),
+            ..Self::new(device)
+        };
+    }
+
+    // ================================================================
+    // point lights
+    // ================================================================
+    pub fn add_point_light(&mut self, light: &PointLight) -> PointLightHandle {
+        self.dirty = true;
+        PointLightHandle(self.point_lights.insert(*light))
+    }
+
+    pub fn update_point_light(&mut self, handle: PointLightHandle, light: &PointLight) -> Result<(), HandleError> {
+        let PointLightHandle(index) = handle;
+        let point_light = self.point_lights.get_mut(index).ok_or(HandleError::Stale(HandleKind::PointLight))?;
+        *point_light = *light;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn remove_point_light(&mut self, handle: PointLightHandle) -> Result<(), HandleError> {
+        let PointLightHandle(index) = handle;
+        self.point_lights.remove(index).ok_or(HandleError::Stale(HandleKind::PointLight))?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    // ================================================================
+    // spot lights
+    // ================================================================
+    pub fn add_spot_light(&mut self, light: &SpotLight) -> SpotLightHandle {
+        self.dirty = true;
+        SpotLightHandle(self.spot_lights.insert(*light))
+    }
+
+    pub fn update_spot_light(&mut self, handle: SpotLightHandle, light: &SpotLight) -> Result<(), HandleError> {
+        let SpotLightHandle(index) = handle;
+        let spot_light = self.spot_lights.get_mut(index).ok_or(HandleError::Stale(HandleKind::SpotLight))?;
+        *spot_light = *light;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn remove_spot_light(&mut self, handle: SpotLightHandle) -> Result<(), HandleError> {
+        let SpotLightHandle(index) = handle;
+        self.spot_lights.remove(index).ok_or(HandleError::Stale(HandleKind::SpotLight))?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    // ================================================================
+    // upload
+    // ================================================================
+    /// Uploads the lights if any have changed since the last upload.
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        if self.point_lights.len() > MAX_POINT_LIGHTS || self.spot_lights.len() > MAX_SPOT_LIGHTS {
+            log::warn!(
+                "{} point lights and {} spot lights exceed the limits of {} and {}; drawing only the first",
+                self.point_lights.len(),
+                self.spot_lights.len(),
+                MAX_POINT_LIGHTS,
+                MAX_SPOT_LIGHTS,
+            );
+        }
+
+        let data = LocalLightsData::new(
+            self.point_lights.iter().map(|(_, light)| light),
+            self.spot_lights.iter().map(|(_, light)| light),
+        );
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[data]));
+    }
+}
+
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightData {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    _padding: f32,
+}
+
+impl PointLightData {
+    pub fn new(light: &PointLight) -> PointLightData {
+        PointLightData {
+            position: light.position().into(),
+            range: light.range(),
+            color: (light.intensity() * light.color()).into(),
+            _padding: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpotLightData {
+    position: [f32; 3],
+    range: f32,
+    direction: [f32; 3],
+    cos_outer_angle: f32,
+    color: [f32; 3],
+    cos_inner_angle: f32,
+}
+
+impl SpotLightData {
+    pub fn new(light: &SpotLight) -> SpotLightData {
+        SpotLightData {
+            position: light.position().into(),
+            range: light.range(),
+            direction: light.direction().normalize().into(),
+            cos_outer_angle: light.outer_angle().cos(),
+            color: (light.intensity() * light.color()).into(),
+            cos_inner_angle: light.inner_angle().cos(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LocalLightsData {
+    point_light_count: u32,
+    spot_light_count: u32,
+    _padding: [u32; 2],
+    point_lights: [PointLightData; MAX_POINT_LIGHTS],
+    spot_lights: [SpotLightData; MAX_SPOT_LIGHTS],
+}
+
+impl LocalLightsData {
+    pub fn new<'a>(
+        point_lights: impl Iterator<Item = &'a PointLight>,
+        spot_lights: impl Iterator<Item = &'a SpotLight>,
+    ) -> LocalLightsData {
+        let mut data: LocalLightsData = bytemuck::Zeroable::zeroed();
+
+        for (slot, light) in data.point_lights.iter_mut().zip(point_lights) {
+            *slot = PointLightData::new(light);
+            data.point_light_count += 1;
+        }
+        for (slot, light) in data.spot_lights.iter_mut().zip(spot_lights) {
+            *slot = SpotLightData::new(light);
+            data.spot_light_count += 1;
+        }
+
+        data
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::*;
+
+    #[test]
+    fn test_local_lights_data() {
+        let point_lights = vec![PointLight::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(1.0, 0.5, 0.0), 2.0, 5.0); MAX_POINT_LIGHTS + 3];
+        let spot_lights = [SpotLight::new(
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, -2.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            1.0,
+            4.0,
+            0.0,
+            std::f32::consts::FRAC_PI_2,
+        )];
+
+        let data = LocalLightsData::new(point_lights.iter(), spot_lights.iter());
+
+        // lights beyond the limit are dropped
+        assert_eq!(data.point_light_count, MAX_POINT_LIGHTS as u32);
+        assert_eq!(data.spot_light_count, 1);
+
+        assert_eq!(data.point_lights[0].color, [2.0, 1.0, 0.0]);
+        assert_eq!(data.spot_lights[0].direction, [0.0, 0.0, -1.0]);
+        assert!(data.spot_lights[0].cos_outer_angle.abs() < 1e-6);
+        assert_eq!(data.spot_lights[0].cos_inner_angle, 1.0);
+    }
+}
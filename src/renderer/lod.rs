@@ -0,0 +1,208 @@
+use std::fmt;
+
+use cgmath::{InnerSpace, Matrix4, Vector3};
+
+use crate::scene::camera::Camera;
+
+use super::culling::{max_scale, Bounds};
+
+/// How a mesh with levels of detail chooses a level for each instance.  Level 0 is the finest;
+/// each threshold is where the next coarser level takes over, so there is one fewer threshold
+/// than levels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodSettings {
+    metric: LodMetric,
+    thresholds: Vec<f32>,
+    hysteresis: f32,
+}
+
+/// What `LodSettings` thresholds are compared against.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LodMetric {
+    /// The distance from the camera's eye to the center of the instance's bounds, with
+    /// increasing thresholds.
+    Distance,
+    /// The height of the instance's bounding sphere as a fraction of the screen's, with
+    /// decreasing thresholds.
+    ScreenSize,
+}
+
+impl LodSettings {
+    pub fn metric(&self) -> LodMetric {
+        self.metric
+    }
+
+    pub fn thresholds(&self) -> &Vec<f32> {
+        &self.thresholds
+    }
+
+    pub fn hysteresis(&self) -> f32 {
+        self.hysteresis
+    }
+
+    /// Switches to level `i + 1` beyond `thresholds[i]` from the eye.
+    pub fn distance(thresholds: Vec<f32>) -> LodSettings {
+        Self::new(LodMetric::Distance, thresholds)
+    }
+
+    /// Switches to level `i + 1` when the instance covers less than `thresholds[i]` of the
+    /// screen's height.
+    pub fn screen_size(thresholds: Vec<f32>) -> LodSettings {
+        Self::new(LodMetric::ScreenSize, thresholds)
+    }
+
+    pub fn new(metric: LodMetric, thresholds: Vec<f32>) -> LodSettings {
+        LodSettings {
+            metric,
+            thresholds,
+            hysteresis: 0.0,
+        }
+    }
+
+    /// Keeps an instance at its level until it is past a threshold by this fraction of it, so
+    /// instances near a threshold don't flicker between levels.
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// The level for an instance at `distance` from the eye covering `screen_size` of the
+    /// screen's height, given its level last frame, if it had one.
+    pub fn select(&self, current: Option<usize>, distance: f32, screen_size: f32) -> usize {
+        let value = match self.metric {
+            LodMetric::Distance => distance,
+            LodMetric::ScreenSize => screen_size,
+        };
+        // whether `value` is past `threshold` towards coarser levels, or finer ones
+        let coarser = |threshold: f32, margin: f32| match self.metric {
+            LodMetric::Distance => value > threshold * (1.0 + margin),
+            LodMetric::ScreenSize => value < threshold * (1.0 - margin),
+        };
+        let finer = |threshold: f32, margin: f32| match self.metric {
+            LodMetric::Distance => value < threshold * (1.0 - margin),
+            LodMetric::ScreenSize => value > threshold * (1.0 + margin),
+        };
+
+        let (mut level, margin) = match current {
+            Some(level) => (level.min(self.thresholds.len()), self.hysteresis),
+            None => (0, 0.0),
+        };
+        while level < self.thresholds.len() && coarser(self.thresholds[level], margin) {
+            level += 1;
+        }
+        while level > 0 && finer(self.thresholds[level - 1], margin) {
+            level -= 1;
+        }
+        level
+    }
+
+    /// Checks that there is at least one level, and one threshold between each pair of them.
+    pub fn check_levels(&self, levels: usize) -> Result<(), LodError> {
+        if levels == 0 {
+            return Err(LodError::NoLevels);
+        }
+        if self.thresholds.len() != levels - 1 {
+            return Err(LodError::ThresholdCount { levels, thresholds: self.thresholds.len() });
+        }
+        Ok(())
+    }
+}
+
+
+/// An error from giving a mesh levels of detail that `LodSettings` can't choose between.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LodError {
+    /// No levels were given.
+    NoLevels,
+    /// There must be one threshold fewer than levels.
+    ThresholdCount { levels: usize, thresholds: usize },
+}
+
+impl fmt::Display for LodError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LodError::NoLevels => write!(f, "a mesh needs at least one level of detail"),
+            LodError::ThresholdCount { levels, thresholds } => write!(f, "{} levels of detail need {} thresholds, not {}", levels, levels - 1, thresholds),
+        }
+    }
+}
+
+impl std::error::Error for LodError {}
+
+
+/// The camera that instances of a mesh with levels of detail choose their levels from.
+#[derive(Debug, Copy, Clone)]
+pub struct LodView<'a> {
+    settings: &'a LodSettings,
+    eye: Vector3<f32>,
+    tan_half_fovy: f32,
+}
+
+impl<'a> LodView<'a> {
+    pub fn eye(&self) -> Vector3<f32> {
+        self.eye
+    }
+
+    pub fn tan_half_fovy(&self) -> f32 {
+        self.tan_half_fovy
+    }
+
+    pub fn new(settings: &'a LodSettings, camera: &Camera) -> LodView<'a> {
+        LodView {
+            settings,
+            eye: camera.eye(),
+            tan_half_fovy: (camera.fovy() / 2.0).tan(),
+        }
+    }
+
+    /// The level for an instance transformed by `model`, whose mesh has model-space `bounds`.
+    pub fn select(&self, current: Option<usize>, bounds: Option<&Bounds>, model: &Matrix4<f32>) -> usize {
+        let (center, radius) = match bounds {
+            Some(bounds) => ((model * bounds.center().extend(1.0)).truncate(), bounds.radius() * max_scale(model)),
+            None => (model.w.truncate(), 0.0),
+        };
+        let distance = (center - self.eye).magnitude();
+        let screen_size = if distance > 0.0 { radius / (distance * self.tan_half_fovy) } else { f32::INFINITY };
+        self.settings.select(current, distance, screen_size)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let distance = LodSettings::distance(vec![10.0, 20.0]);
+        assert_eq!(distance.select(None, 5.0, 0.0), 0);
+        assert_eq!(distance.select(None, 15.0, 0.0), 1);
+        assert_eq!(distance.select(None, 50.0, 0.0), 2);
+        assert_eq!(distance.select(Some(2), 5.0, 0.0), 0);
+
+        let screen_size = LodSettings::screen_size(vec![0.5, 0.1]);
+        assert_eq!(screen_size.select(None, 0.0, 0.8), 0);
+        assert_eq!(screen_size.select(None, 0.0, 0.2), 1);
+        assert_eq!(screen_size.select(None, 0.0, 0.05), 2);
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let settings = LodSettings::distance(vec![10.0]).with_hysteresis(0.1);
+        // an instance needs to be 10% past the threshold to change level
+        assert_eq!(settings.select(Some(0), 10.5, 0.0), 0);
+        assert_eq!(settings.select(Some(0), 11.5, 0.0), 1);
+        assert_eq!(settings.select(Some(1), 9.5, 0.0), 1);
+        assert_eq!(settings.select(Some(1), 8.5, 0.0), 0);
+        // without a level yet, the thresholds are exact
+        assert_eq!(settings.select(None, 10.5, 0.0), 1);
+    }
+
+    #[test]
+    fn test_check_levels() {
+        let settings = LodSettings::distance(vec![10.0, 20.0]);
+        assert_eq!(settings.check_levels(3), Ok(()));
+        assert_eq!(settings.check_levels(2), Err(LodError::ThresholdCount { levels: 2, thresholds: 2 }));
+        assert_eq!(settings.check_levels(0), Err(LodError::NoLevels));
+    }
+}
@@ -0,0 +1,323 @@
+use std::fmt;
+
+use super::tone_mapping::HDR_FORMAT;
+
+/// Sample counts that can be requested with `Renderer::set_sample_count()`, if the adapter
+/// supports them.
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+/// What msaa the adapter supports.
+#[derive(Debug, Clone)]
+pub struct MultisampleSupport {
+    sample_counts: Vec<u32>,
+    depth_readable: bool,
+}
+
+impl MultisampleSupport {
+    /// The counts from `SAMPLE_COUNTS` usable with every format in `formats`, always including 1.
+    pub fn sample_counts(&self) -> &Vec<u32> {
+        &self.sample_counts
+    }
+
+    /// Whether a multisampled depth buffer can be read by shaders, to resolve it for
+    /// post-processing.
+    pub fn depth_readable(&self) -> bool {
+        self.depth_readable
+    }
+
+    /// Counts other than 1 and 4 need the device to have been created with
+    /// `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`.
+    pub fn new(adapter: &wgpu::Adapter, device: &wgpu::Device, formats: &[wgpu::TextureFormat]) -> MultisampleSupport {
+        let adapter_specific = device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+
+        let sample_counts = SAMPLE_COUNTS.into_iter()
+            .filter(|count| *count == 1 || *count == 4 || adapter_specific)
+            .filter(|count| formats.iter().all(|format| {
+                let flags = adapter.get_texture_format_features(*format).flags;
+                let resolvable = *count == 1
+                    || format.is_depth_stencil_format()
+                    || flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+                flags.sample_count_supported(*count) && resolvable
+            }))
+            .collect();
+
+        // on gl, a multisampled depth texture that can be bound leaves the scene's framebuffer
+        // incomplete, so nothing is drawn
+        let depth_readable = adapter.get_info().backend != wgpu::Backend::Gl;
+
+        MultisampleSupport {
+            sample_counts,
+            depth_readable,
+        }
+    }
+
+    // simulates an adapter without msaa, for testing fallbacks
+    #[cfg(test)]
+    pub fn without_msaa(mut self) -> MultisampleSupport {
+        self.sample_counts = vec![1];
+        self
+    }
+}
+
+
+/// An error from requesting a sample count the adapter cannot render with.
+#[derive(Debug, Clone)]
+pub struct UnsupportedSampleCount {
+    sample_count: u32,
+    supported: Vec<u32>,
+}
+
+impl UnsupportedSampleCount {
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn supported(&self) -> &Vec<u32> {
+        &self.supported
+    }
+
+    pub fn new(sample_count: u32, supported: Vec<u32>) -> UnsupportedSampleCount {
+        UnsupportedSampleCount {
+            sample_count,
+            supported,
+        }
+    }
+}
+
+impl fmt::Display for UnsupportedSampleCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported msaa sample count {}; supported counts are {:?}", self.sample_count, self.supported)
+    }
+}
+
+impl std::error::Error for UnsupportedSampleCount {}
+
+
+/// The multisampled color targets that the scene and output passes draw into and resolve from
+/// when msaa is enabled.  The depth buffer lives with the other resources.
+pub struct MultisampleTargets {
+    sample_count: u32,
+    depth_readable: bool,
+    scene: Option<(wgpu::Texture, wgpu::TextureView)>,
+    output: Option<(wgpu::Texture, wgpu::TextureView)>,
+    depth_resolve: Option<DepthResolve>,
+}
+
+impl MultisampleTargets {
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// The multisampled hdr target the scene is drawn into, or `None` without msaa.
+    pub fn scene_view(&self) -> Option<&wgpu::TextureView> {
+        self.scene.as_ref().map(|(_, view)| view)
+    }
+
+    /// The multisampled target the output pass draws into before resolving into the surface, or
+    /// `None` without msaa.
+    pub fn output_view(&self) -> Option<&wgpu::TextureView> {
+        self.output.as_ref().map(|(_, view)| view)
+    }
+
+    /// Whether the depth buffer can be bound as a texture.
+    pub fn depth_readable(&self) -> bool {
+        self.depth_readable
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        support: &MultisampleSupport,
+    ) -> MultisampleTargets {
+        let depth_readable = sample_count == 1 || support.depth_readable();
+
+        if sample_count == 1 {
+            return MultisampleTargets {
+                sample_count,
+                depth_readable,
+                scene: None,
+                output: None,
+                depth_resolve: None,
+            };
+        }
+
+        MultisampleTargets {
+            sample_count,
+            depth_readable,
+            scene: Some(Self::create_target(device, config, HDR_FORMAT, sample_count)),
+            output: Some(Self::create_target(device, config, config.format, sample_count)),
+            depth_resolve: Some(DepthResolve::new(device, config)),
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.scene = self.scene.as_ref().map(|_| Self::create_target(device, config, HDR_FORMAT, self.sample_count));
+        self.output = self.output.as_ref().map(|_| Self::create_target(device, config, config.format, self.sample_count));
+        self.depth_resolve = self.depth_resolve.as_ref().map(|_| DepthResolve::new(device, config));
+    }
+
+    /// Copies the first sample of the multisampled depth buffer into a single-sampled texture and
+    /// returns its view, or `None` without msaa, when the depth buffer can be read directly.  If
+    /// the depth buffer isn't readable, the texture is left at the far plane.
+    pub fn resolve_depth(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_view: &wgpu::TextureView,
+    ) -> Option<&wgpu::TextureView> {
+        let depth_resolve = self.depth_resolve.as_ref()?;
+        depth_resolve.render(device, encoder, Some(depth_view).filter(|_| self.depth_readable));
+        Some(&depth_resolve.view)
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("multisampled target"),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        (texture, view)
+    }
+}
+
+
+// depth can't be resolved by a render pass, so a fullscreen pass copies it instead
+struct DepthResolve {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl DepthResolve {
+    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> DepthResolve {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("resolved depth texture"),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: true,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("depth resolve bind group layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("depth_resolve_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../depth_resolve_shader.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("depth resolve pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("depth resolve pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        DepthResolve {
+            _texture: texture,
+            view,
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, depth_view: Option<&wgpu::TextureView>) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("depth resolve pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        let Some(depth_view) = depth_view else {
+            return;
+        };
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(depth_view),
+                },
+            ],
+            label: Some("depth resolve bind group"),
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
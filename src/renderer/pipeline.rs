@@ -1,22 +1,441 @@
-// pub struct Pipeline {
-//     pipeline: wgpu::RenderPipeline,
-// }
-
-// impl Pipeline {
-//     pub fn new() -> Self {
-
-//     }
-// }
-
-// pub struct PipelineBuilder {
-//     layout: wgpu::PipelineLayout,
-//     color_format: wgpu::TextureFormat,
-//     depth_format: Option<wgpu::TextureFormat>,
-//     vertex_layouts: Vec<usize>,
-//     shader: wgpu::ShaderModule,
-//     primitive: wgpu::PrimitiveState,
-// }
-
-// impl PipelineBuilder {
-//     pub fn new()
-// }
\ No newline at end of file
+use std::{collections::HashMap, fmt, path::{Path, PathBuf}, time::SystemTime};
+
+use crate::{mesh::Vertex, scene::material::ShadingModel};
+
+use super::instances::InstanceData;
+
+
+/// Source of the built-in lit shader for `ColorNormalVertex`, for building variants of it.
+pub const COLOR_NORMAL_SHADER: &str = include_str!("../shader.wgsl");
+/// Source of the built-in lit shader for `TexturedNormalVertex`.
+pub const TEXTURED_NORMAL_SHADER: &str = include_str!("../textured_shader.wgsl");
+/// Source of the built-in unlit shader for `ColorVertex`.
+pub const UNLIT_COLOR_SHADER: &str = include_str!("../unlit_shader.wgsl");
+
+
+/// A pipeline for meshes whose vertices have layout `vertex_layout`.  Bind groups beyond the
+/// camera and material groups are owned by the pipeline and bound whenever it is used.  A
+/// transparent variant is built alongside it for instances with blended materials.
+///
+/// The builder is kept so the pipeline can be recompiled when its shader file changes.
+pub struct PipelineResource {
+    pipeline: wgpu::RenderPipeline,
+    transparent_pipeline: wgpu::RenderPipeline,
+    layout: wgpu::PipelineLayout,
+    builder: PipelineBuilder,
+    color_format: wgpu::TextureFormat,
+    depth_format: Option<wgpu::TextureFormat>,
+    sample_count: u32,
+    shader_modified: Option<SystemTime>,
+    shader_missing: bool,
+}
+
+impl PipelineResource {
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    /// The variant used in the transparent pass, which blends by alpha and tests depth without
+    /// writing it.
+    pub fn transparent_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.transparent_pipeline
+    }
+
+    pub fn vertex_layout(&self) -> &wgpu::VertexBufferLayout<'static> {
+        &self.builder.vertex_layout
+    }
+
+    pub fn bind_groups(&self) -> &Vec<wgpu::BindGroup> {
+        &self.builder.bind_groups
+    }
+
+    pub fn shader_path(&self) -> Option<&Path> {
+        self.builder.shader_path.as_deref()
+    }
+
+    /// Changes the file the shader is reloaded from.  The file is read on the next call to
+    /// `reload_if_modified()` regardless of its modification time.
+    pub fn set_shader_path(&mut self, path: Option<PathBuf>) {
+        self.builder.shader_path = path;
+        self.shader_modified = None;
+        self.shader_missing = false;
+    }
+
+    /// Recompiles the pipeline if its shader file has changed since it was last read.  Returns
+    /// whether the pipeline was recompiled.  On error the previous pipeline is kept, and the file
+    /// is not read again until it changes.
+    pub fn reload_if_modified(&mut self, device: &wgpu::Device) -> Result<bool, ShaderReloadError> {
+        let Some(path) = self.builder.shader_path.clone() else {
+            return Ok(false);
+        };
+
+        let io_error = |error| ShaderReloadError::Io { path: path.clone(), error };
+
+        // a missing file is reported once rather than every time this is called
+        let modified = match std::fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(_) if self.shader_missing => return Ok(false),
+            Err(error) => {
+                self.shader_missing = true;
+                return Err(io_error(error));
+            },
+        };
+        self.shader_missing = false;
+
+        if self.shader_modified == Some(modified) {
+            return Ok(false);
+        }
+        self.shader_modified = Some(modified);
+
+        let source = std::fs::read_to_string(&path).map_err(io_error)?;
+
+        // catch validation errors instead of letting them reach the uncaptured error handler,
+        // which panics by default
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let (pipeline, transparent_pipeline) = self.builder.create_render_pipelines(
+            device,
+            &self.layout,
+            &source,
+            self.color_format,
+            self.depth_format,
+            self.sample_count,
+        );
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(ShaderReloadError::Validation { path, message: error.to_string() });
+        }
+
+        self.pipeline = pipeline;
+        self.transparent_pipeline = transparent_pipeline;
+        self.builder.shader_source = source;
+
+        Ok(true)
+    }
+
+    /// Recreates the pipeline on a new device after the old one was lost, for targets with
+    /// `sample_count` samples, which the new device may have lowered.  Returns false if the
+    /// pipeline has bind groups of its own, which were created on the old device and can't be
+    /// recreated here.
+    pub fn recreate(&mut self, device: &wgpu::Device, shared_layouts: &[&wgpu::BindGroupLayout], sample_count: u32) -> bool {
+        if !self.builder.bind_groups.is_empty() {
+            return false;
+        }
+
+        self.layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&self.builder.label),
+            bind_group_layouts: shared_layouts,
+            push_constant_ranges: &[],
+        });
+        self.set_sample_count(device, sample_count);
+
+        true
+    }
+
+    /// Recreates the pipeline for targets with a different msaa sample count.
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.sample_count = sample_count;
+        (self.pipeline, self.transparent_pipeline) = self.builder.create_render_pipelines(
+            device,
+            &self.layout,
+            &self.builder.shader_source,
+            self.color_format,
+            self.depth_format,
+            self.sample_count,
+        );
+    }
+}
+
+
+/// An error from reloading a pipeline's shader file.
+#[derive(Debug)]
+pub enum ShaderReloadError {
+    /// The shader file could not be read.
+    Io { path: PathBuf, error: std::io::Error },
+    /// The shader or the pipeline built from it failed validation.
+    Validation { path: PathBuf, message: String },
+}
+
+impl fmt::Display for ShaderReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderReloadError::Io { path, error } => write!(f, "error reading shader {}: {}", path.display(), error),
+            ShaderReloadError::Validation { path, message } => write!(f, "error compiling shader {}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderReloadError {}
+
+
+/// An error from compiling a pipeline added with `Renderer::add_pipeline()`.
+#[derive(Debug)]
+pub struct ShaderError {
+    label: String,
+    message: String,
+}
+
+impl ShaderError {
+    /// The label of the pipeline that failed to compile.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The validation error reported by wgpu.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error compiling pipeline {}: {}", self.label, self.message)
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+
+/// Describes a pipeline built from user WGSL, to be registered with `Renderer::add_pipeline()`.
+///
+/// The shader's entry points must be called `vs_main` and `fs_main`.  The vertex stage receives
+/// the vertex type's attributes and the instance transform at locations 5 through 11, laid out as
+/// in `shader.wgsl`.  Group 0 holds the camera (binding 0), the directional and ambient lights
+/// (binding 1), the shadow map and its sampler (bindings 2 and 3), and the point and spot lights
+/// (binding 4).  Group 1 holds the instance's material, and bind groups added with
+/// `with_bind_group()` follow from group 2.
+pub struct PipelineBuilder {
+    label: String,
+    shader_source: String,
+    shader_path: Option<PathBuf>,
+    vertex_layout: wgpu::VertexBufferLayout<'static>,
+    primitive: wgpu::PrimitiveState,
+    blend: Option<wgpu::BlendState>,
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+    shading_model: Option<ShadingModel>,
+    bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl PipelineBuilder {
+    pub fn new<T: Vertex>(label: &str, shader_source: &str) -> PipelineBuilder {
+        PipelineBuilder {
+            label: label.to_string(),
+            shader_source: shader_source.to_string(),
+            shader_path: None,
+            vertex_layout: T::vertex_buffer_layout(),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            blend: Some(wgpu::BlendState::REPLACE),
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            shading_model: None,
+            bind_group_layouts: Vec::new(),
+            bind_groups: Vec::new(),
+        }
+    }
+
+    /// Reads the shader from a file, which is watched for changes once hot reloading is enabled
+    /// with `Renderer::enable_shader_hot_reload()`.
+    pub fn from_file<T: Vertex>(label: &str, path: impl Into<PathBuf>) -> std::io::Result<PipelineBuilder> {
+        let path = path.into();
+        let source = std::fs::read_to_string(&path)?;
+        Ok(Self::new::<T>(label, &source).with_shader_path(path))
+    }
+
+    /// Sets the file the shader is reloaded from when hot reloading is enabled.  The source
+    /// passed to `new()` is used until the file changes.
+    pub fn with_shader_path(mut self, path: impl Into<PathBuf>) -> PipelineBuilder {
+        self.shader_path = Some(path.into());
+        self
+    }
+
+    pub fn with_primitive(mut self, primitive: wgpu::PrimitiveState) -> PipelineBuilder {
+        self.primitive = primitive;
+        self
+    }
+
+    /// Sets the blending of the opaque variant; the transparent variant always blends by alpha.
+    pub fn with_blend(mut self, blend: Option<wgpu::BlendState>) -> PipelineBuilder {
+        self.blend = blend;
+        self
+    }
+
+    /// Sets the depth state of the opaque variant; the transparent variant shares the comparison
+    /// but never writes depth.
+    pub fn with_depth(mut self, depth_write_enabled: bool, depth_compare: wgpu::CompareFunction) -> PipelineBuilder {
+        self.depth_write_enabled = depth_write_enabled;
+        self.depth_compare = depth_compare;
+        self
+    }
+
+    /// Shades every instance drawn with the pipeline with one model, regardless of its material.
+    /// The shader must declare `override SHADING_MODEL: u32`, as the built-in lit shaders do.
+    pub fn with_shading_model(mut self, shading_model: ShadingModel) -> PipelineBuilder {
+        self.shading_model = Some(shading_model);
+        self
+    }
+
+    /// Adds a bind group at the next free group index, starting from 2.
+    pub fn with_bind_group(mut self, layout: wgpu::BindGroupLayout, bind_group: wgpu::BindGroup) -> PipelineBuilder {
+        self.bind_group_layouts.push(layout);
+        self.bind_groups.push(bind_group);
+        self
+    }
+
+    /// Compiles the shader and creates the pipeline.  `shared_layouts` are the camera and
+    /// material bind group layouts.
+    pub fn build(
+        self,
+        device: &wgpu::Device,
+        shared_layouts: &[&wgpu::BindGroupLayout],
+        color_format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+    ) -> PipelineResource {
+        let mut bind_group_layouts = shared_layouts.to_vec();
+        bind_group_layouts.extend(self.bind_group_layouts.iter());
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&self.label),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let (pipeline, transparent_pipeline) = self.create_render_pipelines(device, &layout, &self.shader_source, color_format, depth_format, sample_count);
+
+        // the file was just read by from_file(), or the source was given directly and the file
+        // only takes over once it changes
+        let shader_modified = self.shader_path.as_ref()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .and_then(|metadata| metadata.modified().ok());
+
+        PipelineResource {
+            pipeline,
+            transparent_pipeline,
+            layout,
+            builder: self,
+            color_format,
+            depth_format,
+            sample_count,
+            shader_modified,
+            shader_missing: false,
+        }
+    }
+
+    /// Like `build()`, but returns the validation error if the shader or pipeline is invalid
+    /// instead of letting it reach the device's uncaptured error handler, which panics by default.
+    pub fn try_build(
+        self,
+        device: &wgpu::Device,
+        shared_layouts: &[&wgpu::BindGroupLayout],
+        color_format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+    ) -> Result<PipelineResource, ShaderError> {
+        let label = self.label.clone();
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipeline = self.build(device, shared_layouts, color_format, depth_format, sample_count);
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(ShaderError { label, message: error.to_string() });
+        }
+
+        Ok(pipeline)
+    }
+
+    // compiles the shader once for the opaque variant and the transparent one
+    fn create_render_pipelines(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader_source: &str,
+        color_format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&self.label),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let create = |blend, depth_write_enabled| self.create_render_pipeline(
+            device,
+            layout,
+            &shader,
+            color_format,
+            depth_format,
+            sample_count,
+            blend,
+            depth_write_enabled,
+        );
+        (
+            create(self.blend, self.depth_write_enabled),
+            create(Some(wgpu::BlendState::ALPHA_BLENDING), false),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_render_pipeline(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        color_format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+        blend: Option<wgpu::BlendState>,
+        depth_write_enabled: bool,
+    ) -> wgpu::RenderPipeline {
+        let mut constants = HashMap::new();
+        if let Some(shading_model) = self.shading_model {
+            constants.insert("SHADING_MODEL".to_string(), shading_model.shader_value() as f64);
+        }
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: &constants,
+            ..Default::default()
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&self.label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                buffers: &[self.vertex_layout.clone(), InstanceData::vertex_buffer_layout()],
+                compilation_options: compilation_options.clone(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options,
+            }),
+            primitive: self.primitive,
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled,
+                depth_compare: self.depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+}
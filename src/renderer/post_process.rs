@@ -0,0 +1,500 @@
+use std::mem;
+
+use cgmath::Vector3;
+use generational_arena::{Arena, Index};
+use wgpu::util::DeviceExt;
+
+use super::{error::{HandleError, HandleKind}, tone_mapping::HDR_FORMAT};
+
+const PRELUDE: &str = include_str!("../post_process_prelude.wgsl");
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PostProcessHandle(Index);
+
+
+/// Describes a fullscreen post-process pass, to be added to the chain with
+/// `Renderer::add_post_process()`.
+///
+/// The shader only needs a fragment entry point called `fs_main` taking a
+/// `PostProcessVertexOutput`, whose `uv` runs from (0, 0) at the top left to (1, 1) at the bottom
+/// right.  A prelude declaring the following is prepended to it:
+/// - `input_texture` (binding 0): the output of the previous pass, or the scene for the first pass
+/// - `scene_texture` (binding 1): the hdr scene before any post-processing
+/// - `depth_texture` (binding 2): the scene's depth in the red channel, read with `textureLoad`.
+///   With msaa this is the first sample of each pixel, or the far plane on gl, which can't read
+///   multisampled depth
+/// - `input_sampler` (binding 3): a linear sampler clamped to the edges
+/// - `frame` (binding 4): a `PostProcessFrame` with the `resolution` and `texel_size`
+///
+/// The uniform block from `with_uniforms()` is at group 0, binding 5, declared by the shader.
+/// Passes run in hdr, before tone mapping.
+#[derive(Clone)]
+pub struct PostProcessBuilder {
+    label: String,
+    shader_source: String,
+    uniforms: Vec<u8>,
+}
+
+impl PostProcessBuilder {
+    pub fn new(label: &str, shader_source: &str) -> PostProcessBuilder {
+        PostProcessBuilder {
+            label: label.to_string(),
+            shader_source: shader_source.to_string(),
+            uniforms: Vec::new(),
+        }
+    }
+
+    /// Sets the initial contents of the pass's uniform block.
+    pub fn with_uniforms<T: bytemuck::Pod>(mut self, uniforms: &T) -> PostProcessBuilder {
+        self.uniforms = bytemuck::bytes_of(uniforms).to_vec();
+        self
+    }
+
+    /// Adds glow around parts of the image brighter than a threshold.
+    pub fn bloom(settings: BloomSettings) -> PostProcessBuilder {
+        Self::new("bloom", include_str!("../post_bloom_shader.wgsl")).with_uniforms(&settings)
+    }
+
+    /// Smooths jagged edges.
+    pub fn fxaa() -> PostProcessBuilder {
+        Self::new("fxaa", include_str!("../post_fxaa_shader.wgsl"))
+    }
+
+    /// Darkens the edges of the image.
+    pub fn vignette(settings: VignetteSettings) -> PostProcessBuilder {
+        Self::new("vignette", include_str!("../post_vignette_shader.wgsl")).with_uniforms(&settings)
+    }
+
+    /// Adjusts tint, saturation, contrast, and brightness.
+    pub fn color_grading(settings: ColorGradingSettings) -> PostProcessBuilder {
+        Self::new("color grading", include_str!("../post_color_grading_shader.wgsl")).with_uniforms(&settings)
+    }
+
+    pub fn build(self, device: &wgpu::Device, pipeline_layout: &wgpu::PipelineLayout) -> PostProcessPass {
+        let source = format!("{}\n{}", PRELUDE, self.shader_source);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&self.label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&self.label),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let uniform_buffer = PostProcessPass::create_uniform_buffer(device, &self.uniforms);
+
+        PostProcessPass {
+            builder: self,
+            pipeline,
+            uniform_buffer,
+            enabled: true,
+        }
+    }
+}
+
+
+/// A built pass.  The builder is kept, with the latest uniforms, to rebuild the pass after device
+/// loss.
+pub struct PostProcessPass {
+    builder: PostProcessBuilder,
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    enabled: bool,
+}
+
+impl PostProcessPass {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn create_uniform_buffer(device: &wgpu::Device, uniforms: &[u8]) -> wgpu::Buffer {
+        // uniform buffers cannot be empty, and their size must be a multiple of 16 on some backends
+        let mut contents = uniforms.to_vec();
+        contents.resize(contents.len().max(16).next_multiple_of(16), 0);
+
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("post-process uniform buffer"),
+            contents: &contents,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+}
+
+
+/// An ordered list of post-process passes, which ping-pong between two hdr targets.
+pub struct PostProcessChain {
+    passes: Arena<PostProcessPass>,
+    order: Vec<PostProcessHandle>,
+
+    targets: [(wgpu::Texture, wgpu::TextureView); 2],
+    frame_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+}
+
+impl PostProcessChain {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> PostProcessChain {
+        let targets = [Self::create_target(device, config), Self::create_target(device, config)];
+
+        let frame_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("post-process frame buffer"),
+            contents: bytemuck::cast_slice(&[PostProcessFrameData::new(config)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post-process sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0, wgpu::TextureSampleType::Float { filterable: true }),
+                texture_entry(1, wgpu::TextureSampleType::Float { filterable: true }),
+                // bound as a plain float texture, which more backends can load from than depth textures
+                texture_entry(2, wgpu::TextureSampleType::Float { filterable: false }),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                uniform_entry(4),
+                uniform_entry(5),
+            ],
+            label: Some("post-process bind group layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post-process pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        PostProcessChain {
+            passes: Arena::new(),
+            order: Vec::new(),
+            targets,
+            frame_buffer,
+            sampler,
+            bind_group_layout,
+            pipeline_layout,
+        }
+    }
+
+    // ================================================================
+    // passes
+    // ================================================================
+    /// Builds a pass and inserts it at `position` in the chain, or at the end if `position` is
+    /// past the end.
+    pub fn insert(&mut self, position: usize, builder: PostProcessBuilder, device: &wgpu::Device) -> PostProcessHandle {
+        let pass = builder.build(device, &self.pipeline_layout);
+        let handle = PostProcessHandle(self.passes.insert(pass));
+        self.order.insert(position.min(self.order.len()), handle);
+        handle
+    }
+
+    pub fn remove(&mut self, handle: PostProcessHandle) -> Result<(), HandleError> {
+        let PostProcessHandle(index) = handle;
+        self.passes.remove(index).ok_or(HandleError::Stale(HandleKind::PostProcess))?;
+        self.order.retain(|pass| *pass != handle);
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, handle: PostProcessHandle, enabled: bool) -> Result<(), HandleError> {
+        self.get_pass_mut(handle)?.enabled = enabled;
+        Ok(())
+    }
+
+    /// Replaces the contents of a pass's uniform block.
+    pub fn update_uniforms(&mut self, handle: PostProcessHandle, uniforms: &[u8], device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), HandleError> {
+        let pass = self.get_pass_mut(handle)?;
+        pass.builder.uniforms = uniforms.to_vec();
+        if uniforms.len() as u64 > pass.uniform_buffer.size() {
+            pass.uniform_buffer = PostProcessPass::create_uniform_buffer(device, uniforms);
+        } else {
+            queue.write_buffer(&pass.uniform_buffer, 0, uniforms);
+        }
+        Ok(())
+    }
+
+    fn get_pass_mut(&mut self, handle: PostProcessHandle) -> Result<&mut PostProcessPass, HandleError> {
+        let PostProcessHandle(index) = handle;
+        self.passes.get_mut(index).ok_or(HandleError::Stale(HandleKind::PostProcess))
+    }
+
+    pub fn has_enabled_passes(&self) -> bool {
+        self.passes.iter().any(|(_, pass)| pass.enabled())
+    }
+
+    /// The passes in the order they run.
+    pub fn order(&self) -> &Vec<PostProcessHandle> {
+        &self.order
+    }
+
+    /// Recreates the targets and passes on a new device after the old one was lost, keeping
+    /// handles, order, and whether each pass is enabled.
+    pub fn recreate(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        let fresh = Self::new(device, config);
+        self.targets = fresh.targets;
+        self.frame_buffer = fresh.frame_buffer;
+        self.sampler = fresh.sampler;
+        self.bind_group_layout = fresh.bind_group_layout;
+        self.pipeline_layout = fresh.pipeline_layout;
+
+        for (_, pass) in self.passes.iter_mut() {
+            let enabled = pass.enabled;
+            *pass = pass.builder.clone().build(device, &self.pipeline_layout);
+            pass.enabled = enabled;
+        }
+    }
+
+    // ================================================================
+    // rendering
+    // ================================================================
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration) {
+        self.targets = [Self::create_target(device, config), Self::create_target(device, config)];
+        queue.write_buffer(&self.frame_buffer, 0, bytemuck::cast_slice(&[PostProcessFrameData::new(config)]));
+    }
+
+    /// Runs the enabled passes in order over the scene, returning the view holding the result, or
+    /// `None` if no pass ran and the scene should be used as is.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+    ) -> Option<&wgpu::TextureView> {
+        let mut input = None;
+
+        for (target, pass) in self.order.iter()
+            .filter_map(|PostProcessHandle(index)| self.passes.get(*index))
+            .filter(|pass| pass.enabled())
+            .enumerate()
+        {
+            let output = &self.targets[target % 2].1;
+
+            // bind groups are cheap, and building them here keeps them in step with resizes
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(input.unwrap_or(scene_view)),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(scene_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(depth_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: self.frame_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: pass.uniform_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("post-process bind group"),
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("post-process pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&pass.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+
+            input = Some(output);
+        }
+
+        input
+    }
+
+    fn create_target(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("post-process target"),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        (texture, view)
+    }
+}
+
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostProcessFrameData {
+    resolution: [f32; 2],
+    texel_size: [f32; 2],
+}
+
+impl PostProcessFrameData {
+    fn new(config: &wgpu::SurfaceConfiguration) -> PostProcessFrameData {
+        let width = config.width.max(1) as f32;
+        let height = config.height.max(1) as f32;
+        PostProcessFrameData {
+            resolution: [width, height],
+            texel_size: [1.0 / width, 1.0 / height],
+        }
+    }
+}
+
+
+/// Uniforms for `PostProcessBuilder::bloom()`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BloomSettings {
+    threshold: f32,
+    intensity: f32,
+    radius: f32,
+    _padding: f32,
+}
+
+impl BloomSettings {
+    /// Parts of the image whose brightest channel exceeds `threshold` glow, spreading `radius`
+    /// pixels.
+    pub fn new(threshold: f32, intensity: f32, radius: f32) -> BloomSettings {
+        BloomSettings {
+            threshold,
+            intensity,
+            radius,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Uniforms for `PostProcessBuilder::vignette()`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct VignetteSettings {
+    strength: f32,
+    radius: f32,
+    softness: f32,
+    _padding: f32,
+}
+
+impl VignetteSettings {
+    /// Darkening starts `radius` from the center, measured in screen heights, and reaches
+    /// `strength` over `softness`.
+    pub fn new(strength: f32, radius: f32, softness: f32) -> VignetteSettings {
+        VignetteSettings {
+            strength,
+            radius,
+            softness,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Uniforms for `PostProcessBuilder::color_grading()`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorGradingSettings {
+    tint: [f32; 3],
+    saturation: f32,
+    contrast: f32,
+    brightness: f32,
+    _padding: [f32; 2],
+}
+
+impl ColorGradingSettings {
+    pub fn new(tint: Vector3<f32>, saturation: f32, contrast: f32, brightness: f32) -> ColorGradingSettings {
+        ColorGradingSettings {
+            tint: tint.into(),
+            saturation,
+            contrast,
+            brightness,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> Self {
+        Self::new(Vector3::new(1.0, 1.0, 1.0), 1.0, 1.0, 1.0)
+    }
+}
+
+// checked here because the shaders declare matching structs
+const _: () = assert!(mem::size_of::<ColorGradingSettings>() == 32);
@@ -41,9 +41,18 @@ impl ResizableBuffer {
     }
 
     pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[u8]) {
+        self.reserve(device, data.len() as u32);
+
+        // update buffer buffer
+        queue.write_buffer(&self.buffer, 0, data);
+    }
+
+    /// Grows the buffer to hold `size` bytes, for filling it on the gpu; its contents are lost if
+    /// it is reallocated.
+    pub fn reserve(&mut self, device: &wgpu::Device, size: u32) {
         // reallocate buffer if we need to expand it
-        if data.len() as u32 > self.capacity() {
-            let new_capacity = data.len() + data.len() / 4;
+        if size > self.capacity() {
+            let new_capacity = size + size / 4;
             let new_capacity = (new_capacity + 4 - new_capacity % 4) as u64; // can panic if not divisible by 4
                 // (https://github.com/gfx-rs/wgpu/issues/4731)
 
@@ -54,10 +63,13 @@ impl ResizableBuffer {
                 mapped_at_creation: false,
             });
         }
+        self.size = size;
+    }
 
-        // update buffer buffer
-        queue.write_buffer(&self.buffer, 0, data);
-        self.size = data.len() as u32;
+    /// Writes `data` at `offset` without resizing; the range must lie within the buffer's
+    /// capacity.
+    pub fn write(&self, queue: &wgpu::Queue, offset: u64, data: &[u8]) {
+        queue.write_buffer(&self.buffer, offset, data);
     }
 
     const LABEL: &'static str = "resizable buffer";
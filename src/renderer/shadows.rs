@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+
+use crate::scene::{camera::Camera, light::ShadowSettings};
+
+use super::instances::InstanceData;
+
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// The directional light's shadow map, and the depth-only pipelines that render into it, one per
+/// vertex layout.  Vertex types cast shadows if they have a `Float32x3` position at location 0.
+pub struct ShadowMap {
+    resolution: u32,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    pipelines: HashMap<wgpu::VertexBufferLayout<'static>, wgpu::RenderPipeline>,
+}
+
+impl ShadowMap {
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    /// Binds the light uniform at group 0 for the shadow pass.
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn pipeline(&self, vertex_layout: &wgpu::VertexBufferLayout<'static>) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(vertex_layout)
+    }
+
+    pub fn new(resolution: u32, light_buffer: &wgpu::Buffer, device: &wgpu::Device) -> ShadowMap {
+        let (texture, view) = Self::create_texture(resolution, device);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("shadow bind group layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            }],
+            label: Some("shadow bind group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shadow_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shadow_shader.wgsl").into()),
+        });
+
+        ShadowMap {
+            resolution,
+            texture,
+            view,
+            sampler,
+            bind_group,
+            pipeline_layout,
+            shader,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Recreates the shadow map at a new resolution.  Bind groups holding the old view must be
+    /// recreated.
+    pub fn resize(&mut self, resolution: u32, device: &wgpu::Device) {
+        self.resolution = resolution;
+        (self.texture, self.view) = Self::create_texture(resolution, device);
+    }
+
+    /// Creates the shadow pipeline for a vertex layout if it does not exist yet.
+    pub fn prepare_pipeline(&mut self, vertex_layout: &wgpu::VertexBufferLayout<'static>, device: &wgpu::Device) {
+        if self.pipelines.contains_key(vertex_layout) {
+            return;
+        }
+
+        let has_position = vertex_layout.attributes.iter()
+            .any(|attribute| attribute.shader_location == 0 && attribute.format == wgpu::VertexFormat::Float32x3);
+        if !has_position {
+            return;
+        }
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shadow pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: Some("vs_main"),
+                buffers: &[vertex_layout.clone(), InstanceData::vertex_buffer_layout()],
+                compilation_options: Default::default(),
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // thin and open meshes should still cast shadows
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        self.pipelines.insert(vertex_layout.clone(), pipeline);
+    }
+
+    fn create_texture(resolution: u32, device: &wgpu::Device) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shadow map"),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        (texture, view)
+    }
+}
+
+/// Fits an orthographic projection along `direction` around the part of the camera frustum within
+/// the shadow distance.  The projection bounds a sphere around that part of the frustum, so its
+/// size does not change as the camera turns, and it moves in whole texels so shadow edges do not
+/// shimmer as the camera moves.  Casters up to one sphere radius outside the sphere towards the
+/// light still cast shadows into it.
+pub fn fit_light_view_projection(camera: &Camera, direction: Vector3<f32>, settings: &ShadowSettings) -> Matrix4<f32> {
+    let direction = direction.normalize();
+
+    let forward = (camera.target() - camera.eye()).normalize();
+    let right = forward.cross(camera.up()).normalize();
+    let up = right.cross(forward);
+    let tan_y = (camera.fovy() / 2.0).tan();
+    let tan_x = tan_y * camera.aspect();
+
+    let near = camera.z_near();
+    let far = camera.z_far().min(near + settings.distance());
+    let mut corners = Vec::with_capacity(8);
+    for depth in [near, far] {
+        for x in [-1.0, 1.0] {
+            for y in [-1.0, 1.0] {
+                corners.push(camera.eye() + depth * (forward + x * tan_x * right + y * tan_y * up));
+            }
+        }
+    }
+
+    let center = corners.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, corner| sum + corner) / 8.0;
+    let radius = corners.iter()
+        .map(|corner| (corner - center).magnitude())
+        .fold(0.0, f32::max);
+
+    let light_up = if direction.z.abs() > 0.99 { Vector3::unit_y() } else { Vector3::unit_z() };
+    let light_right = direction.cross(light_up).normalize();
+    let light_up = light_right.cross(direction);
+
+    let texel_size = 2.0 * radius / settings.resolution() as f32;
+    let snap = |distance: f32| (distance / texel_size).floor() * texel_size;
+    let center = snap(center.dot(light_right)) * light_right
+        + snap(center.dot(light_up)) * light_up
+        + center.dot(direction) * direction;
+
+    let eye = center - 2.0 * radius * direction;
+    let view = Matrix4::look_at_rh(Point3::from_vec(eye), Point3::from_vec(center), light_up);
+    let proj = cgmath::ortho(-radius, radius, -radius, radius, 0.0, 4.0 * radius);
+
+    // map depth from opengl's -1 to 1 onto wgpu's 0 to 1
+    let depth_to_wgpu = Matrix4::from_translation(Vector3::new(0.0, 0.0, 0.5))
+        * Matrix4::from_nonuniform_scale(1.0, 1.0, 0.5);
+
+    depth_to_wgpu * proj * view
+}
+
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector4;
+
+    use super::*;
+
+    #[test]
+    fn test_fit_light_view_projection() {
+        let camera = Camera::new(
+            Vector3::new(0.8, -1.5, 1.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::unit_z(),
+            1.25,
+            std::f32::consts::TAU / 8.0,
+            0.1,
+            100.0,
+        );
+        let settings = ShadowSettings::new(1024, 0.002, 5.0);
+        let view_proj = fit_light_view_projection(&camera, Vector3::new(1.0, 1.0, -1.0), &settings);
+
+        // the camera target is within the shadow distance, so it lands inside the shadow map
+        for point in [camera.eye(), camera.target(), Vector3::new(0.5, 0.5, 0.5)] {
+            let clip = view_proj * Vector4::new(point.x, point.y, point.z, 1.0);
+            let ndc = clip.truncate() / clip.w;
+            assert!(ndc.x.abs() < 1.0 && ndc.y.abs() < 1.0, "{:?} maps to {:?}", point, ndc);
+            assert!(ndc.z > 0.0 && ndc.z < 1.0, "{:?} maps to {:?}", point, ndc);
+        }
+
+        // beyond the shadow distance is outside
+        let far = camera.eye() + 20.0 * (camera.target() - camera.eye()).normalize();
+        let clip = view_proj * Vector4::new(far.x, far.y, far.z, 1.0);
+        let ndc = clip.truncate() / clip.w;
+        assert!(ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z > 1.0);
+    }
+}
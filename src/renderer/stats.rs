@@ -0,0 +1,55 @@
+/// A snapshot of what the renderer holds, from `Renderer::stats()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RendererStats {
+    meshes: u32,
+    instances: u32,
+    drawn_instances: u32,
+    culled_instances: u32,
+    uploaded_instance_bytes: u64,
+    gpu_bytes: u64,
+}
+
+impl RendererStats {
+    /// Live meshes, including empty ones that aren't drawn.
+    pub fn meshes(&self) -> u32 {
+        self.meshes
+    }
+
+    /// Live instances, including inactive ones.
+    pub fn instances(&self) -> u32 {
+        self.instances
+    }
+
+    /// Instances drawn in the last frame.
+    pub fn drawn_instances(&self) -> u32 {
+        self.drawn_instances
+    }
+
+    /// Active instances left out of the last frame by frustum culling.
+    pub fn culled_instances(&self) -> u32 {
+        self.culled_instances
+    }
+
+    /// Bytes of instance data written to instance buffers in the last frame, which is zero while
+    /// no instance changes, even if the camera moves, as long as the same instances are visible.
+    pub fn uploaded_instance_bytes(&self) -> u64 {
+        self.uploaded_instance_bytes
+    }
+
+    /// Bytes of mesh, instance, texture, and material buffers on the gpu.  Render targets and
+    /// pipelines aren't counted.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.gpu_bytes
+    }
+
+    pub fn new(meshes: u32, instances: u32, drawn_instances: u32, culled_instances: u32, uploaded_instance_bytes: u64, gpu_bytes: u64) -> RendererStats {
+        RendererStats {
+            meshes,
+            instances,
+            drawn_instances,
+            culled_instances,
+            uploaded_instance_bytes,
+            gpu_bytes,
+        }
+    }
+}
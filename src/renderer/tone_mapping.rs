@@ -0,0 +1,255 @@
+use std::mem;
+
+/// The format of the scene target that meshes and lines are drawn into before tone mapping.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// How the hdr scene is mapped into the output's range after exposure is applied.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ToneMapping {
+    /// Colors above 1.0 clip.
+    #[default]
+    None,
+    /// `color / (1 + color)`, which never clips but desaturates highlights.
+    Reinhard,
+    /// A fit of the ACES filmic curve.
+    Aces,
+}
+
+impl ToneMapping {
+    fn shader_value(&self) -> u32 {
+        match self {
+            ToneMapping::None => 0,
+            ToneMapping::Reinhard => 1,
+            ToneMapping::Aces => 2,
+        }
+    }
+}
+
+/// The hdr scene target, and the fullscreen pass that tone maps it into the output.
+pub struct ToneMapper {
+    tone_mapping: ToneMapping,
+    exposure: f32,
+
+    scene_texture: wgpu::Texture,
+    scene_view: wgpu::TextureView,
+
+    settings_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl ToneMapper {
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn scene_texture(&self) -> &wgpu::Texture {
+        &self.scene_texture
+    }
+
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.scene_view
+    }
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> ToneMapper {
+        let (scene_texture, scene_view) = Self::create_scene_texture(device, config);
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("tone mapping buffer"),
+            size: mem::size_of::<ToneMappingData>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("tone mapping bind group layout"),
+        });
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &settings_buffer, &scene_view);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("tone_mapping_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../tone_mapping_shader.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("tone mapping pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(device, &shader, &pipeline_layout, config.format, sample_count);
+
+        let mut tone_mapper = ToneMapper {
+            tone_mapping: ToneMapping::None,
+            exposure: 1.0,
+            scene_texture,
+            scene_view,
+            settings_buffer,
+            bind_group_layout,
+            bind_group,
+            shader,
+            pipeline_layout,
+            pipeline,
+        };
+        tone_mapper.upload_settings(queue);
+
+        tone_mapper
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping, queue: &wgpu::Queue) {
+        self.tone_mapping = tone_mapping;
+        self.upload_settings(queue);
+    }
+
+    pub fn set_exposure(&mut self, exposure: f32, queue: &wgpu::Queue) {
+        self.exposure = exposure;
+        self.upload_settings(queue);
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.scene_texture, self.scene_view) = Self::create_scene_texture(device, config);
+        self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, &self.settings_buffer, &self.scene_view);
+    }
+
+    /// Draws the tone mapped scene over the whole render pass, reading from `source` instead of
+    /// the scene target when given, e.g. the output of post-processing.
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass, device: &wgpu::Device, source: Option<&wgpu::TextureView>) {
+        render_pass.set_pipeline(&self.pipeline);
+        match source {
+            Some(source) => {
+                let bind_group = Self::create_bind_group(device, &self.bind_group_layout, &self.settings_buffer, source);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+            },
+            None => render_pass.set_bind_group(0, &self.bind_group, &[]),
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Recreates the pipeline for an output with a different msaa sample count.
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) {
+        self.pipeline = Self::create_pipeline(device, &self.shader, &self.pipeline_layout, config.format, sample_count);
+    }
+
+    fn upload_settings(&mut self, queue: &wgpu::Queue) {
+        let data = ToneMappingData {
+            exposure: self.exposure,
+            tone_mapping: self.tone_mapping.shader_value(),
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[data]));
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("tone mapping pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    fn create_scene_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hdr scene target"),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        (texture, view)
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        settings_buffer: &wgpu::Buffer,
+        scene_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: settings_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(scene_view),
+                },
+            ],
+            label: Some("tone mapping bind group"),
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMappingData {
+    exposure: f32,
+    tone_mapping: u32,
+    _padding: [u32; 2],
+}
@@ -0,0 +1,134 @@
+use std::ops::Range;
+
+use cgmath::InnerSpace;
+
+use super::{culling::Frustum, gpu_resources::{MaterialHandle, MeshHandle, Resources}, instances::InstanceData, resizable_buffer::ResizableBuffer};
+
+/// Draws the instances with blended materials after the opaque ones.  Each frame they are copied
+/// into one buffer sorted back to front by their depth along the camera's view direction, so
+/// nearer surfaces blend over farther ones, and drawn with their pipelines' transparent variants.
+pub struct TransparentRenderer {
+    instance_buffer: ResizableBuffer,
+    draws: Vec<TransparentDraw>,
+}
+
+// consecutive sorted instances of one mesh, level of detail, and material
+struct TransparentDraw {
+    mesh: MeshHandle,
+    lod: usize,
+    material: MaterialHandle,
+    range: Range<u32>,
+}
+
+impl TransparentRenderer {
+    pub fn new(device: &wgpu::Device) -> TransparentRenderer {
+        TransparentRenderer {
+            instance_buffer: ResizableBuffer::new(
+                100,
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                device,
+            ),
+            draws: Vec::new(),
+        }
+    }
+
+    /// The number of instances drawn by the last `prepare()`.
+    pub fn instance_count(&self) -> u32 {
+        self.draws.last().map_or(0, |draw| draw.range.end)
+    }
+
+    /// The capacity of the instance buffer in bytes.
+    pub fn gpu_bytes(&self) -> u64 {
+        self.instance_buffer.capacity() as u64
+    }
+
+    /// Sorts the buffered instances with blended materials that lie inside one of `frusta`, or
+    /// all of them if it is empty, and uploads them.  The instance buffers must be built first.
+    pub fn prepare(&mut self, frusta: &[Frustum], resources: &Resources, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.draws.clear();
+        let Some(camera) = resources.camera() else {
+            return;
+        };
+        let eye = camera.eye();
+        let forward = (camera.target() - eye).normalize();
+
+        let mut sorted: Vec<(f32, MeshHandle, usize, MaterialHandle, InstanceData)> = Vec::new();
+        for instance_list in resources.iterate_instance_lists() {
+            let Some(mesh) = resources.get_mesh(instance_list.mesh()) else {
+                continue;
+            };
+            let bounds = mesh.bounds();
+            for batch in instance_list.batches() {
+                if !resources.get_material(batch.material()).is_some_and(|material| material.blended()) {
+                    continue;
+                }
+                for index in batch.range() {
+                    let data = *instance_list.buffered_data(index);
+                    let model = data.model();
+                    if let (Some(bounds), false) = (bounds, frusta.is_empty()) {
+                        if !frusta.iter().any(|frustum| frustum.intersects(bounds, &model)) {
+                            continue;
+                        }
+                    }
+                    let center = match bounds {
+                        Some(bounds) => (model * bounds.center().extend(1.0)).truncate(),
+                        None => model.w.truncate(),
+                    };
+                    let depth = (center - eye).dot(forward);
+                    sorted.push((depth, instance_list.mesh(), batch.lod(), batch.material(), data));
+                }
+            }
+        }
+        // farthest first; the sort is stable, so equal depths keep a consistent order
+        sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        for (index, (_, mesh, lod, material, _)) in sorted.iter().enumerate() {
+            let index = index as u32;
+            match self.draws.last_mut() {
+                Some(draw) if draw.mesh == *mesh && draw.lod == *lod && draw.material == *material => draw.range.end = index + 1,
+                _ => self.draws.push(TransparentDraw {
+                    mesh: *mesh,
+                    lod: *lod,
+                    material: *material,
+                    range: index..index + 1,
+                }),
+            }
+        }
+
+        if !sorted.is_empty() {
+            let data: Vec<InstanceData> = sorted.iter().map(|(.., data)| *data).collect();
+            self.instance_buffer.update(device, queue, bytemuck::cast_slice(&data));
+        }
+    }
+
+    /// Draws the sorted instances into a pass whose depth attachment holds the opaque instances.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, resources: &Resources) {
+        if self.draws.is_empty() {
+            return;
+        }
+        render_pass.set_bind_group(0, resources.camera_bind_group(), &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.buffer().slice(..));
+
+        for draw in &self.draws {
+            let (Some(mesh), Some(material)) = (resources.get_mesh(draw.mesh), resources.get_material(draw.material)) else {
+                continue;
+            };
+            let Some(pipeline) = resources.resolve_pipeline(mesh, material).and_then(|handle| resources.get_pipeline(handle)) else {
+                continue;
+            };
+            let level = mesh.level(draw.lod);
+            if mesh.vertex_layout() != pipeline.vertex_layout() || level.index_count() == 0 {
+                continue;
+            }
+
+            render_pass.set_pipeline(pipeline.transparent_pipeline());
+            for (i, bind_group) in pipeline.bind_groups().iter().enumerate() {
+                render_pass.set_bind_group(2 + i as u32, bind_group, &[]);
+            }
+            render_pass.set_bind_group(1, material.bind_group(), &[]);
+            render_pass.set_vertex_buffer(0, level.vertex_buffer().slice(..));
+            render_pass.set_index_buffer(level.index_buffer().slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..level.index_count(), 0, draw.range.clone());
+        }
+    }
+}
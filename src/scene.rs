@@ -4,6 +4,8 @@ use crate::mesh::{Mesh, Vertex};
 
 pub mod camera;
 pub mod light;
+pub mod line_set;
+pub mod material;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Transform {
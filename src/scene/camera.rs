@@ -17,6 +17,7 @@ pub const ZEROES: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.0, 0.0,
 );
 
+#[derive(Debug, Copy, Clone)]
 pub struct Camera {
     eye: Vector3<f32>,
     target: Vector3<f32>,
@@ -41,6 +42,22 @@ impl Camera {
         self.up
     }
 
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    pub fn fovy(&self) -> f32 {
+        self.fovy
+    }
+
+    pub fn z_near(&self) -> f32 {
+        self.z_near
+    }
+
+    pub fn z_far(&self) -> f32 {
+        self.z_far
+    }
+
     pub fn update_eye(&mut self, eye: Vector3<f32>) {
         self.eye = eye;
         self.dirty= true;
@@ -166,12 +183,10 @@ pub struct CameraUniform {
     view_proj: [[f32; 4]; 4],
 }
 
-pub fn create_camera_bind_group_and_layout(
-    camera_buffer: &wgpu::Buffer,
-    light_buffer: &wgpu::Buffer,
-    device: &wgpu::Device
-) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
-    let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+/// Group 0: the camera (binding 0), the directional and ambient lights (binding 1), the
+/// directional light's shadow map (bindings 2 and 3), and the point and spot lights (binding 4).
+pub fn create_camera_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
                 binding: 0,
@@ -193,12 +208,48 @@ pub fn create_camera_bind_group_and_layout(
                 },
                 count: None,
             },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
         ],
         label: Some("camera bind group layout"),
-    });
+    })
+}
 
-    let camera_bind_group = device.create_bind_group( &wgpu::BindGroupDescriptor {
-        layout: &camera_bind_group_layout,
+pub fn create_camera_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    camera_buffer: &wgpu::Buffer,
+    light_buffer: &wgpu::Buffer,
+    shadow_view: &wgpu::TextureView,
+    shadow_sampler: &wgpu::Sampler,
+    local_lights_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
@@ -208,11 +259,21 @@ pub fn create_camera_bind_group_and_layout(
                 binding: 1,
                 resource: light_buffer.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(shadow_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(shadow_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: local_lights_buffer.as_entire_binding(),
+            },
         ],
         label: Some("camera bind group"),
-    });
-
-    (camera_bind_group_layout, camera_bind_group)
+    })
 }
 
 pub struct Ray {
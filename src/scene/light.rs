@@ -5,6 +5,7 @@ pub struct DirectionalLight {
     direction: Vector3<f32>,
     color: Vector3<f32>,
     intensity: f32,
+    shadows: Option<ShadowSettings>,
 }
 
 impl DirectionalLight {
@@ -20,6 +21,11 @@ impl DirectionalLight {
         self.intensity
     }
 
+    /// Shadow settings, or `None` if the light casts no shadows.
+    pub fn shadows(&self) -> Option<ShadowSettings> {
+        self.shadows
+    }
+
     pub fn new(
         direction: Vector3<f32>,
         color: Vector3<f32>,
@@ -29,10 +35,55 @@ impl DirectionalLight {
             direction,
             color,
             intensity,
+            shadows: None,
+        }
+    }
+
+    pub fn with_shadows(mut self, shadows: ShadowSettings) -> Self {
+        self.shadows = Some(shadows);
+        self
+    }
+}
+
+/// How a `DirectionalLight` casts shadows.  The shadow map covers the part of the camera frustum
+/// within `distance` of the camera.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowSettings {
+    resolution: u32,
+    bias: f32,
+    distance: f32,
+}
+
+impl ShadowSettings {
+    /// Width and height of the shadow map in texels.
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    /// Depth offset applied when comparing against the shadow map, to avoid shadow acne.
+    pub fn bias(&self) -> f32 {
+        self.bias
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn new(resolution: u32, bias: f32, distance: f32) -> ShadowSettings {
+        ShadowSettings {
+            resolution,
+            bias,
+            distance,
         }
     }
 }
 
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self::new(2048, 0.002, 20.0)
+    }
+}
+
 pub struct AmbientLight {
     color: Vector3<f32>,
     intensity: f32,
@@ -53,4 +104,103 @@ impl AmbientLight {
             intensity,
         }
     }
-}
\ No newline at end of file
+}
+
+/// A light that shines in all directions from a point, fading out to nothing at `range`.
+#[derive(Debug, Copy, Clone)]
+pub struct PointLight {
+    position: Vector3<f32>,
+    color: Vector3<f32>,
+    intensity: f32,
+    range: f32,
+}
+
+impl PointLight {
+    pub fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    pub fn color(&self) -> Vector3<f32> {
+        self.color
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn range(&self) -> f32 {
+        self.range
+    }
+
+    pub fn new(position: Vector3<f32>, color: Vector3<f32>, intensity: f32, range: f32) -> PointLight {
+        PointLight {
+            position,
+            color,
+            intensity,
+            range,
+        }
+    }
+}
+
+/// A point light limited to a cone around `direction`.  Its light fades from full strength at
+/// `inner_angle` to nothing at `outer_angle`, both measured in radians from the cone's axis.
+#[derive(Debug, Copy, Clone)]
+pub struct SpotLight {
+    position: Vector3<f32>,
+    direction: Vector3<f32>,
+    color: Vector3<f32>,
+    intensity: f32,
+    range: f32,
+    inner_angle: f32,
+    outer_angle: f32,
+}
+
+impl SpotLight {
+    pub fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    pub fn direction(&self) -> Vector3<f32> {
+        self.direction
+    }
+
+    pub fn color(&self) -> Vector3<f32> {
+        self.color
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn range(&self) -> f32 {
+        self.range
+    }
+
+    pub fn inner_angle(&self) -> f32 {
+        self.inner_angle
+    }
+
+    pub fn outer_angle(&self) -> f32 {
+        self.outer_angle
+    }
+
+    pub fn new(
+        position: Vector3<f32>,
+        direction: Vector3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> SpotLight {
+        SpotLight {
+            position,
+            direction,
+            color,
+            intensity,
+            range,
+            inner_angle,
+            outer_angle,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::color_vertex::ColorVertex;
+
+/// Lines that are drawn every frame until removed, registered with `Renderer::add_line_set()`,
+/// rather than for one frame like lines from `Renderer::draw_line()`.
+#[derive(Clone)]
+pub struct LineSet {
+    lines: Vec<(ColorVertex, ColorVertex)>,
+    thickness: f32,
+    on_top: bool,
+}
+
+impl LineSet {
+    pub fn lines(&self) -> &Vec<(ColorVertex, ColorVertex)> {
+        &self.lines
+    }
+
+    /// The width of the lines on screen in pixels, whatever their distance from the camera.
+    pub fn thickness(&self) -> f32 {
+        self.thickness
+    }
+
+    /// Whether the lines are drawn over everything else in the scene, ignoring depth.
+    pub fn on_top(&self) -> bool {
+        self.on_top
+    }
+
+    pub fn new() -> LineSet {
+        LineSet {
+            lines: Vec::new(),
+            thickness: 1.0,
+            on_top: false,
+        }
+    }
+
+    pub fn add_line(&mut self, start: ColorVertex, end: ColorVertex) {
+        self.lines.push((start, end));
+    }
+
+    pub fn with_thickness(mut self, thickness: f32) -> LineSet {
+        self.thickness = thickness;
+        self
+    }
+
+    pub fn with_on_top(mut self, on_top: bool) -> LineSet {
+        self.on_top = on_top;
+        self
+    }
+}
+
+impl Default for LineSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
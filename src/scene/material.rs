@@ -0,0 +1,258 @@
+use cgmath::{Vector3, Vector4};
+
+use crate::renderer::gpu_resources::{PipelineHandle, TextureHandle};
+
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Fragments with alpha below the cutoff are discarded.
+    Mask(f32),
+    /// Blended over what is behind it by its alpha, in a pass after the opaque instances.
+    Blend,
+}
+
+/// How lit materials respond to light.  Light intensities are scaled so a white surface facing a
+/// light looks the same under each model, apart from highlights.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ShadingModel {
+    /// Diffuse only.
+    #[default]
+    Lambert,
+    /// Diffuse plus a Blinn-Phong highlight whose size follows the roughness.
+    BlinnPhong,
+    /// Metallic-roughness shading with GGX specular.
+    Pbr,
+}
+
+impl ShadingModel {
+    /// The value the built-in shaders use for this model.
+    pub fn shader_value(&self) -> u32 {
+        match self {
+            ShadingModel::Lambert => 0,
+            ShadingModel::BlinnPhong => 1,
+            ShadingModel::Pbr => 2,
+        }
+    }
+}
+
+/// Describes how the surface of an instance is shaded.  Register a material with
+/// `Renderer::add_material()` and pass the returned handle to `Renderer::add_instance()`.
+#[derive(Debug, Copy, Clone)]
+pub struct Material {
+    base_color: Vector4<f32>,
+    albedo_texture: Option<TextureHandle>,
+    roughness: f32,
+    metallic: f32,
+    emissive: Vector3<f32>,
+    unlit: bool,
+    shading_model: ShadingModel,
+    alpha_mode: AlphaMode,
+    pipeline: Option<PipelineHandle>,
+}
+
+impl Material {
+    /// Multiplies the vertex color or albedo texture.
+    pub fn base_color(&self) -> Vector4<f32> {
+        self.base_color
+    }
+
+    /// Sampled using the vertex uvs; ignored for vertex types without uvs.
+    pub fn albedo_texture(&self) -> Option<TextureHandle> {
+        self.albedo_texture
+    }
+
+    pub fn roughness(&self) -> f32 {
+        self.roughness
+    }
+
+    pub fn metallic(&self) -> f32 {
+        self.metallic
+    }
+
+    pub fn emissive(&self) -> Vector3<f32> {
+        self.emissive
+    }
+
+    /// Unlit materials ignore lights and show their base color as is.
+    pub fn unlit(&self) -> bool {
+        self.unlit
+    }
+
+    /// Ignored by pipelines built with `PipelineBuilder::with_shading_model()`.
+    pub fn shading_model(&self) -> ShadingModel {
+        self.shading_model
+    }
+
+    /// With `AlphaMode::Blend`, the alpha of the base color times the vertex color's is the
+    /// material's opacity.
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    /// Whether instances with this material are drawn in the transparent pass.
+    pub fn blended(&self) -> bool {
+        matches!(self.alpha_mode, AlphaMode::Blend)
+    }
+
+    /// Overrides the mesh's pipeline for instances with this material.  The pipeline must have
+    /// been built for the mesh's vertex type.
+    pub fn pipeline(&self) -> Option<PipelineHandle> {
+        self.pipeline
+    }
+
+    pub fn new(base_color: Vector4<f32>) -> Material {
+        Material {
+            base_color,
+            albedo_texture: None,
+            roughness: 1.0,
+            metallic: 0.0,
+            emissive: Vector3::new(0.0, 0.0, 0.0),
+            unlit: false,
+            shading_model: ShadingModel::Lambert,
+            alpha_mode: AlphaMode::Opaque,
+            pipeline: None,
+        }
+    }
+
+    pub fn white() -> Material {
+        Self::new(Vector4::new(1.0, 1.0, 1.0, 1.0))
+    }
+
+    pub fn with_albedo_texture(mut self, texture: TextureHandle) -> Material {
+        self.albedo_texture = Some(texture);
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Material {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_metallic(mut self, metallic: f32) -> Material {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: Vector3<f32>) -> Material {
+        self.emissive = emissive;
+        self
+    }
+
+    pub fn with_unlit(mut self, unlit: bool) -> Material {
+        self.unlit = unlit;
+        self
+    }
+
+    pub fn with_shading_model(mut self, shading_model: ShadingModel) -> Material {
+        self.shading_model = shading_model;
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Material {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    pub fn with_pipeline(mut self, pipeline: PipelineHandle) -> Material {
+        self.pipeline = Some(pipeline);
+        self
+    }
+}
+
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialData {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    roughness: f32,
+    metallic: f32,
+    alpha_cutoff: f32,
+    unlit: u32,
+    shading_model: u32,
+    blend: u32,
+    _padding: [u32; 3],
+}
+
+impl MaterialData {
+    pub fn new(material: &Material) -> MaterialData {
+        let alpha_cutoff = match material.alpha_mode() {
+            AlphaMode::Mask(cutoff) => cutoff,
+            AlphaMode::Opaque | AlphaMode::Blend => 0.0,
+        };
+
+        MaterialData {
+            base_color: material.base_color().into(),
+            emissive: material.emissive().into(),
+            roughness: material.roughness(),
+            metallic: material.metallic(),
+            alpha_cutoff,
+            unlit: material.unlit() as u32,
+            shading_model: material.shading_model().shader_value(),
+            blend: material.blended() as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+pub fn create_material_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+        label: Some("material bind group layout"),
+    })
+}
+
+pub fn create_material_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    material_buffer: &wgpu::Buffer,
+    albedo_view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: material_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(albedo_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("material bind group"),
+    })
+}
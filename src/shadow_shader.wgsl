@@ -0,0 +1,35 @@
+// depth-only pass that renders instances from the directional light's point of view
+
+struct InstanceInput {
+    @location(5) model_matrix0: vec4<f32>,
+    @location(6) model_matrix1: vec4<f32>,
+    @location(7) model_matrix2: vec4<f32>,
+    @location(8) model_matrix3: vec4<f32>,
+};
+
+struct LightUniform {
+    direction: vec3<f32>,
+    color: vec3<f32>,
+    ambient_color: vec3<f32>,
+    view_proj: mat4x4<f32>,
+    shadow_bias: f32,
+    shadows_enabled: u32,
+    shadow_texel_size: f32,
+};
+@group(0) @binding(0)
+var<uniform> light: LightUniform;
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix0,
+        instance.model_matrix1,
+        instance.model_matrix2,
+        instance.model_matrix3,
+    );
+
+    return light.view_proj * model_matrix * vec4<f32>(position, 1.0);
+}
@@ -1,6 +1,6 @@
-use cgmath::{Vector3, InnerSpace};
+use cgmath::{Vector2, Vector3, InnerSpace};
 
-use crate::{mesh::Mesh, color_normal_vertex::ColorNormalVertex};
+use crate::{mesh::Mesh, color_normal_vertex::ColorNormalVertex, textured_normal_vertex::TexturedNormalVertex};
 
 // pub fn test_scene(renderer: &Renderer) -> Scene {
 //     let mut camera = Camera::new(
@@ -66,6 +66,33 @@ pub fn gradient_quad_mesh() -> Mesh<ColorNormalVertex> {
     mesh
 }
 
+pub fn textured_quad_mesh() -> Mesh<TexturedNormalVertex> {
+    let mut mesh = Mesh::new();
+
+    let normal = Vector3::new(0.0, -1.0, 0.0);
+    let lower_left = TexturedNormalVertex::new(Vector3::new(-1.0, 0.0, -1.0), normal, Vector2::new(0.0, 1.0));
+    let lower_right = TexturedNormalVertex::new(Vector3::new(1.0, 0.0, -1.0), normal, Vector2::new(1.0, 1.0));
+    let upper_left = TexturedNormalVertex::new(Vector3::new(-1.0, 0.0, 1.0), normal, Vector2::new(0.0, 0.0));
+    let upper_right = TexturedNormalVertex::new(Vector3::new(1.0, 0.0, 1.0), normal, Vector2::new(1.0, 0.0));
+
+    mesh.add_quad_facing(lower_left, lower_right, upper_right, upper_left, normal);
+
+    mesh
+}
+
+/// RGBA8 data for a `size` by `size` texture of alternating white and black squares.
+pub fn checkerboard_texture(size: u32, squares: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity((4 * size * size) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let white = (x * squares / size + y * squares / size).is_multiple_of(2);
+            let value = if white { 255 } else { 0 };
+            data.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+    data
+}
+
 pub fn cube_mesh() -> Mesh<ColorNormalVertex> {
     let mut mesh = Mesh::new();
 
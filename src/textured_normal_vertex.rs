@@ -0,0 +1,51 @@
+use cgmath::{Vector2, Vector3};
+
+use crate::mesh::Vertex;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TexturedNormalVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    uv: [f32; 2],
+}
+
+impl TexturedNormalVertex {
+    pub fn new(position: Vector3<f32>, normal: Vector3<f32>, uv: Vector2<f32>) -> TexturedNormalVertex {
+        TexturedNormalVertex {
+            position: position.into(),
+            normal: normal.into(),
+            uv: uv.into(),
+        }
+    }
+}
+
+impl Vertex for TexturedNormalVertex {
+    fn position(&self) -> Vector3<f32> {
+        self.position.into()
+    }
+
+    fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<TexturedNormalVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
+                }
+            ]
+        }
+    }
+}
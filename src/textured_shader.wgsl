@@ -0,0 +1,265 @@
+// ================================
+// vertex shader
+// ================================
+struct InstanceInput {
+    @location(5) model_matrix0: vec4<f32>,
+    @location(6) model_matrix1: vec4<f32>,
+    @location(7) model_matrix2: vec4<f32>,
+    @location(8) model_matrix3: vec4<f32>,
+
+    @location(9) normal_matrix0: vec3<f32>,
+    @location(10) normal_matrix1: vec3<f32>,
+    @location(11) normal_matrix2: vec3<f32>,
+};
+
+struct CameraUniform {
+    position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct LightUniform {
+    direction: vec3<f32>,
+    color: vec3<f32>,
+    ambient_color: vec3<f32>,
+    view_proj: mat4x4<f32>,
+    shadow_bias: f32,
+    shadows_enabled: u32,
+    shadow_texel_size: f32,
+};
+@group(0) @binding(1)
+var<uniform> light: LightUniform;
+@group(0) @binding(2)
+var shadow_map: texture_depth_2d;
+@group(0) @binding(3)
+var shadow_sampler: sampler_comparison;
+
+struct PointLight {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+};
+
+struct SpotLight {
+    position: vec3<f32>,
+    range: f32,
+    direction: vec3<f32>,
+    cos_outer_angle: f32,
+    color: vec3<f32>,
+    cos_inner_angle: f32,
+};
+
+struct LocalLights {
+    point_light_count: u32,
+    spot_light_count: u32,
+    point_lights: array<PointLight, 64>,
+    spot_lights: array<SpotLight, 64>,
+};
+@group(0) @binding(4)
+var<uniform> local_lights: LocalLights;
+
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    roughness: f32,
+    metallic: f32,
+    alpha_cutoff: f32,
+    unlit: u32,
+    shading_model: u32,
+    blend: u32, // the alpha is written only when blending
+};
+@group(1) @binding(0)
+var<uniform> material: MaterialUniform;
+@group(1) @binding(1)
+var t_albedo: texture_2d<f32>;
+@group(1) @binding(2)
+var s_albedo: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix0,
+        instance.model_matrix1,
+        instance.model_matrix2,
+        instance.model_matrix3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix0,
+        instance.normal_matrix1,
+        instance.normal_matrix2,
+    );
+
+    var out: VertexOutput;
+    out.uv = model.uv;
+
+    var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+
+    out.clip_position = camera.view_proj * world_position;
+
+    out.world_normal = normal_matrix * model.normal;
+
+    return out;
+}
+
+// ================================
+// fragment shader
+// ================================
+
+// fraction of the directional light reaching a point, filtered over 3x3 shadow map texels
+fn shadow(world_position: vec3<f32>) -> f32 {
+    if light.shadows_enabled == 0u {
+        return 1.0;
+    }
+
+    let light_position = light.view_proj * vec4<f32>(world_position, 1.0);
+    let ndc = light_position.xyz / light_position.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+
+    // outside the shadow map is lit
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    var total = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * light.shadow_texel_size;
+            total += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, ndc.z - light.shadow_bias);
+        }
+    }
+
+    return total / 9.0;
+}
+
+const LAMBERT: u32 = 0u;
+const BLINN_PHONG: u32 = 1u;
+const PBR: u32 = 2u;
+const PI: f32 = 3.14159265;
+
+// set per pipeline with PipelineBuilder::with_shading_model(); 255 leaves it to the material
+override SHADING_MODEL: u32 = 255u;
+
+struct Surface {
+    albedo: vec3<f32>,
+    normal: vec3<f32>,
+    view: vec3<f32>,
+    roughness: f32,
+    metallic: f32,
+    shading_model: u32,
+}
+
+// light reflected towards the viewer per unit of light arriving from to_light, including the
+// cosine term.  Every model is scaled so a white surface facing the light reflects all of it
+// diffusely, which keeps light intensities comparable between models.
+fn brdf(surface: Surface, to_light: vec3<f32>) -> vec3<f32> {
+    let n_dot_l = max(dot(surface.normal, to_light), 0.0);
+    if surface.shading_model == LAMBERT {
+        return surface.albedo * n_dot_l;
+    }
+
+    let half_vector = normalize(to_light + surface.view);
+    let n_dot_h = max(dot(surface.normal, half_vector), 0.0);
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metallic);
+    let diffuse = surface.albedo * (1.0 - surface.metallic);
+    let alpha = max(surface.roughness * surface.roughness, 0.002);
+
+    if surface.shading_model == BLINN_PHONG {
+        // normalized blinn-phong, with the exponent matched to the roughness
+        let shininess = max(2.0 / (alpha * alpha) - 2.0, 1.0);
+        let specular = f0 * (shininess + 8.0) / 8.0 * pow(n_dot_h, shininess);
+        return (diffuse + specular) * n_dot_l;
+    }
+
+    // cook-torrance with a ggx distribution, schlick-ggx geometry, and schlick fresnel
+    let n_dot_v = max(dot(surface.normal, surface.view), 0.0001);
+    let alpha2 = alpha * alpha;
+    let d_denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * d_denominator * d_denominator);
+
+    let k = (surface.roughness + 1.0) * (surface.roughness + 1.0) / 8.0;
+    let geometry = n_dot_l / (n_dot_l * (1.0 - k) + k) * n_dot_v / (n_dot_v * (1.0 - k) + k);
+
+    let v_dot_h = max(dot(surface.view, half_vector), 0.0);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+    let specular = distribution * geometry * fresnel / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+    return ((1.0 - fresnel) * diffuse + PI * specular) * n_dot_l;
+}
+
+// windowed inverse square falloff, reaching zero at the light's range
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+// light reflected from the point and spot lights
+fn local_light(surface: Surface, world_position: vec3<f32>) -> vec3<f32> {
+    var total = vec3<f32>(0.0);
+
+    for (var i = 0u; i < local_lights.point_light_count; i++) {
+        let point_light = local_lights.point_lights[i];
+        let to_light = point_light.position - world_position;
+        let distance = max(length(to_light), 0.0001);
+        let radiance = point_light.color * attenuation(distance, point_light.range);
+        total += radiance * brdf(surface, to_light / distance);
+    }
+
+    for (var i = 0u; i < local_lights.spot_light_count; i++) {
+        let spot_light = local_lights.spot_lights[i];
+        let to_light = spot_light.position - world_position;
+        let distance = max(length(to_light), 0.0001);
+        let cone = smoothstep(spot_light.cos_outer_angle, spot_light.cos_inner_angle, dot(-to_light / distance, spot_light.direction));
+        let radiance = spot_light.color * attenuation(distance, spot_light.range) * cone;
+        total += radiance * brdf(surface, to_light / distance);
+    }
+
+    return total;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let sampled = textureSample(t_albedo, s_albedo, in.uv) * material.base_color;
+    let albedo = sampled.rgb;
+    if sampled.a < material.alpha_cutoff {
+        discard;
+    }
+
+    var result = albedo;
+    if material.unlit == 0u {
+        var surface: Surface;
+        surface.albedo = albedo;
+        surface.normal = normalize(in.world_normal);
+        surface.view = normalize(camera.position.xyz - in.world_position);
+        surface.roughness = material.roughness;
+        surface.metallic = material.metallic;
+        surface.shading_model = select(material.shading_model, SHADING_MODEL, SHADING_MODEL != 255u);
+
+        let ambient_color = light.ambient_color * albedo;
+        let directional_color = light.color * shadow(in.world_position) * brdf(surface, -light.direction);
+        let local_color = local_light(surface, in.world_position);
+
+        result = ambient_color + directional_color + local_color;
+    }
+    result += material.emissive;
+
+    return vec4<f32>(result, select(1.0, sampled.a, material.blend != 0u));
+}
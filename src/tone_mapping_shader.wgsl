@@ -0,0 +1,44 @@
+// fullscreen pass that maps the hdr scene into the output's range
+
+struct ToneMappingUniform {
+    exposure: f32,
+    tone_mapping: u32,
+};
+@group(0) @binding(0)
+var<uniform> settings: ToneMappingUniform;
+@group(0) @binding(1)
+var scene: texture_2d<f32>;
+
+const NONE: u32 = 0u;
+const REINHARD: u32 = 1u;
+const ACES: u32 = 2u;
+
+// a triangle covering the screen, from the vertex index alone
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// krzysztof narkowicz's fit of the aces filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    return clamp(
+        (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0),
+    );
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = textureLoad(scene, vec2<i32>(position.xy), 0).rgb * settings.exposure;
+
+    var mapped = color;
+    if settings.tone_mapping == REINHARD {
+        mapped = color / (1.0 + color);
+    } else if settings.tone_mapping == ACES {
+        mapped = aces(color);
+    }
+
+    return vec4<f32>(mapped, 1.0);
+}
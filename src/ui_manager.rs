@@ -11,8 +11,8 @@ pub struct UIFrame {
 
 
 pub struct UIManager {
-    // context: egui::Context,
-    winit_state: egui_winit::State,
+    context: egui::Context,
+    winit_state: Option<egui_winit::State>, // none when rendering headless
     screen_descriptor: egui_wgpu::ScreenDescriptor,
     ui_renderer: egui_wgpu::Renderer,
     frame: Option<UIFrame>,
@@ -20,24 +20,25 @@ pub struct UIManager {
 
 impl UIManager {
     pub fn new(
-        window: &Window,
+        window: Option<&Window>,
         device: &wgpu::Device,
         surface_config: &SurfaceConfiguration,
         depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
     ) -> UIManager {
         let context = egui::Context::default();
         // let viewport_id = context.viewport_id();
 
         let viewport_id = egui::viewport::ViewportId::ROOT;
 
-        let winit_state = egui_winit::State::new(
-            context,
+        let winit_state = window.map(|window| egui_winit::State::new(
+            context.clone(),
             viewport_id,
             window,
             Some(1.0),
             None,
             None,
-        );
+        ));
 
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [surface_config.width, surface_config.height],
@@ -48,14 +49,14 @@ impl UIManager {
             device,
             surface_config.format,
             depth_format,
-            1,
+            sample_count,
             false,
         );
 
         let frame = None;
 
         UIManager {
-            // context,
+            context,
             winit_state,
             screen_descriptor,
             ui_renderer,
@@ -103,21 +104,35 @@ impl UIManager {
         }
     }
 
-    pub fn run<F: FnMut(&Context)>(&mut self, window: &Window, gui: F) {
+    pub fn run<F: FnMut(&Context)>(&mut self, window: Option<&Window>, gui: F) {
         match self.frame {
             None => {
-                let raw_input = self.winit_state.take_egui_input(window);
+                let raw_input = match (&mut self.winit_state, window) {
+                    (Some(winit_state), Some(window)) => winit_state.take_egui_input(window),
+                    _ => {
+                        let [width, height] = self.screen_descriptor.size_in_pixels;
+                        egui::RawInput {
+                            screen_rect: Some(egui::Rect::from_min_size(
+                                egui::Pos2::ZERO,
+                                egui::vec2(width as f32, height as f32),
+                            )),
+                            ..Default::default()
+                        }
+                    },
+                };
         
                 // todo: handle viewport updates (per https://docs.rs/egui-winit/latest/egui_winit/struct.State.html#method.take_egui_input)
         
-                let full_output = self.winit_state.egui_ctx().run(raw_input, gui);
+                let full_output = self.context.run(raw_input, gui);
 
-                self.winit_state.handle_platform_output(window, full_output.platform_output);
+                if let (Some(winit_state), Some(window)) = (&mut self.winit_state, window) {
+                    winit_state.handle_platform_output(window, full_output.platform_output);
+                }
                 
 
                 // handle any extra platform output 
         
-                let clipped_primitives = self.winit_state.egui_ctx().tessellate(full_output.shapes, full_output.pixels_per_point);
+                let clipped_primitives = self.context.tessellate(full_output.shapes, full_output.pixels_per_point);
                 let textures_delta = full_output.textures_delta;
         
                 self.frame = Some(UIFrame {
@@ -146,9 +161,27 @@ impl UIManager {
         self.frame = None;
     }
 
+    /// Drops the pending frame without drawing it, e.g. when the surface isn't ready.  Texture
+    /// updates are still applied, since egui doesn't send them again.
+    pub fn skip_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, surface_config: &SurfaceConfiguration) {
+        if let Some(frame) = &mut self.frame {
+            frame.clipped_primitives.clear();
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("ui skip encoder"),
+            });
+            self.update_resources(device, queue, &mut encoder, surface_config);
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        self.frame = None;
+    }
+
     // todo: multiple windows?
     pub fn on_window_event(&mut self, window: &Window, event: &winit::event::WindowEvent) -> bool {
-        let response = self.winit_state.on_window_event(window, event);
-        response.consumed
+        match &mut self.winit_state {
+            Some(winit_state) => winit_state.on_window_event(window, event).consumed,
+            None => false,
+        }
     }
 }
\ No newline at end of file
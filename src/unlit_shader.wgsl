@@ -0,0 +1,79 @@
+// ================================
+// vertex shader
+// ================================
+struct InstanceInput {
+    @location(5) model_matrix0: vec4<f32>,
+    @location(6) model_matrix1: vec4<f32>,
+    @location(7) model_matrix2: vec4<f32>,
+    @location(8) model_matrix3: vec4<f32>,
+
+    @location(9) normal_matrix0: vec3<f32>,
+    @location(10) normal_matrix1: vec3<f32>,
+    @location(11) normal_matrix2: vec3<f32>,
+};
+
+struct CameraUniform {
+    position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    roughness: f32,
+    metallic: f32,
+    alpha_cutoff: f32,
+    unlit: u32,
+    shading_model: u32,
+    blend: u32, // the alpha is written only when blending
+};
+@group(1) @binding(0)
+var<uniform> material: MaterialUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix0,
+        instance.model_matrix1,
+        instance.model_matrix2,
+        instance.model_matrix3,
+    );
+
+    var out: VertexOutput;
+    out.color = model.color;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+
+    return out;
+}
+
+// ================================
+// fragment shader
+// ================================
+
+// vertices without normals cannot be lit, so this shader ignores lights
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let alpha = in.color.a * material.base_color.a;
+    if alpha < material.alpha_cutoff {
+        discard;
+    }
+
+    let result = in.color.rgb * material.base_color.rgb + material.emissive;
+
+    return vec4<f32>(result, select(1.0, alpha, material.blend != 0u));
+}
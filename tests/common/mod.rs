@@ -0,0 +1,220 @@
+// the golden image harness shared by the integration tests, which renders frames headlessly and
+// compares them against reference PNGs
+
+use std::path::{Path, PathBuf};
+
+use shaderunner::Renderer;
+
+/// Creates a headless renderer on the fallback adapter for tests.  Returns `None` after printing
+/// a note if no fallback adapter is available, so tests can skip on machines that cannot render
+/// at all; set `SHADERUNNER_REQUIRE_GPU=1` to panic instead, so a missing adapter fails the run.
+pub fn headless_renderer(width: u32, height: u32, format: wgpu::TextureFormat) -> Option<Renderer> {
+    let renderer = pollster::block_on(Renderer::new_headless(width, height, format, true));
+
+    if renderer.is_none() {
+        if std::env::var_os("SHADERUNNER_REQUIRE_GPU").is_some() {
+            panic!("golden: no fallback adapter available and SHADERUNNER_REQUIRE_GPU is set");
+        }
+        println!("no fallback adapter available; skipping (set SHADERUNNER_REQUIRE_GPU=1 to fail instead)");
+    }
+    renderer
+}
+
+/// Renders a single frame with `headless_renderer()` and returns it as tightly packed RGBA8 rows.
+/// `setup` adds meshes, instances, camera, lights, and immediate-mode lines before the frame is
+/// drawn.  Returns `None` if the test should be skipped for lack of an adapter.
+pub fn render<F: FnOnce(&mut Renderer)>(width: u32, height: u32, setup: F) -> Option<Vec<u8>> {
+    let mut renderer = headless_renderer(width, height, wgpu::TextureFormat::Rgba8UnormSrgb)?;
+
+    setup(&mut renderer);
+    renderer.render().expect("golden: error rendering frame");
+
+    Some(renderer.read_pixels().expect("golden: error reading pixels"))
+}
+
+/// The result of comparing two RGBA8 images.
+pub struct Comparison {
+    differing_pixels: usize,
+    max_difference: u8,
+    diff: Vec<u8>,
+}
+
+impl Comparison {
+    /// Number of pixels with a channel that differs by more than the tolerance.
+    pub fn differing_pixels(&self) -> usize {
+        self.differing_pixels
+    }
+
+    /// Largest difference in any channel of any pixel.
+    pub fn max_difference(&self) -> u8 {
+        self.max_difference
+    }
+
+    /// An RGBA8 image showing differing pixels in red over a dimmed copy of the expected image.
+    pub fn diff(&self) -> &Vec<u8> {
+        &self.diff
+    }
+}
+
+/// Compares two RGBA8 images of the same size; a pixel differs if any of its channels differs by
+/// more than `tolerance`.
+pub fn compare(actual: &[u8], expected: &[u8], tolerance: u8) -> Comparison {
+    assert_eq!(actual.len(), expected.len(), "compare: images have different sizes");
+
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+
+    for (actual, expected) in actual.chunks(4).zip(expected.chunks(4)) {
+        let difference = actual.iter()
+            .zip(expected)
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            differing_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luma = (expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 12;
+            diff.extend_from_slice(&[luma as u8, luma as u8, luma as u8, 255]);
+        }
+    }
+
+    Comparison {
+        differing_pixels,
+        max_difference,
+        diff,
+    }
+}
+
+/// Compares rendered frames against reference PNGs stored in a directory.
+///
+/// References are only written when `SHADERUNNER_BLESS=1` is set, which blesses new tests and
+/// overwrites existing references; otherwise a missing reference fails the test.  On failure the
+/// actual image and a diff image are written to the output directory.
+pub struct GoldenTest {
+    reference_dir: PathBuf,
+    output_dir: PathBuf,
+    tolerance: u8,
+    max_differing_pixels: usize,
+}
+
+impl GoldenTest {
+    pub fn new(reference_dir: impl Into<PathBuf>, output_dir: impl Into<PathBuf>) -> GoldenTest {
+        GoldenTest {
+            reference_dir: reference_dir.into(),
+            output_dir: output_dir.into(),
+            tolerance: 0,
+            max_differing_pixels: 0,
+        }
+    }
+
+    /// Sets how far any channel of a pixel may be from the reference before the pixel differs.
+    pub fn with_tolerance(mut self, tolerance: u8) -> GoldenTest {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Sets how many pixels may differ before the comparison fails, to absorb rasterization
+    /// differences between adapters along edges.
+    pub fn with_max_differing_pixels(mut self, max_differing_pixels: usize) -> GoldenTest {
+        self.max_differing_pixels = max_differing_pixels;
+        self
+    }
+
+    /// Checks `pixels` against the reference image called `name`, panicking with a description of
+    /// the mismatch on failure.
+    pub fn assert_matches(&self, name: &str, width: u32, height: u32, pixels: &[u8]) {
+        let reference_path = self.reference_dir.join(format!("{}.png", name));
+
+        if std::env::var_os("SHADERUNNER_BLESS").is_some() {
+            save_png(&reference_path, width, height, pixels);
+            println!("golden: wrote reference {}", reference_path.display());
+            return;
+        }
+        if !reference_path.exists() {
+            let actual_path = self.output_dir.join(format!("{}.actual.png", name));
+            save_png(&actual_path, width, height, pixels);
+
+            panic!(
+                "golden: no reference {} for {}; wrote {}, rerun with SHADERUNNER_BLESS=1 to accept it",
+                reference_path.display(),
+                name,
+                actual_path.display(),
+            );
+        }
+
+        let (reference_width, reference_height, reference) = load_png(&reference_path);
+        if (reference_width, reference_height) != (width, height) {
+            panic!(
+                "golden: {} is {}x{} but the reference is {}x{}",
+                name, width, height, reference_width, reference_height,
+            );
+        }
+
+        let comparison = compare(pixels, &reference, self.tolerance);
+        if comparison.differing_pixels() > self.max_differing_pixels {
+            let actual_path = self.output_dir.join(format!("{}.actual.png", name));
+            let diff_path = self.output_dir.join(format!("{}.diff.png", name));
+            save_png(&actual_path, width, height, pixels);
+            save_png(&diff_path, width, height, comparison.diff());
+
+            panic!(
+                "golden: {} has {} pixels differing by more than {} (max difference {}); wrote {} and {}",
+                name,
+                comparison.differing_pixels(),
+                self.tolerance,
+                comparison.max_difference(),
+                actual_path.display(),
+                diff_path.display(),
+            );
+        }
+    }
+}
+
+pub fn save_png(path: &Path, width: u32, height: u32, pixels: &[u8]) {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).expect("error creating golden image directory");
+    }
+
+    image::save_buffer(path, pixels, width, height, image::ExtendedColorType::Rgba8)
+        .expect("error writing png");
+}
+
+pub fn load_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let image = image::open(path).expect("error reading png").to_rgba8();
+    (image.width(), image.height(), image.into_raw())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let expected = [10, 20, 30, 255, 100, 100, 100, 255];
+        let actual = [12, 20, 30, 255, 100, 150, 100, 255];
+
+        let comparison = compare(&actual, &expected, 2);
+        assert_eq!(comparison.differing_pixels(), 1);
+        assert_eq!(comparison.max_difference(), 50);
+        assert_eq!(&comparison.diff()[4..], &[255, 0, 0, 255]);
+
+        let comparison = compare(&actual, &expected, 50);
+        assert_eq!(comparison.differing_pixels(), 0);
+    }
+    #[test]
+    #[should_panic(expected = "SHADERUNNER_BLESS")]
+    fn test_missing_reference() {
+        if std::env::var_os("SHADERUNNER_BLESS").is_some() {
+            panic!("SHADERUNNER_BLESS is set; a missing reference would be written");
+        }
+
+        let dir = std::env::temp_dir().join("shaderunner_golden_missing_reference");
+        GoldenTest::new(dir.join("references"), dir.join("output"))
+            .assert_matches("missing", 1, 1, &[0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,506 @@
+mod common;
+
+use cgmath::{Vector3, Vector4};
+use common::GoldenTest;
+use shaderunner::{test_assets, AmbientLight, BloomSettings, Camera, ColorGradingSettings, ColorNormalVertex, ColorVertex, DirectionalLight, Material, Mesh, PipelineBuilder, PointLight, PostProcessBuilder, Renderer, ShadingModel, ShadowSettings, SpotLight, ToneMapping, Transform, VignetteSettings};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 128;
+
+fn golden_test() -> GoldenTest {
+    GoldenTest::new(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/target/golden"),
+    )
+        .with_tolerance(8)
+        .with_max_differing_pixels(WIDTH as usize * HEIGHT as usize / 100)
+}
+
+// renders a frame set up by `setup` and checks it against the reference called `name`
+fn assert_golden<F: FnOnce(&mut Renderer)>(name: &str, setup: F) {
+    let Some(pixels) = common::render(WIDTH, HEIGHT, setup) else {
+        return;
+    };
+
+    golden_test().assert_matches(name, WIDTH, HEIGHT, &pixels);
+}
+
+fn camera() -> Camera {
+    Camera::new(
+        Vector3::new(0.8, -1.5, 1.0),
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::unit_z(),
+        WIDTH as f32 / HEIGHT as f32,
+        std::f32::consts::TAU / 8.0,
+        0.1,
+        100.0,
+    )
+}
+
+#[test]
+fn basic_scene() {
+    assert_golden("basic_scene", |renderer| {
+        let material = renderer.default_material();
+
+        let cube = renderer.add_mesh(&test_assets::cube_mesh());
+        renderer.add_instance(cube, material, Transform::from_translation(Vector3::new(-0.5, -0.5, -0.5))).unwrap();
+
+        let sphere = renderer.add_mesh(&test_assets::simple_sphere_mesh(1.0, 16, Vector3::new(1.0, 1.0, 1.0)));
+        renderer.add_instance(sphere, material, Transform::from_translation(Vector3::new(0.5, 0.5, 0.5))).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, -0.6, -1.0), Vector3::new(1.0, 1.0, 1.0), 0.5),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.02),
+        );
+        renderer.update_camera(&camera());
+
+        renderer.draw_line_green(Vector3::new(-10.0, -10.0, -10.0), Vector3::new(10.0, 10.0, 10.0));
+    });
+}
+
+#[test]
+fn textured_quad() {
+    assert_golden("textured_quad", |renderer| {
+        let texture = renderer.add_texture(64, 64, &test_assets::checkerboard_texture(64, 8)).unwrap();
+        let material = renderer.add_material(&Material::white().with_albedo_texture(texture)).unwrap();
+        let quad = renderer.add_mesh(&test_assets::textured_quad_mesh());
+        renderer.add_instance(quad, material, Transform::identity()).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(1.0, 1.0, 1.0), 0.8),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.2),
+        );
+        renderer.update_camera(&camera());
+    });
+}
+
+#[test]
+fn materials() {
+    assert_golden("materials", |renderer| {
+        let red = renderer.add_material(&Material::new(Vector4::new(1.0, 0.2, 0.2, 1.0))).unwrap();
+        let unlit = renderer.add_material(&Material::new(Vector4::new(0.2, 0.2, 1.0, 1.0)).with_unlit(true)).unwrap();
+        let emissive = renderer.add_material(&Material::new(Vector4::new(0.0, 0.0, 0.0, 1.0)).with_emissive(Vector3::new(0.0, 0.8, 0.0))).unwrap();
+
+        let sphere = renderer.add_mesh(&test_assets::simple_sphere_mesh(0.3, 8, Vector3::new(1.0, 1.0, 1.0)));
+        renderer.add_instance(sphere, red, Transform::from_translation(Vector3::new(-0.7, 0.0, 0.0))).unwrap();
+        renderer.add_instance(sphere, unlit, Transform::from_translation(Vector3::new(0.0, 0.0, 0.0))).unwrap();
+        renderer.add_instance(sphere, emissive, Transform::from_translation(Vector3::new(0.7, 0.0, 0.0))).unwrap();
+        renderer.add_instance(sphere, red, Transform::from_translation(Vector3::new(0.0, 0.0, 0.7))).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 0.8),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.1),
+        );
+        renderer.update_camera(&camera());
+    });
+}
+
+#[test]
+fn unlit_vertex_colors() {
+    assert_golden("unlit_vertex_colors", |renderer| {
+        // a mesh without normals picks up the built-in unlit pipeline for its vertex type
+        let mut mesh = Mesh::new();
+        mesh.add_quad_facing(
+            ColorVertex::new(Vector3::new(-0.8, 0.0, -0.6), Vector3::new(1.0, 0.0, 0.0)),
+            ColorVertex::new(Vector3::new(0.8, 0.0, -0.6), Vector3::new(0.0, 1.0, 0.0)),
+            ColorVertex::new(Vector3::new(0.8, 0.0, 0.6), Vector3::new(0.0, 0.0, 1.0)),
+            ColorVertex::new(Vector3::new(-0.8, 0.0, 0.6), Vector3::new(1.0, 1.0, 1.0)),
+            Vector3::new(0.0, -1.0, 0.0),
+        );
+        let quad = renderer.add_mesh(&mesh);
+        renderer.add_instance(quad, renderer.default_material(), Transform::identity()).unwrap();
+
+        renderer.update_camera(&camera());
+    });
+}
+
+#[test]
+fn shadows() {
+    assert_golden("shadows", |renderer| {
+        let material = renderer.default_material();
+
+        let mut ground = Mesh::new();
+        let corner = |x: f32, y: f32| ColorNormalVertex::new(
+            Vector3::new(x, y, -0.5),
+            Vector3::new(0.8, 0.8, 0.8),
+            Vector3::unit_z(),
+        );
+        ground.add_quad_facing(corner(-3.0, -3.0), corner(3.0, -3.0), corner(3.0, 3.0), corner(-3.0, 3.0), Vector3::unit_z());
+        let ground = renderer.add_mesh(&ground);
+        renderer.add_instance(ground, material, Transform::identity()).unwrap();
+
+        let sphere = renderer.add_mesh(&test_assets::simple_sphere_mesh(0.3, 8, Vector3::new(1.0, 1.0, 1.0)));
+        renderer.add_instance(sphere, material, Transform::from_translation(Vector3::new(-0.3, 0.0, 0.2))).unwrap();
+
+        renderer.update_camera(&camera());
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 0.5, -1.0), Vector3::new(1.0, 1.0, 1.0), 0.8)
+                .with_shadows(ShadowSettings::new(512, 0.002, 5.0)),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.1),
+        );
+    });
+}
+
+#[test]
+fn local_lights() {
+    assert_golden("local_lights", |renderer| {
+        let material = renderer.default_material();
+
+        let mut ground = Mesh::new();
+        let corner = |x: f32, y: f32| ColorNormalVertex::new(
+            Vector3::new(x, y, -0.5),
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::unit_z(),
+        );
+        ground.add_quad_facing(corner(-3.0, -3.0), corner(3.0, -3.0), corner(3.0, 3.0), corner(-3.0, 3.0), Vector3::unit_z());
+        let ground = renderer.add_mesh(&ground);
+        renderer.add_instance(ground, material, Transform::identity()).unwrap();
+
+        // the removed and updated lights check that handles address the right lights
+        let removed = renderer.add_point_light(&PointLight::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0), 10.0, 10.0));
+        let red = renderer.add_point_light(&PointLight::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), 1.0, 1.0));
+        renderer.update_point_light(red, &PointLight::new(Vector3::new(-0.6, 0.3, -0.2), Vector3::new(1.0, 0.1, 0.1), 1.5, 1.5)).unwrap();
+        renderer.remove_point_light(removed).unwrap();
+
+        renderer.add_spot_light(&SpotLight::new(
+            Vector3::new(0.6, 0.0, 0.5),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.2, 0.4, 1.0),
+            2.0,
+            3.0,
+            0.2,
+            0.4,
+        ));
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(0.0, 0.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 0.0),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.05),
+        );
+        renderer.update_camera(&camera());
+    });
+}
+
+#[test]
+fn shading_models() {
+    assert_golden("shading_models", |renderer| {
+        let base_color = Vector4::new(0.8, 0.3, 0.2, 1.0);
+        let lambert = renderer.add_material(&Material::new(base_color).with_roughness(0.3)).unwrap();
+        let blinn_phong = renderer.add_material(&Material::new(base_color)
+            .with_shading_model(ShadingModel::BlinnPhong)
+            .with_roughness(0.3)).unwrap();
+        let pbr = renderer.add_material(&Material::new(base_color)
+            .with_shading_model(ShadingModel::Pbr)
+            .with_roughness(0.3)
+            .with_metallic(1.0)).unwrap();
+
+        // the pipeline's shading model overrides the lambert material
+        let pbr_pipeline = renderer.add_pipeline(
+            PipelineBuilder::new::<ColorNormalVertex>("pbr shader", shaderunner::renderer::pipeline::COLOR_NORMAL_SHADER)
+                .with_shading_model(ShadingModel::Pbr),
+        ).unwrap();
+
+        // unit spheres scaled down, so the normals are unit length
+        let sphere = test_assets::simple_sphere_mesh(1.0, 12, Vector3::new(1.0, 1.0, 1.0));
+        let at = |x: f32, z: f32| Transform::new(Vector3::new(x, 0.0, z), cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0), 0.3);
+        let pbr_sphere = renderer.add_mesh_with_pipeline(&sphere, pbr_pipeline).unwrap();
+        renderer.add_instance(pbr_sphere, lambert, at(0.0, 0.7)).unwrap();
+
+        let sphere = renderer.add_mesh(&sphere);
+        renderer.add_instance(sphere, lambert, at(-0.7, 0.0)).unwrap();
+        renderer.add_instance(sphere, blinn_phong, at(0.0, 0.0)).unwrap();
+        renderer.add_instance(sphere, pbr, at(0.7, 0.0)).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(-0.5, 1.0, -0.6), Vector3::new(1.0, 1.0, 1.0), 1.0),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.1),
+        );
+        renderer.update_camera(&camera());
+    });
+}
+
+#[test]
+fn tone_mapping() {
+    assert_golden("tone_mapping", |renderer| {
+        let material = renderer.default_material();
+
+        let sphere = test_assets::simple_sphere_mesh(1.0, 12, Vector3::new(1.0, 0.6, 0.3));
+        let sphere = renderer.add_mesh(&sphere);
+        renderer.add_instance(sphere, material, Transform::from_scale(0.5)).unwrap();
+
+        // far brighter than 1.0, which would clip to a flat disc without tone mapping
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 4.0),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.2),
+        );
+        renderer.update_camera(&camera());
+
+        renderer.set_tone_mapping(ToneMapping::Aces);
+        renderer.set_exposure(0.8);
+    });
+}
+
+#[test]
+fn post_processing() {
+    assert_golden("post_processing", |renderer| {
+        let material = renderer.default_material();
+
+        let sphere = test_assets::simple_sphere_mesh(1.0, 12, Vector3::new(1.0, 0.6, 0.3));
+        let sphere = renderer.add_mesh(&sphere);
+        renderer.add_instance(sphere, material, Transform::from_scale(0.5)).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 3.0),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.2),
+        );
+        renderer.update_camera(&camera());
+
+        renderer.add_post_process(PostProcessBuilder::bloom(BloomSettings::new(1.0, 0.5, 6.0)));
+        renderer.add_post_process(PostProcessBuilder::fxaa());
+        renderer.add_post_process(PostProcessBuilder::vignette(VignetteSettings::new(0.8, 0.3, 0.5)));
+        renderer.add_post_process(PostProcessBuilder::color_grading(
+            ColorGradingSettings::new(Vector3::new(1.0, 0.9, 0.8), 1.2, 1.1, 1.0),
+        ));
+        renderer.set_tone_mapping(ToneMapping::Reinhard);
+    });
+}
+
+const FOG_SHADER: &str = "
+struct Fog {
+    color: vec4<f32>,
+    near: f32,
+    far: f32,
+};
+@group(0) @binding(5)
+var<uniform> fog: Fog;
+
+@fragment
+fn fs_main(in: PostProcessVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, in.uv, 0.0);
+    let depth = textureLoad(depth_texture, vec2<i32>(in.position.xy), 0).r;
+    let amount = smoothstep(fog.near, fog.far, depth);
+    return vec4<f32>(mix(color.rgb, fog.color.rgb, amount), color.a);
+}
+";
+
+const INVERT_SHADER: &str = "
+@fragment
+fn fs_main(in: PostProcessVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, in.uv, 0.0);
+    return vec4<f32>(1.0 - color.rgb, color.a);
+}
+";
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Fog {
+    color: [f32; 4],
+    near: f32,
+    far: f32,
+    _padding: [f32; 2],
+}
+
+#[test]
+fn custom_post_process() {
+    assert_golden("custom_post_process", |renderer| {
+        let material = renderer.default_material();
+
+        let sphere = test_assets::simple_sphere_mesh(0.5, 12, Vector3::new(1.0, 0.6, 0.3));
+        let sphere = renderer.add_mesh(&sphere);
+        renderer.add_instance(sphere, material, Transform::from_translation(Vector3::new(0.3, 0.0, 0.0))).unwrap();
+        renderer.add_instance(sphere, material, Transform::from_translation(Vector3::new(-1.7, 1.0, -1.0))).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 1.0),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.2),
+        );
+        renderer.update_camera(&camera());
+
+        let fog = Fog { color: [0.0, 0.0, 0.0, 1.0], near: 0.0, far: 1.0, _padding: [0.0; 2] };
+        let fog_pass = renderer.add_post_process(PostProcessBuilder::new("fog", FOG_SHADER).with_uniforms(&fog));
+
+        // disabled passes are skipped
+        let invert = renderer.insert_post_process(0, PostProcessBuilder::new("invert", INVERT_SHADER));
+        renderer.set_post_process_enabled(invert, false).unwrap();
+
+        // uniforms can change after the pass is added
+        let fog = Fog { color: [0.3, 0.4, 0.6, 1.0], near: 0.9, far: 0.96, _padding: [0.0; 2] };
+        renderer.update_post_process(fog_pass, &fog).unwrap();
+    });
+}
+
+#[test]
+fn thick_lines() {
+    assert_golden("thick_lines", |renderer| {
+        let material = renderer.default_material();
+        let cube = renderer.add_mesh(&test_assets::cube_mesh());
+        renderer.add_instance(cube, material, Transform::from_translation(Vector3::new(-0.5, -0.5, -0.5))).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 1.0),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.2),
+        );
+        renderer.update_camera(&camera());
+
+        // a depth-tested line through the cube, hidden inside it, and one on top in front of it
+        let line = |x: f32, y: f32, color: Vector3<f32>| (
+            ColorVertex::new(Vector3::new(x, y, -1.0), color),
+            ColorVertex::new(Vector3::new(-x, y, 1.0), color),
+        );
+        let (start, end) = line(-1.2, 0.0, Vector3::new(1.0, 0.0, 0.0));
+        renderer.draw_thick_line(start, end, 6.0, false);
+        let (start, end) = line(1.2, 0.2, Vector3::new(0.0, 1.0, 0.0));
+        renderer.draw_thick_line(start, end, 4.0, true);
+
+        // a half transparent line, blended over the cube
+        let (start, end) = line(-1.2, -0.8, Vector3::new(0.0, 0.0, 1.0));
+        renderer.draw_thick_line(start.with_alpha(0.5), end.with_alpha(0.5), 10.0, true);
+    });
+}
+
+#[test]
+fn msaa() {
+    let mut supported = true;
+    let pixels = common::render(WIDTH, HEIGHT, |renderer| {
+        supported = renderer.set_sample_count(4).is_ok();
+
+        let material = renderer.default_material();
+        let cube = test_assets::cube_mesh();
+        let cube = renderer.add_mesh(&cube);
+        renderer.add_instance(cube, material, Transform::from_translation(Vector3::new(-0.5, -0.5, -0.5))).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 1.0),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.2),
+        );
+        renderer.update_camera(&camera());
+
+        // thin diagonal lines show aliasing most
+        for i in 0..5 {
+            let offset = i as f32 * 0.25 - 0.5;
+            renderer.draw_line_red(Vector3::new(-1.0, -0.7, offset), Vector3::new(1.0, -0.7, offset + 0.4));
+        }
+    });
+
+    let Some(pixels) = pixels else {
+        return;
+    };
+    if !supported {
+        println!("4x msaa not supported; skipping");
+        return;
+    }
+
+    golden_test().assert_matches("msaa", WIDTH, HEIGHT, &pixels);
+}
+
+const NORMAL_SHADER: &str = "
+struct InstanceInput {
+    @location(5) model_matrix0: vec4<f32>,
+    @location(6) model_matrix1: vec4<f32>,
+    @location(7) model_matrix2: vec4<f32>,
+    @location(8) model_matrix3: vec4<f32>,
+    @location(9) normal_matrix0: vec3<f32>,
+    @location(10) normal_matrix1: vec3<f32>,
+    @location(11) normal_matrix2: vec3<f32>,
+};
+
+struct CameraUniform {
+    position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0)
+var<uniform> tint: vec4<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix0,
+        instance.model_matrix1,
+        instance.model_matrix2,
+        instance.model_matrix3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix0,
+        instance.normal_matrix1,
+        instance.normal_matrix2,
+    );
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_normal = normal_matrix * model.normal;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>((normalize(in.world_normal) * 0.5 + 0.5) * tint.rgb, 1.0);
+}
+";
+
+#[test]
+fn custom_pipeline() {
+    assert_golden("custom_pipeline", |renderer| {
+        use wgpu::util::DeviceExt;
+
+        let tint = renderer.device().create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tint buffer"),
+            contents: bytemuck::cast_slice(&[1.0_f32, 0.5, 1.0, 1.0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let layout = renderer.device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tint bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = renderer.device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tint bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: tint.as_entire_binding(),
+            }],
+        });
+
+        let pipeline = renderer.add_pipeline(
+            PipelineBuilder::new::<ColorNormalVertex>("normal shader", NORMAL_SHADER)
+                .with_bind_group(layout, bind_group),
+        ).unwrap();
+
+        // one sphere uses the pipeline through its mesh, the other through its material
+        let sphere = test_assets::simple_sphere_mesh(0.4, 8, Vector3::new(1.0, 1.0, 1.0));
+        let custom_sphere = renderer.add_mesh_with_pipeline(&sphere, pipeline).unwrap();
+        renderer.add_instance(custom_sphere, renderer.default_material(), Transform::from_translation(Vector3::new(-0.5, 0.0, 0.0))).unwrap();
+
+        let sphere = renderer.add_mesh(&sphere);
+        let material = renderer.add_material(&Material::white().with_pipeline(pipeline)).unwrap();
+        renderer.add_instance(sphere, material, Transform::from_translation(Vector3::new(0.5, 0.0, 0.0))).unwrap();
+        renderer.add_instance(sphere, renderer.default_material(), Transform::from_translation(Vector3::new(0.0, 0.0, 0.6))).unwrap();
+
+        renderer.update_light(
+            &DirectionalLight::new(Vector3::new(1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), 0.8),
+            &AmbientLight::new(Vector3::new(1.0, 1.0, 1.0), 0.1),
+        );
+        renderer.update_camera(&camera());
+    });
+}